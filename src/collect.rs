@@ -0,0 +1,325 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use ruff_python_ast as ast;
+use ruff_python_parser::parse_module;
+
+/// pytest's `python_classes` / `python_functions` settings. Each entry is a name prefix or a
+/// glob (`*`, `?`), as in pytest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PytestPatterns {
+    pub classes: Vec<String>,
+    pub functions: Vec<String>,
+}
+
+impl Default for PytestPatterns {
+    fn default() -> Self {
+        Self {
+            classes: vec!["Test".to_string()],
+            functions: vec!["test".to_string()],
+        }
+    }
+}
+
+impl PytestPatterns {
+    /// Read the patterns from the first pytest config file under `root`, in pytest's own
+    /// order: `pytest.ini`, `pyproject.toml`, `tox.ini`, `setup.cfg`.
+    pub fn load(root: &Utf8Path) -> Result<Self> {
+        Ok(Self::load_with_source(root)?.0)
+    }
+
+    /// Like [`Self::load`], also returning the config file the patterns came from (`None`
+    /// when pytest's defaults apply).
+    pub fn load_with_source(root: &Utf8Path) -> Result<(Self, Option<Utf8PathBuf>)> {
+        let mut patterns = Self::default();
+
+        let ini = root.join("pytest.ini");
+        if ini.exists() {
+            patterns.apply_ini(&read(&ini)?, "pytest");
+            return Ok((patterns, Some(ini)));
+        }
+
+        let pyproject = root.join("pyproject.toml");
+        if pyproject.exists() {
+            let table: toml::Table = toml::from_str(&read(&pyproject)?)
+                .with_context(|| format!("Failed to parse {pyproject}"))?;
+            if let Some(options) = table
+                .get("tool")
+                .and_then(|t| t.get("pytest"))
+                .and_then(|p| p.get("ini_options"))
+            {
+                if let Some(classes) = options.get("python_classes").and_then(toml_patterns) {
+                    patterns.classes = classes;
+                }
+                if let Some(functions) = options.get("python_functions").and_then(toml_patterns) {
+                    patterns.functions = functions;
+                }
+                return Ok((patterns, Some(pyproject)));
+            }
+        }
+
+        for (file, section) in [("tox.ini", "pytest"), ("setup.cfg", "tool:pytest")] {
+            let path = root.join(file);
+            if path.exists() {
+                let raw = read(&path)?;
+                if ini_has_section(&raw, section) {
+                    patterns.apply_ini(&raw, section);
+                    return Ok((patterns, Some(path)));
+                }
+            }
+        }
+
+        Ok((patterns, None))
+    }
+
+    fn apply_ini(&mut self, raw: &str, section: &str) {
+        if let Some(classes) = ini_value(raw, section, "python_classes") {
+            self.classes = classes.split_whitespace().map(str::to_string).collect();
+        }
+        if let Some(functions) = ini_value(raw, section, "python_functions") {
+            self.functions = functions.split_whitespace().map(str::to_string).collect();
+        }
+    }
+
+    fn is_test_class(&self, name: &str) -> bool {
+        self.classes.iter().any(|p| name_matches(p, name))
+    }
+
+    fn is_test_function(&self, name: &str) -> bool {
+        self.functions.iter().any(|p| name_matches(p, name))
+    }
+}
+
+/// Collectable test items in a module, as pytest node-id suffixes (`test_x`,
+/// `TestFoo::test_y`, `TestFoo::TestNested::test_z`). Private (`_`-prefixed) names are
+/// helpers, never items.
+pub fn test_items(source: &str, patterns: &PytestPatterns) -> Vec<String> {
+    let Ok(parsed) = parse_module(source) else {
+        return Vec::new();
+    };
+
+    let mut items = Vec::new();
+    for stmt in &parsed.syntax().body {
+        match stmt {
+            ast::Stmt::FunctionDef(func) if is_collected(patterns, func.name.as_str(), false) => {
+                items.push(func.name.to_string());
+            }
+            ast::Stmt::ClassDef(class) => class_items(class, "", patterns, &mut items),
+            _ => {}
+        }
+    }
+    items
+}
+
+/// Append the items of `class` (and of test classes nested in it), each prefixed with
+/// `prefix` and the class name. unittest loads only a `TestCase`'s own methods, so classes
+/// nested in one are skipped.
+fn class_items(
+    class: &ast::StmtClassDef,
+    prefix: &str,
+    patterns: &PytestPatterns,
+    items: &mut Vec<String>,
+) {
+    let name = class.name.as_str();
+    // unittest classes are collected by base class and use unittest's `test` prefix.
+    let unittest = is_unittest_case(class);
+    if name.starts_with('_') || (!unittest && !patterns.is_test_class(name)) {
+        return;
+    }
+    // pytest refuses to collect plain test classes with an `__init__`.
+    if !unittest && class.body.iter().any(|stmt| defines(stmt, "__init__")) {
+        return;
+    }
+    let prefix = format!("{prefix}{name}::");
+    for inner in &class.body {
+        match inner {
+            ast::Stmt::FunctionDef(method)
+                if is_collected(patterns, method.name.as_str(), unittest) =>
+            {
+                items.push(format!("{prefix}{}", method.name));
+            }
+            ast::Stmt::ClassDef(nested) if !unittest => {
+                class_items(nested, &prefix, patterns, items)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_collected(patterns: &PytestPatterns, name: &str, unittest: bool) -> bool {
+    if name.starts_with('_') {
+        false
+    } else if unittest {
+        name.starts_with("test")
+    } else {
+        patterns.is_test_function(name)
+    }
+}
+
+fn defines(stmt: &ast::Stmt, name: &str) -> bool {
+    matches!(stmt, ast::Stmt::FunctionDef(func) if func.name.as_str() == name)
+}
+
+fn is_unittest_case(class: &ast::StmtClassDef) -> bool {
+    class.bases().iter().any(|base| match base {
+        ast::Expr::Name(name) => name.id.as_str().ends_with("TestCase"),
+        ast::Expr::Attribute(attr) => attr.attr.as_str().ends_with("TestCase"),
+        _ => false,
+    })
+}
+
+fn read(path: &Utf8Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))
+}
+
+fn toml_patterns(value: &toml::Value) -> Option<Vec<String>> {
+    match value {
+        toml::Value::String(s) => Some(s.split_whitespace().map(str::to_string).collect()),
+        toml::Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(str::to_string)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn ini_has_section(raw: &str, section: &str) -> bool {
+    raw.lines().any(|l| l.trim() == format!("[{section}]"))
+}
+
+/// Value of `key` in `[section]`, joining indented continuation lines.
+fn ini_value(raw: &str, section: &str, key: &str) -> Option<String> {
+    let header = format!("[{section}]");
+    let mut in_section = false;
+    let mut value: Option<String> = None;
+
+    for line in raw.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if value.is_some() {
+                break;
+            }
+            in_section = trimmed == header;
+            continue;
+        }
+        if !in_section || trimmed.is_empty() || trimmed.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(current) = value.as_mut() {
+            if line.starts_with([' ', '\t']) {
+                current.push(' ');
+                current.push_str(trimmed);
+                continue;
+            }
+            break;
+        }
+        if let Some((k, v)) = trimmed.split_once(['=', ':']) {
+            if k.trim() == key {
+                value = Some(v.trim().to_string());
+            }
+        }
+    }
+    value
+}
+
+/// pytest semantics: glob patterns must match the whole name, plain entries are prefixes.
+fn name_matches(pattern: &str, name: &str) -> bool {
+    if pattern.contains(['*', '?']) {
+        glob_match(pattern.as_bytes(), name.as_bytes())
+    } else {
+        name.starts_with(pattern)
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PytestPatterns, test_items};
+    use camino::{Utf8Path, Utf8PathBuf};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn pytest_patterns_from_pyproject_drive_item_enumeration() {
+        let tmp = tempdir().unwrap();
+        let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+        let root: &Utf8Path = root_path.as_ref();
+
+        let source = "def check_one():\n    pass\n\ndef test_two():\n    pass\n\n\
+                      class TestThing:\n    def check_method(self):\n        pass\n\n\
+                      class Helper:\n    def check_nope(self):\n        pass\n";
+
+        // pytest defaults: `test` prefix for functions, `Test` prefix for classes.
+        let defaults = PytestPatterns::load(root).unwrap();
+        assert_eq!(defaults, PytestPatterns::default());
+        assert_eq!(test_items(source, &defaults), vec!["test_two"]);
+
+        fs::write(
+            root.join("pyproject.toml"),
+            "[tool.pytest.ini_options]\npython_functions = \"check\"\n",
+        )
+        .unwrap();
+        let custom = PytestPatterns::load(root).unwrap();
+        assert_eq!(custom.functions, vec!["check"]);
+        assert_eq!(
+            test_items(source, &custom),
+            vec!["check_one", "TestThing::check_method"]
+        );
+
+        // pytest.ini takes precedence over pyproject.toml, and globs must match the whole name.
+        fs::write(
+            root.join("pytest.ini"),
+            "[pytest]\npython_functions = *_check\npython_classes = *Suite\n",
+        )
+        .unwrap();
+        let ini = PytestPatterns::load(root).unwrap();
+        assert_eq!(
+            test_items("def one_check(): pass\ndef check_x(): pass\n", &ini),
+            vec!["one_check"]
+        );
+    }
+
+    #[test]
+    fn test_items_walk_nested_classes_and_skip_helpers() {
+        let source = "class TestFoo:\n    def test_one(self): pass\n    def test_two(self): pass\n    def _test_helper(self): pass\n    def helper(self): pass\n";
+        assert_eq!(test_items(source, &PytestPatterns::default()).len(), 2);
+
+        let source = "def test_top(): pass\n\
+                      async def test_async(): pass\n\
+                      def _test_private(): pass\n\
+                      class TestOuter:\n    def test_a(self): pass\n    class TestInner:\n        def test_b(self): pass\n    class Helper:\n        def test_c(self): pass\n\
+                      class TestWithInit:\n    def __init__(self): pass\n    def test_d(self): pass\n\
+                      class _TestPrivate:\n    def test_e(self): pass\n";
+        assert_eq!(
+            test_items(source, &PytestPatterns::default()),
+            vec![
+                "test_top",
+                "test_async",
+                "TestOuter::test_a",
+                "TestOuter::TestInner::test_b"
+            ]
+        );
+
+        let source = "import unittest\n\
+                      class TestCaseOuter(unittest.TestCase):\n    def test_a(self): pass\n    class TestInner:\n        def test_b(self): pass\n    class InnerCase(unittest.TestCase):\n        def test_c(self): pass\n";
+        assert_eq!(
+            test_items(source, &PytestPatterns::default()),
+            vec!["TestCaseOuter::test_a"]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+use serde_json::{Map, Value, json};
+
+use crate::collect::PytestPatterns;
+use crate::paths::SKIPPED_DIRS;
+use crate::{PROJECT_MARKERS, ROOT_MARKER, SelectArgs, choose_root, explicit_roots};
+
+/// Settings read from the `[tool.testdiff]` table of `<root>/pyproject.toml`. They fill in
+/// whatever the command line left unset; explicit flags always win.
+#[derive(Debug, Default, PartialEq)]
+pub struct FileConfig {
+    pub path: Utf8PathBuf,
+    pub distance_limit: Option<usize>,
+    pub max: Option<usize>,
+    pub extra_skip_dirs: Vec<String>,
+    pub source_roots: Vec<String>,
+}
+
+const FILE_KEYS: &[&str] = &["distance_limit", "max", "extra_skip_dirs", "source_roots"];
+
+impl FileConfig {
+    /// `None` when there is no `pyproject.toml` or it has no `[tool.testdiff]` table. Unknown
+    /// keys are an error so a typo cannot silently do nothing.
+    pub fn load(root: &Utf8Path) -> Result<Option<Self>> {
+        let path = root.join("pyproject.toml");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let raw =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {path}"))?;
+        let table: toml::Table =
+            toml::from_str(&raw).with_context(|| format!("Failed to parse {path}"))?;
+        let Some(settings) = table.get("tool").and_then(|t| t.get("testdiff")) else {
+            return Ok(None);
+        };
+        let Some(settings) = settings.as_table() else {
+            bail!("`tool.testdiff` in {path} must be a table");
+        };
+
+        let mut config = Self {
+            path: path.clone(),
+            ..Self::default()
+        };
+        for (key, value) in settings {
+            let invalid = |expected: &str| {
+                anyhow::anyhow!("`tool.testdiff.{key}` in {path} must be {expected}")
+            };
+            match key.as_str() {
+                "distance_limit" | "max" => {
+                    let n = value
+                        .as_integer()
+                        .and_then(|n| usize::try_from(n).ok())
+                        .ok_or_else(|| invalid("a non-negative integer"))?;
+                    if key == "max" {
+                        config.max = Some(n);
+                    } else {
+                        config.distance_limit = Some(n);
+                    }
+                }
+                "extra_skip_dirs" | "source_roots" => {
+                    let items = value
+                        .as_array()
+                        .and_then(|items| {
+                            items
+                                .iter()
+                                .map(|item| item.as_str().map(str::to_string))
+                                .collect::<Option<Vec<_>>>()
+                        })
+                        .ok_or_else(|| invalid("an array of strings"))?;
+                    if key == "extra_skip_dirs" {
+                        config.extra_skip_dirs = items;
+                    } else {
+                        config.source_roots = items;
+                    }
+                }
+                _ => bail!(
+                    "Unknown key `{key}` in [tool.testdiff] of {path} (expected one of: {})",
+                    FILE_KEYS.join(", ")
+                ),
+            }
+        }
+        Ok(Some(config))
+    }
+
+    /// Fill the settings `args` left unset, returning the ids of the ones taken from the file.
+    pub fn apply(self, args: &mut SelectArgs) -> Vec<&'static str> {
+        let mut applied = Vec::new();
+        if args.distance_limit.is_none() && self.distance_limit.is_some() {
+            args.distance_limit = self.distance_limit;
+            applied.push("distance_limit");
+        }
+        if args.max.is_none() && self.max.is_some() {
+            args.max = self.max;
+            applied.push("max");
+        }
+        if args.extra_skip_dirs.is_empty() && !self.extra_skip_dirs.is_empty() {
+            args.extra_skip_dirs = self.extra_skip_dirs;
+            applied.push("extra_skip_dirs");
+        }
+        if args.source_root.is_empty() && !self.source_roots.is_empty() {
+            args.source_root = self.source_roots;
+            applied.push("source_root");
+        }
+        applied
+    }
+}
+
+/// `--dump-config`: print the effective settings as JSON and exit.
+pub fn dump_config(args: &SelectArgs, matches: &ArgMatches, cwd: &Path) -> Result<()> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&effective_config(args, matches, cwd)?)?
+    );
+    Ok(())
+}
+
+/// `{"schemaVersion": 1, "root": "...", "settings": {"<name>": {"value": ..., "source": ...}}}`
+/// where `source` is `cli`, `env`, `default`, `builtin`, or the config file a value came from.
+fn effective_config(args: &SelectArgs, matches: &ArgMatches, cwd: &Path) -> Result<Value> {
+    let explicit_root = explicit_roots(args, cwd).into_iter().next();
+    let root = choose_root(explicit_root, &[], cwd)?;
+    let (patterns, pytest_config) = PytestPatterns::load_with_source(&root)?;
+    let pytest_source = pytest_config.map_or_else(|| "default".to_string(), |p| p.to_string());
+
+    let mut args = args.clone();
+    let (from_file, file_source) = match FileConfig::load(&root)? {
+        Some(file) => {
+            let path = file.path.to_string();
+            (file.apply(&mut args), path)
+        }
+        None => (Vec::new(), String::new()),
+    };
+    let args = &args;
+
+    let mut settings = Map::new();
+    let mut flag = |id: &str, value: Value| {
+        let source = if from_file.contains(&id) {
+            file_source.as_str()
+        } else {
+            source(matches, id)
+        };
+        settings.insert(id.to_string(), json!({ "value": value, "source": source }));
+    };
+    flag("root", json!(args.root));
+    flag("root_from_git", json!(args.root_from_git));
+    flag("distance_limit", json!(args.distance_limit));
+    flag("deleted_distance_limit", json!(args.deleted_distance_limit));
+    flag("no_transitive", json!(args.no_transitive));
+    flag("max", json!(args.max));
+    flag("max_files", json!(args.max_files));
+    flag("max_warnings", json!(args.max_warnings));
+    flag("min_filename_match", json!(args.min_filename_match));
+    flag("jobs", json!(args.jobs));
+    flag(
+        "cache_dir",
+        json!(args.cache_dir.as_ref().map(|dir| dir.as_str())),
+    );
+    flag("fixture_dirs", json!(args.fixture_dirs));
+    flag("global_conftest_dirs", json!(args.global_conftest_dirs));
+    flag("map_ext", json!(args.map_ext));
+    flag("git_pathspec", json!(args.git_pathspec));
+    flag("git_diff_filter", json!(args.git_diff_filter));
+    flag("ignore_path_case", json!(args.ignore_path_case));
+    flag("type_checking_imports", json!(args.type_checking_imports));
+    flag("data_deps", json!(args.data_deps));
+    flag("source_root", json!(args.source_root));
+    flag("extra_skip_dirs", json!(args.extra_skip_dirs));
+
+    let mut fixed = |id: &str, value: Value, source: &str| {
+        settings.insert(id.to_string(), json!({ "value": value, "source": source }));
+    };
+    fixed(
+        "root_markers",
+        json!([&[ROOT_MARKER][..], PROJECT_MARKERS].concat()),
+        "builtin",
+    );
+    fixed("skipped_dirs", json!(SKIPPED_DIRS), "builtin");
+    fixed("test_files", json!(["test_*.py", "*_test.py"]), "builtin");
+    fixed("python_classes", json!(patterns.classes), &pytest_source);
+    fixed(
+        "python_functions",
+        json!(patterns.functions),
+        &pytest_source,
+    );
+
+    let mut fields = Map::new();
+    fields.insert("root".to_string(), json!(root.as_str()));
+    fields.insert("settings".to_string(), Value::Object(settings));
+    Ok(crate::json::document(fields))
+}
+
+fn source(matches: &ArgMatches, id: &str) -> &'static str {
+    match matches.value_source(id) {
+        Some(ValueSource::CommandLine) => "cli",
+        Some(ValueSource::EnvVariable) => "env",
+        _ => "default",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileConfig, effective_config};
+    use crate::Cli;
+    use camino::Utf8PathBuf;
+    use clap::{CommandFactory, FromArgMatches};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn reports_the_source_of_each_setting() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        fs::write(
+            root.join("pyproject.toml"),
+            "[tool.pytest.ini_options]\npython_functions = \"check\"\n",
+        )
+        .unwrap();
+
+        let argv = [
+            "testdiff",
+            "--dump-config",
+            "--distance-limit",
+            "3",
+            "--root",
+            root.to_str().unwrap(),
+        ];
+        let matches = Cli::command().try_get_matches_from(argv).unwrap();
+        let args = Cli::from_arg_matches(&matches).unwrap().select;
+
+        let config = effective_config(&args, &matches, &root).unwrap();
+        let settings = &config["settings"];
+        assert_eq!(config["schemaVersion"], 1);
+        assert_eq!(settings["distance_limit"]["value"], 3);
+        assert_eq!(settings["distance_limit"]["source"], "cli");
+        assert_eq!(settings["max_warnings"]["value"], 50);
+        assert_eq!(settings["max_warnings"]["source"], "default");
+        assert_eq!(settings["max"]["source"], "default");
+        assert_eq!(settings["python_functions"]["value"][0], "check");
+        assert!(
+            settings["python_functions"]["source"]
+                .as_str()
+                .unwrap()
+                .ends_with("pyproject.toml")
+        );
+        assert_eq!(settings["python_classes"]["value"][0], "Test");
+    }
+
+    #[test]
+    fn tool_testdiff_table_fills_unset_flags() {
+        let tmp = tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(tmp.path().canonicalize().unwrap()).unwrap();
+        fs::write(
+            root.join("pyproject.toml"),
+            "[tool.testdiff]\ndistance_limit = 2\nmax = 10\nextra_skip_dirs = [\"generated\"]\n",
+        )
+        .unwrap();
+
+        let argv = ["testdiff", "--max", "5", "--root", root.as_str()];
+        let matches = Cli::command().try_get_matches_from(argv).unwrap();
+        let mut args = Cli::from_arg_matches(&matches).unwrap().select;
+        let applied = FileConfig::load(&root).unwrap().unwrap().apply(&mut args);
+        assert_eq!(applied, vec!["distance_limit", "extra_skip_dirs"]);
+        assert_eq!(args.distance_limit, Some(2));
+        assert_eq!(args.max, Some(5), "the CLI flag wins");
+        assert_eq!(args.extra_skip_dirs, vec!["generated"]);
+
+        let unapplied = Cli::from_arg_matches(&matches).unwrap().select;
+        let config = effective_config(&unapplied, &matches, root.as_std_path()).unwrap();
+        let settings = &config["settings"];
+        assert!(
+            settings["distance_limit"]["source"]
+                .as_str()
+                .unwrap()
+                .ends_with("pyproject.toml")
+        );
+        assert_eq!(settings["max"]["source"], "cli");
+        assert!(
+            settings["extra_skip_dirs"]["source"]
+                .as_str()
+                .unwrap()
+                .ends_with("pyproject.toml")
+        );
+
+        // `--skip-dir` replaces the file's list, like every other flag.
+        let argv = ["testdiff", "--skip-dir", "vendor", "--root", root.as_str()];
+        let matches = Cli::command().try_get_matches_from(argv).unwrap();
+        let mut args = Cli::from_arg_matches(&matches).unwrap().select;
+        let applied = FileConfig::load(&root).unwrap().unwrap().apply(&mut args);
+        assert_eq!(applied, vec!["distance_limit"]);
+        assert_eq!(args.extra_skip_dirs, vec!["vendor"]);
+
+        fs::write(
+            root.join("pyproject.toml"),
+            "[tool.testdiff]\ndistance_limt = 2\n",
+        )
+        .unwrap();
+        let err = FileConfig::load(&root).unwrap_err().to_string();
+        assert!(err.contains("Unknown key `distance_limt`"), "{err}");
+    }
+}
@@ -0,0 +1,73 @@
+use ruff_python_ast as ast;
+use ruff_python_ast::comparable::ComparableStmt;
+use ruff_python_parser::parse_module;
+
+/// Heuristic: true when `old` and `new` differ only in comments, whitespace, or docstrings.
+///
+/// Both sources are parsed and compared structurally (ranges are ignored), after dropping
+/// the docstrings of the module and of every function/class reachable through def bodies.
+/// Anything that fails to parse is treated as a real change.
+pub fn is_cosmetic_only(old: &str, new: &str) -> bool {
+    let (Ok(old), Ok(new)) = (parse_module(old), parse_module(new)) else {
+        return false;
+    };
+
+    let mut old_body = old.into_syntax().body;
+    let mut new_body = new.into_syntax().body;
+    strip_docstrings(&mut old_body);
+    strip_docstrings(&mut new_body);
+
+    old_body.len() == new_body.len()
+        && old_body
+            .iter()
+            .zip(new_body.iter())
+            .all(|(a, b)| ComparableStmt::from(a) == ComparableStmt::from(b))
+}
+
+fn strip_docstrings(body: &mut Vec<ast::Stmt>) {
+    let has_docstring = matches!(
+        body.first(),
+        Some(ast::Stmt::Expr(ast::StmtExpr { value, .. }))
+            if matches!(value.as_ref(), ast::Expr::StringLiteral(_))
+    );
+    if has_docstring {
+        body.remove(0);
+    }
+
+    for stmt in body.iter_mut() {
+        match stmt {
+            ast::Stmt::FunctionDef(ast::StmtFunctionDef { body, .. })
+            | ast::Stmt::ClassDef(ast::StmtClassDef { body, .. }) => strip_docstrings(body),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_cosmetic_only;
+
+    #[test]
+    fn comment_and_whitespace_changes_are_cosmetic() {
+        let old = "def f(x):\n    return x + 1\n";
+        let new = "# helper\ndef f(x):\n\n    # add one\n    return x+1  # inline\n";
+        assert!(is_cosmetic_only(old, new));
+    }
+
+    #[test]
+    fn docstring_changes_are_cosmetic() {
+        let old = "\"\"\"Module.\"\"\"\nclass A:\n    def f(self):\n        return 1\n";
+        let new = "\"\"\"Module docs.\"\"\"\nclass A:\n    \"\"\"Class.\"\"\"\n    def f(self):\n        \"\"\"Return one.\"\"\"\n        return 1\n";
+        assert!(is_cosmetic_only(old, new));
+    }
+
+    #[test]
+    fn code_changes_are_not_cosmetic() {
+        assert!(!is_cosmetic_only("x = 1\n", "x = 2\n"));
+        assert!(!is_cosmetic_only(
+            "def f():\n    return 'a'\n",
+            "def f():\n    return 'b'\n"
+        ));
+        assert!(!is_cosmetic_only("x = 1\n", "x = (\n"));
+    }
+}
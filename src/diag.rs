@@ -0,0 +1,141 @@
+//! Diagnostics on stderr: one line per message behind a severity prefix, colored only when
+//! stderr is a terminal and `NO_COLOR` is unset. Selection output on stdout never goes through
+//! here, so it stays machine-readable.
+
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How important a message is; a message shows when its level is at or below [`max_level`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// The level `-v` flags ask for: warnings by default, info with one, debug with two.
+    pub fn from_verbosity(verbose: u8) -> Self {
+        match verbose {
+            0 => Self::Warning,
+            1 => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Error => "Error:",
+            Self::Warning => "Warning:",
+            Self::Info => "Info:",
+            Self::Debug => "Debug:",
+        }
+    }
+
+    /// SGR parameters for the prefix: bold red, bold yellow, cyan, dim.
+    fn style(self) -> &'static str {
+        match self {
+            Self::Error => "1;31",
+            Self::Warning => "1;33",
+            Self::Info => "36",
+            Self::Debug => "2",
+        }
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Warning as u8);
+
+/// Show messages up to `level`; the CLI sets this once from `-v`/`--quiet`.
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// The most verbose level currently shown (`Warning` unless [`set_max_level`] changed it).
+pub fn max_level() -> Level {
+    match MAX_LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Error,
+        1 => Level::Warning,
+        2 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= max_level()
+}
+
+pub fn error(message: impl Display) {
+    emit(Level::Error, message);
+}
+
+pub fn warning(message: impl Display) {
+    emit(Level::Warning, message);
+}
+
+pub fn info(message: impl Display) {
+    emit(Level::Info, message);
+}
+
+pub fn debug(message: impl Display) {
+    emit(Level::Debug, message);
+}
+
+/// Print `message` to stderr behind the prefix for `level`, if that level is shown.
+pub fn emit(level: Level, message: impl Display) {
+    if enabled(level) {
+        eprintln!("{} {message}", paint(level.style(), level.prefix()));
+    }
+}
+
+/// `text` in bold when stderr is colored, for headings in multi-line stderr reports.
+pub fn bold(text: &str) -> String {
+    paint("1", text)
+}
+
+fn paint(style: &str, text: &str) -> String {
+    if use_color() {
+        format!("\x1b[{style}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Whether stderr gets ANSI colors, decided once per process. `NO_COLOR` (any non-empty
+/// value, per no-color.org) and a redirected stderr both turn them off.
+fn use_color() -> bool {
+    static COLOR: OnceLock<bool> = OnceLock::new();
+    *COLOR.get_or_init(|| {
+        color_allowed(std::env::var_os("NO_COLOR").as_deref(), || {
+            std::io::stderr().is_terminal()
+        })
+    })
+}
+
+fn color_allowed(no_color: Option<&std::ffi::OsStr>, is_terminal: impl FnOnce() -> bool) -> bool {
+    no_color.is_none_or(|v| v.is_empty()) && is_terminal()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn color_needs_a_terminal_and_no_no_color() {
+        assert!(color_allowed(None, || true));
+        assert!(!color_allowed(None, || false));
+        assert!(!color_allowed(Some(OsStr::new("1")), || true));
+        assert!(color_allowed(Some(OsStr::new("")), || true));
+    }
+
+    #[test]
+    fn verbosity_maps_to_levels() {
+        assert_eq!(Level::from_verbosity(0), Level::Warning);
+        assert_eq!(Level::from_verbosity(1), Level::Info);
+        assert_eq!(Level::from_verbosity(5), Level::Debug);
+        assert!(Level::Error < Level::Warning && Level::Info < Level::Debug);
+    }
+}
@@ -1,68 +1,472 @@
+use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use clap::Args;
+use clap::{Args, ValueEnum};
 use once_cell::sync::Lazy;
 use pathdiff::diff_paths;
 use regex::Regex;
 use roxmltree::{Document, Node};
 
+use testdiff::PreviousFailures;
+
+use crate::diag;
+use crate::shard::Durations;
+
 /// Convert pytest-style JUnit XML into GitHub Actions log annotations.
 #[derive(Args, Debug)]
 pub struct FormatArgs {
-    /// Path to a pytest JUnit XML report (e.g., produced with `pytest --junitxml=report.xml`)
-    pub path: PathBuf,
+    /// Path to a pytest JUnit XML report (e.g., produced with `pytest --junitxml=report.xml`).
+    /// With several reports of the same suite, tests that failed in only some of them are
+    /// reported as flaky warnings
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
 
     /// Emit warnings for skipped tests (by default, skips are ignored)
     #[arg(long)]
     pub include_skipped: bool,
+
+    /// Fail unless the XML looks like a JUnit report (root `testsuite`/`testsuites` with at least one `testcase`)
+    #[arg(long)]
+    pub validate: bool,
+
+    /// Only annotate testcases whose file is in this comma-separated list (e.g. the output of a
+    /// prior `testdiff` run); `@file` reads one path per line
+    #[arg(long, value_delimiter = ',')]
+    pub only_files: Vec<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Github)]
+    pub format: ReportFormat,
+
+    /// Also write a Markdown job summary of the failures, each traceback collapsed in a
+    /// `<details>` block, appended to `$GITHUB_STEP_SUMMARY` (stdout when unset)
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Instead of annotations, print a table of the N slowest testcases by their `time`
+    /// attribute, ending with the total time
+    #[arg(long, value_name = "N", conflicts_with_all = ["format", "summary"])]
+    pub slowest: Option<usize>,
+}
+
+/// Output formats for `testdiff format`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// GitHub Actions workflow commands (`::error file=...,line=...::message`)
+    #[default]
+    Github,
+    /// A Checkstyle XML document with the failures grouped per file
+    Checkstyle,
+    /// `{"schemaVersion", "findings": [{"name", "classname", "file", "line", "kind", "message"}]}`
+    Json,
+    /// A SARIF 2.1.0 log with one `result` per failure, for code scanning dashboards
+    Sarif,
+    /// A GitLab Code Quality report (JSON array), for `artifacts:reports:codequality`
+    Gitlab,
+}
+
+/// One failure, error, or skip to report.
+#[derive(Debug, PartialEq)]
+struct Finding {
+    /// `error` or `warning`; doubles as the Checkstyle severity.
+    level: &'static str,
+    /// JUnit element reported: `failure`, `error`, or `skipped`.
+    kind: &'static str,
+    /// Raw `classname` and `name` attributes (the suite name for collection errors).
+    classname: Option<String>,
+    name: Option<String>,
+    file: Option<PathBuf>,
+    line: Option<usize>,
+    /// Test (or, for collection errors, suite) name.
+    test: String,
+    /// Short, single-line message.
+    message: String,
+    /// Full text of the failure node (usually the traceback), if any.
+    body: Option<String>,
+}
+
+impl Finding {
+    /// `test: message`, as shown in annotations.
+    fn text(&self) -> String {
+        format!("{}: {}", self.test, self.message)
+    }
 }
 
 /// Entry point for the `testdiff format` subcommand.
 pub fn format_junit(args: &FormatArgs) -> Result<()> {
-    let xml = std::fs::read_to_string(&args.path)
-        .with_context(|| format!("Failed to read {}", args.path.display()))?;
+    let xmls = args
+        .paths
+        .iter()
+        .map(|path| {
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    let doc = Document::parse(&xml)
-        .with_context(|| format!("Failed to parse XML in {}", args.path.display()))?;
+    let mut docs = Vec::new();
+    for (path, xml) in args.paths.iter().zip(&xmls) {
+        let doc = Document::parse(xml)
+            .with_context(|| format!("Failed to parse XML in {}", path.display()))?;
+        if args.validate {
+            validate_report(&doc)
+                .with_context(|| format!("{} is not a JUnit report", path.display()))?;
+        }
+        docs.push(doc);
+    }
+
+    if let Some(limit) = args.slowest {
+        let timings = docs.iter().flat_map(testcase_timings).collect();
+        print!("{}", render_slowest(timings, limit));
+        return Ok(());
+    }
 
     let cwd = std::env::current_dir()?;
-    let mut reported = 0usize;
+    let only = FileFilter::from_args(&args.only_files, &cwd)?;
+
+    let findings = if let [doc] = docs.as_slice() {
+        report_findings(doc, args.include_skipped, only.as_ref())
+    } else {
+        merge_runs(&docs, args.include_skipped, only.as_ref())
+    };
+
+    match args.format {
+        ReportFormat::Github => {
+            for finding in &findings {
+                emit_annotation(
+                    finding.level,
+                    finding.file.as_deref(),
+                    finding.line,
+                    &finding.text(),
+                    &cwd,
+                );
+            }
+        }
+        ReportFormat::Checkstyle => print!("{}", render_checkstyle(&findings, &cwd)),
+        ReportFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&findings_json(&findings, &cwd))?
+        ),
+        ReportFormat::Sarif => println!(
+            "{}",
+            serde_json::to_string_pretty(&sarif_log(&findings, &cwd))?
+        ),
+        ReportFormat::Gitlab => println!("{}", render_gitlab(&findings, &cwd)?),
+    }
+
+    if args.summary {
+        write_summary(&render_summary(&findings, &cwd))?;
+    }
+
+    if findings.is_empty() {
+        let paths: Vec<_> = args.paths.iter().map(|p| p.display().to_string()).collect();
+        diag::info(format_args!(
+            "no failures, errors, or skipped tests found in {}",
+            paths.join(", ")
+        ));
+    }
+
+    Ok(())
+}
 
-    for case in doc
+/// Tests that failed or errored in the JUnit report at `path`, for `--previous-failures`.
+pub fn load_previous_failures(path: &Path) -> Result<PreviousFailures> {
+    let xml = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let doc = Document::parse(&xml)
+        .with_context(|| format!("Failed to parse XML in {}", path.display()))?;
+    Ok(previous_failures(&doc))
+}
+
+fn previous_failures(doc: &Document<'_>) -> PreviousFailures {
+    let mut previous = PreviousFailures::default();
+    // Unexpected passes are warnings, not failures.
+    for finding in report_findings(doc, false, None)
+        .into_iter()
+        .filter(|finding| finding.level == "error")
+    {
+        if let Some(file) = finding.file {
+            previous.files.push(file);
+        }
+        if let Some(classname) = finding.classname {
+            previous.classnames.push(classname);
+        }
+    }
+    previous
+}
+
+/// Recorded test durations in the JUnit report at `path`, for `--durations`.
+pub fn load_durations(path: &Path) -> Result<Durations> {
+    let xml = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let doc = Document::parse(&xml)
+        .with_context(|| format!("Failed to parse XML in {}", path.display()))?;
+    Ok(durations(&doc))
+}
+
+fn durations(doc: &Document<'_>) -> Durations {
+    let mut durations = Durations::default();
+    for case in doc.descendants().filter(|n| n.has_tag_name("testcase")) {
+        let Some(secs) = case.attribute("time").and_then(|t| t.parse::<f64>().ok()) else {
+            continue;
+        };
+        if let Some(file) = non_empty_attribute(&case, "file") {
+            *durations.files.entry(PathBuf::from(file)).or_default() += secs;
+        }
+        if let Some(classname) = non_empty_attribute(&case, "classname") {
+            *durations.classnames.entry(classname).or_default() += secs;
+        }
+    }
+    durations
+}
+
+/// One testcase's run time, for `--slowest`.
+#[derive(Debug, PartialEq)]
+struct Timing {
+    test: String,
+    /// The `time` attribute, if present and numeric.
+    seconds: Option<f64>,
+}
+
+fn testcase_timings(doc: &Document<'_>) -> Vec<Timing> {
+    doc.descendants()
+        .filter(|n| n.has_tag_name("testcase"))
+        .map(|case| Timing {
+            test: testcase_name(&case),
+            seconds: case.attribute("time").and_then(|t| t.parse().ok()),
+        })
+        .collect()
+}
+
+/// The `limit` slowest testcases, slowest first (ties keep report order), as a
+/// right-aligned `time  test` table closed by the total over every testcase. Testcases
+/// without a `time` count as 0s and are noted below the table.
+fn render_slowest(mut timings: Vec<Timing>, limit: usize) -> String {
+    let secs = |timing: &Timing| timing.seconds.unwrap_or(0.0);
+    let total: f64 = timings.iter().map(secs).sum();
+    let untimed = timings.iter().filter(|t| t.seconds.is_none()).count();
+    timings.sort_by(|a, b| secs(b).total_cmp(&secs(a)));
+
+    let rows: Vec<(String, &str)> = timings
+        .iter()
+        .take(limit)
+        .map(|t| (format!("{:.3}s", secs(t)), t.test.as_str()))
+        .collect();
+    let total_cell = format!("{total:.3}s");
+    let width = rows
+        .iter()
+        .map(|(time, _)| time.len())
+        .chain([total_cell.len(), "time".len()])
+        .max()
+        .unwrap_or(0);
+
+    let mut out = format!("{:>width$}  test\n", "time");
+    for (time, test) in &rows {
+        out.push_str(&format!("{time:>width$}  {test}\n"));
+    }
+    out.push_str(&format!(
+        "{total_cell:>width$}  total ({} testcases)\n",
+        timings.len()
+    ));
+    if untimed > 0 {
+        out.push_str(&format!(
+            "Note: {untimed} testcases have no `time` attribute; counted as 0s\n"
+        ));
+    }
+    out
+}
+
+/// Everything one report has to say: failing (and optionally skipped) testcases, then
+/// collection failures (e.g., import errors) reported on the suite itself.
+fn report_findings(
+    doc: &Document<'_>,
+    include_skipped: bool,
+    only: Option<&FileFilter>,
+) -> Vec<Finding> {
+    let mut findings = case_findings(doc, include_skipped, only);
+    findings.extend(suite_error_findings(doc, only));
+    findings
+}
+
+/// Findings across several runs of the same suite, matched on `(classname, name)`. A test that
+/// failed in every run it appears in is reported as an error from its last failing run; one
+/// that also passed somewhere is flaky and downgraded to a warning noting how often it failed.
+fn merge_runs(
+    docs: &[Document<'_>],
+    include_skipped: bool,
+    only: Option<&FileFilter>,
+) -> Vec<Finding> {
+    type Key = (Option<String>, Option<String>);
+
+    let mut passes: HashMap<Key, usize> = HashMap::new();
+    let mut fails: HashMap<Key, usize> = HashMap::new();
+    let mut latest: Vec<(Key, Vec<Finding>)> = Vec::new();
+    for doc in docs {
+        let mut by_test: Vec<(Key, Vec<Finding>)> = Vec::new();
+        for finding in report_findings(doc, include_skipped, only) {
+            let key = (finding.classname.clone(), finding.name.clone());
+            match by_test.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.push(finding),
+                None => by_test.push((key, vec![finding])),
+            }
+        }
+        for (key, group) in by_test {
+            if group.iter().any(|f| f.level == "error") {
+                *fails.entry(key.clone()).or_default() += 1;
+            }
+            match latest.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, existing)) => *existing = group,
+                None => latest.push((key, group)),
+            }
+        }
+        for case in doc
+            .descendants()
+            .filter(|node| node.has_tag_name("testcase"))
+        {
+            if children_named(&case, &["failure", "error", "skipped"]).is_empty() {
+                let key = (
+                    non_empty_attribute(&case, "classname"),
+                    non_empty_attribute(&case, "name"),
+                );
+                *passes.entry(key).or_default() += 1;
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for (key, group) in latest {
+        let failed = fails.get(&key).copied().unwrap_or(0);
+        let passed = passes.get(&key).copied().unwrap_or(0);
+        for mut finding in group {
+            if finding.level == "error" && passed > 0 {
+                finding.level = "warning";
+                finding.message = format!(
+                    "flaky (failed in {failed} of {} runs): {}",
+                    failed + passed,
+                    finding.message
+                );
+            }
+            out.push(finding);
+        }
+    }
+    out
+}
+
+fn case_findings(
+    doc: &Document<'_>,
+    include_skipped: bool,
+    only: Option<&FileFilter>,
+) -> Vec<Finding> {
+    let cases: Vec<Node<'_, '_>> = doc
         .descendants()
         .filter(|node| node.has_tag_name("testcase"))
-    {
-        if let Some(child) = first_child(&case, &["failure", "error"]) {
-            let (file, line) = derive_location(&case, child.text());
-            let message = format!(
-                "{}: {}",
-                testcase_name(&case),
-                pick_message(&child, "Test failed")
-            );
-            emit_annotation("error", file.as_deref(), line, &message, &cwd);
-            reported += 1;
-        } else if args.include_skipped {
-            if let Some(child) = first_child(&case, &["skipped"]) {
+        .collect();
+    // Reruns (xdist, rerun plugins, merged reports) repeat a testcase; only its last outcome
+    // counts, so a failure followed by a pass is not reported. Unnamed (synthetic) cases are
+    // never merged.
+    let last: HashMap<_, usize> = cases
+        .iter()
+        .enumerate()
+        .map(|(i, case)| (testcase_key(case), i))
+        .collect();
+
+    let mut out = Vec::new();
+    for (i, case) in cases.into_iter().enumerate() {
+        let named = case.attribute("name").is_some_and(|name| !name.is_empty());
+        if named && last[&testcase_key(&case)] != i {
+            continue;
+        }
+        // Some runners attach several failure/error nodes to one testcase; annotate each.
+        let failures = children_named(&case, &["failure", "error"]);
+        if !failures.is_empty() {
+            for child in failures {
                 let (file, line) = derive_location(&case, child.text());
-                let message = format!(
-                    "{}: {}",
-                    testcase_name(&case),
-                    pick_message(&child, "Test skipped")
-                );
-                emit_annotation("warning", file.as_deref(), line, &message, &cwd);
-                reported += 1;
+                if !FileFilter::allows(only, file.as_deref()) {
+                    continue;
+                }
+                out.push(Finding {
+                    level: "error",
+                    kind: if child.has_tag_name("failure") {
+                        "failure"
+                    } else {
+                        "error"
+                    },
+                    classname: non_empty_attribute(&case, "classname"),
+                    name: non_empty_attribute(&case, "name"),
+                    file,
+                    line,
+                    test: testcase_name(&case),
+                    message: pick_message(&child, "Test failed"),
+                    body: node_body(&child),
+                });
             }
+        } else if let Some(child) = first_child(&case, &["skipped"]) {
+            // Expected failures are the normal state of an xfail test; an unexpected pass is
+            // always worth a warning, plain skips only with --include-skipped.
+            let (kind, message) = match skip_kind(&child) {
+                SkipKind::Xfail => continue,
+                SkipKind::Skip if !include_skipped => continue,
+                SkipKind::Skip => ("skipped", pick_message(&child, "Test skipped")),
+                SkipKind::Xpass => ("xpass", "unexpectedly passed".to_string()),
+            };
+            let (file, line) = derive_location(&case, child.text());
+            if !FileFilter::allows(only, file.as_deref()) {
+                continue;
+            }
+            out.push(Finding {
+                level: "warning",
+                kind,
+                classname: non_empty_attribute(&case, "classname"),
+                name: non_empty_attribute(&case, "name"),
+                file,
+                line,
+                test: testcase_name(&case),
+                message,
+                body: node_body(&child),
+            });
         }
     }
+    out
+}
 
-    if reported == 0 {
-        eprintln!(
-            "No failures, errors, or skipped tests found in {}",
-            args.path.display()
-        );
+/// The `--only-files` set. Report paths may be absolute or relative to a different directory
+/// than the listed ones, so a file matches when it ends with a listed path.
+struct FileFilter {
+    files: Vec<PathBuf>,
+}
+
+impl FileFilter {
+    fn from_args(raw: &[String], cwd: &Path) -> Result<Option<Self>> {
+        if raw.is_empty() {
+            return Ok(None);
+        }
+        let files = crate::expand_argfiles(raw, cwd)?
+            .into_iter()
+            .map(|f| PathBuf::from(f.trim_start_matches("./")))
+            .collect();
+        Ok(Some(Self { files }))
     }
 
+    /// No filter allows everything; with a filter, cases without a derivable file are dropped.
+    fn allows(filter: Option<&Self>, file: Option<&Path>) -> bool {
+        match (filter, file) {
+            (None, _) => true,
+            (Some(filter), Some(file)) => filter.files.iter().any(|f| file.ends_with(f)),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+fn validate_report(doc: &Document<'_>) -> Result<()> {
+    let root = doc.root_element();
+    let tag = root.tag_name().name();
+    if tag != "testsuite" && tag != "testsuites" {
+        anyhow::bail!("expected root element `testsuite` or `testsuites`, found `{tag}`");
+    }
+    if !root.descendants().any(|node| node.has_tag_name("testcase")) {
+        anyhow::bail!("no `testcase` elements found under `{tag}`");
+    }
     Ok(())
 }
 
@@ -71,9 +475,80 @@ fn first_child<'a>(case: &'a Node<'_, '_>, names: &[&str]) -> Option<Node<'a, 'a
         .find(|child| child.is_element() && names.iter().any(|tag| child.has_tag_name(*tag)))
 }
 
+/// What a testcase's `<skipped>` element stands for.
+#[derive(Debug, PartialEq)]
+enum SkipKind {
+    Skip,
+    /// `@pytest.mark.xfail` test that failed as expected (`type="pytest.xfail"`).
+    Xfail,
+    /// xfail-marked test that passed; pytest reports it as a skip with a telltale message.
+    Xpass,
+}
+
+fn skip_kind(skipped: &Node<'_, '_>) -> SkipKind {
+    let kind = skipped.attribute("type").unwrap_or_default();
+    let message = skipped.attribute("message").unwrap_or_default();
+    if kind == "pytest.xpass" || message.contains("xfail-marked test passes unexpectedly") {
+        SkipKind::Xpass
+    } else if kind == "pytest.xfail" {
+        SkipKind::Xfail
+    } else {
+        SkipKind::Skip
+    }
+}
+
+fn children_named<'a, 'input>(case: &Node<'a, 'input>, names: &[&str]) -> Vec<Node<'a, 'input>> {
+    case.children()
+        .filter(|child| child.is_element() && names.iter().any(|tag| child.has_tag_name(*tag)))
+        .collect()
+}
+
+fn suite_error_findings(doc: &Document<'_>, only: Option<&FileFilter>) -> Vec<Finding> {
+    let mut out = Vec::new();
+    for suite in doc
+        .descendants()
+        .filter(|node| node.has_tag_name("testsuite"))
+    {
+        let suite_name = suite
+            .attribute("name")
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or("(unknown suite)");
+        for child in children_named(&suite, &["error"]) {
+            let (file, line) = derive_location(&suite, child.text());
+            if !FileFilter::allows(only, file.as_deref()) {
+                continue;
+            }
+            out.push(Finding {
+                level: "error",
+                kind: "error",
+                classname: None,
+                name: Some(suite_name.to_string()),
+                file,
+                line,
+                test: suite_name.to_string(),
+                message: pick_message(&child, "Collection error"),
+                body: node_body(&child),
+            });
+        }
+    }
+    out
+}
+
+/// Identity of a testcase across reruns: `(classname, name, file, line)` as reported.
+fn testcase_key<'a>(case: &Node<'a, '_>) -> [Option<&'a str>; 4] {
+    ["classname", "name", "file", "line"].map(|attr| case.attribute(attr))
+}
+
+fn non_empty_attribute(node: &Node<'_, '_>, name: &str) -> Option<String> {
+    node.attribute(name)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
 fn testcase_name(case: &Node<'_, '_>) -> String {
-    let class = case.attribute("classname");
-    let name = case.attribute("name");
+    // Synthetic collection-error testcases may carry an empty name.
+    let class = case.attribute("classname").filter(|c| !c.is_empty());
+    let name = case.attribute("name").filter(|n| !n.is_empty());
 
     match (class, name) {
         (Some(class), Some(name)) => format!("{class}.{name}"),
@@ -97,6 +572,13 @@ fn pick_message(node: &Node<'_, '_>, default: &str) -> String {
     default.to_string()
 }
 
+fn node_body(node: &Node<'_, '_>) -> Option<String> {
+    node.text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
 fn derive_location(case: &Node<'_, '_>, body: Option<&str>) -> (Option<PathBuf>, Option<usize>) {
     let file_attr = case.attribute("file").map(PathBuf::from);
     let line_attr = case.attribute("line").and_then(|s| s.parse::<usize>().ok());
@@ -155,6 +637,241 @@ fn emit_annotation(
     println!("{}", build_annotation(level, file, line, message, cwd));
 }
 
+/// Checkstyle XML with one `<file>` per derived path (relative to `cwd`, in order of first
+/// appearance) and findings without a file under a synthetic `(unknown)` one.
+fn render_checkstyle(findings: &[Finding], cwd: &Path) -> String {
+    let mut files: Vec<(String, Vec<&Finding>)> = Vec::new();
+    for finding in findings {
+        let name = report_path(finding, cwd);
+        match files.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, group)) => group.push(finding),
+            None => files.push((name, vec![finding])),
+        }
+    }
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<checkstyle version=\"4.3\">\n");
+    for (name, group) in files {
+        out.push_str(&format!("  <file name=\"{}\">\n", escape_xml(&name)));
+        for finding in group {
+            let line = finding
+                .line
+                .map(|line| format!(" line=\"{line}\""))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "    <error{line} severity=\"{}\" message=\"{}\" source=\"pytest\"/>\n",
+                finding.level,
+                escape_xml(&finding.text())
+            ));
+        }
+        out.push_str("  </file>\n");
+    }
+    out.push_str("</checkstyle>\n");
+    out
+}
+
+/// GitLab Code Quality report: one issue per finding, fingerprinted on the test name
+/// (`classname.name`) and file so the same failure keeps its identity across pipelines, plus
+/// its index among the findings sharing both, since GitLab drops issues with equal
+/// fingerprints.
+fn render_gitlab(findings: &[Finding], cwd: &Path) -> Result<String> {
+    let mut seen: HashMap<(String, String), usize> = HashMap::new();
+    let issues: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|finding| {
+            let path = report_path(finding, cwd);
+            let index = seen
+                .entry((finding.test.clone(), path.clone()))
+                .or_default();
+            let key = index.to_string();
+            *index += 1;
+            let severity = if finding.level == "error" {
+                "major"
+            } else {
+                "minor"
+            };
+            serde_json::json!({
+                "description": finding.text(),
+                "check_name": "pytest",
+                "fingerprint": fingerprint(&[&finding.test, &path, &key]),
+                "severity": severity,
+                "location": {
+                    "path": path,
+                    "lines": { "begin": finding.line.unwrap_or(1) },
+                },
+            })
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&issues)?)
+}
+
+/// Findings as a minimal SARIF 2.1.0 log: one run whose `results` carry the classname as
+/// `ruleId` and, when the file is known, a location relative to `cwd`.
+fn sarif_log(findings: &[Finding], cwd: &Path) -> serde_json::Value {
+    let results: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|finding| {
+            let mut result = serde_json::json!({
+                "ruleId": finding.classname.as_deref().unwrap_or(&finding.test),
+                "level": finding.level,
+                "message": { "text": finding.message },
+            });
+            if finding.file.is_some() {
+                let mut location = serde_json::json!({
+                    "artifactLocation": { "uri": report_path(finding, cwd).replace('\\', "/") },
+                });
+                if let Some(line) = finding.line {
+                    location["region"] = serde_json::json!({ "startLine": line });
+                }
+                result["locations"] = serde_json::json!([{ "physicalLocation": location }]);
+            }
+            result
+        })
+        .collect();
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "testdiff",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// Findings as a JSON document; `file` is relative to `cwd` and `null` when unknown.
+fn findings_json(findings: &[Finding], cwd: &Path) -> serde_json::Value {
+    let items = findings
+        .iter()
+        .map(|finding| {
+            serde_json::json!({
+                "name": finding.name,
+                "classname": finding.classname,
+                "file": finding.file.as_ref().map(|_| report_path(finding, cwd)),
+                "line": finding.line,
+                "kind": finding.kind,
+                "message": finding.message,
+            })
+        })
+        .collect();
+    let mut fields = serde_json::Map::new();
+    fields.insert("findings".to_string(), serde_json::Value::Array(items));
+    crate::json::document(fields)
+}
+
+/// Where a finding is reported: its file relative to `cwd`, or `(unknown)`.
+fn report_path(finding: &Finding, cwd: &Path) -> String {
+    match &finding.file {
+        Some(file) => diff_paths(file, cwd)
+            .unwrap_or_else(|| file.to_path_buf())
+            .display()
+            .to_string(),
+        None => "(unknown)".to_string(),
+    }
+}
+
+/// 64-bit FNV-1a of the NUL-joined `parts`, as hex. Unlike std's hasher it is fixed across
+/// Rust releases, so fingerprints stay stable between testdiff builds.
+fn fingerprint(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (i, part) in parts.iter().enumerate() {
+        let sep: &[u8] = if i == 0 { b"" } else { b"\0" };
+        for &byte in sep.iter().chain(part.as_bytes()) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+fn write_summary(markdown: &str) -> Result<()> {
+    let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") else {
+        print!("{markdown}");
+        return Ok(());
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", Path::new(&path).display()))?;
+    file.write_all(markdown.as_bytes())
+        .with_context(|| format!("Failed to write {}", Path::new(&path).display()))
+}
+
+/// Markdown job summary: a table of failures and errors (test, file, line, message) ending
+/// in a total row, then one collapsed `<details>` block per failure with the short message
+/// as its `<summary>` and the traceback in a fenced code block.
+fn render_summary(findings: &[Finding], cwd: &Path) -> String {
+    let failures: Vec<&Finding> = findings.iter().filter(|f| f.level == "error").collect();
+    let mut out = format!("### Test failures ({})\n\n", failures.len());
+    out.push_str("| Test | File | Line | Message |\n| --- | --- | --- | --- |\n");
+    for finding in &failures {
+        let file = finding
+            .file
+            .as_ref()
+            .map(|_| report_path(finding, cwd))
+            .unwrap_or_default();
+        let line = finding.line.map(|l| l.to_string()).unwrap_or_default();
+        out.push_str(&format!(
+            "| {} | {} | {line} | {} |\n",
+            table_cell(&finding.test),
+            table_cell(&file),
+            // Attribute messages may span lines; a table row cannot.
+            table_cell(finding.message.lines().next().unwrap_or_default())
+        ));
+    }
+    out.push_str(&format!(
+        "| **Total** | | | {} failed |\n\n",
+        failures.len()
+    ));
+
+    for finding in failures {
+        out.push_str(&format!(
+            "<details><summary>{}</summary>\n\n",
+            escape_html(&finding.text())
+        ));
+        match &finding.body {
+            Some(body) => {
+                // A fence longer than any backtick run in the body cannot be closed early.
+                let mut fence = "```".to_string();
+                while body.contains(&fence) {
+                    fence.push('`');
+                }
+                out.push_str(&format!("{fence}\n{}\n{fence}\n", escape_html(body)));
+            }
+            None => out.push_str("No traceback recorded.\n"),
+        }
+        out.push_str("\n</details>\n\n");
+    }
+    out
+}
+
+/// HTML-escaped text safe inside a Markdown table cell (`|` would end the cell).
+fn table_cell(value: &str) -> String {
+    escape_html(value).replace('|', "\\|")
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
 fn escape_for_github(message: &str) -> String {
     message
         .replace('%', "%25")
@@ -207,6 +924,180 @@ mod tests {
         assert_eq!(line, Some(22));
     }
 
+    #[test]
+    fn xfail_is_quiet_and_xpass_warns() {
+        let xml = r#"<testsuite>
+<testcase classname="t" name="test_skip" file="tests/test_x.py" line="1"><skipped type="pytest.skip" message="no db">tests/test_x.py:1: no db</skipped></testcase>
+<testcase classname="t" name="test_xfail" file="tests/test_x.py" line="5"><skipped type="pytest.xfail" message="known bug"/></testcase>
+<testcase classname="t" name="test_xpass" file="tests/test_x.py" line="9"><skipped message="xfail-marked test passes unexpectedly"/></testcase>
+</testsuite>"#;
+        let doc = Document::parse(xml).unwrap();
+        let summarize = |include_skipped| {
+            report_findings(&doc, include_skipped, None)
+                .into_iter()
+                .map(|f| (f.name.unwrap(), f.level, f.kind, f.message))
+                .collect::<Vec<_>>()
+        };
+
+        let xpass = (
+            "test_xpass".to_string(),
+            "warning",
+            "xpass",
+            "unexpectedly passed".to_string(),
+        );
+        assert_eq!(summarize(false), vec![xpass.clone()]);
+        assert_eq!(
+            summarize(true),
+            vec![
+                (
+                    "test_skip".to_string(),
+                    "warning",
+                    "skipped",
+                    "no db".to_string()
+                ),
+                xpass
+            ]
+        );
+        assert!(previous_failures(&doc).classnames.is_empty());
+    }
+
+    #[test]
+    fn previous_failures_skip_passing_and_skipped_tests() {
+        let xml = r#"<testsuite>
+<testcase classname="tests.test_a" name="test_ok"/>
+<testcase classname="tests.test_b.TestB" name="test_bad" file="tests/test_b.py"><failure message="boom"/></testcase>
+<testcase classname="tests.test_c" name="test_skip"><skipped message="later"/></testcase>
+</testsuite>"#;
+
+        let previous = previous_failures(&Document::parse(xml).unwrap());
+
+        assert_eq!(previous.files, vec![PathBuf::from("tests/test_b.py")]);
+        assert_eq!(previous.classnames, vec!["tests.test_b.TestB".to_string()]);
+        assert!(previous.contains("tests/test_b.py", "tests.test_b"));
+        assert!(!previous.contains("tests/test_c.py", "tests.test_c"));
+    }
+
+    #[test]
+    fn durations_sum_testcase_times_per_class_and_file() {
+        let xml = r#"<testsuites><testsuite>
+<testcase classname="tests.test_a" name="test_one" time="1.5"/>
+<testcase classname="tests.test_a" name="test_two" time="0.5"/>
+<testcase classname="tests.test_b.TestB" name="test_x" file="tests/test_b.py" time="3"/>
+<testcase classname="tests.test_c" name="test_untimed"/>
+</testsuite></testsuites>"#;
+
+        let durations = durations(&Document::parse(xml).unwrap());
+
+        assert_eq!(
+            durations.seconds("tests/test_a.py", "tests.test_a"),
+            Some(2.0)
+        );
+        assert_eq!(
+            durations.seconds("tests/test_b.py", "tests.test_b"),
+            Some(3.0)
+        );
+        assert_eq!(durations.seconds("tests/test_c.py", "tests.test_c"), None);
+    }
+
+    #[test]
+    fn slowest_table_sorts_descending_and_totals() {
+        let xml = r#"<testsuite>
+<testcase classname="tests.test_a" name="test_fast" time="0.25"/>
+<testcase classname="tests.test_a" name="test_slow" time="12.5"/>
+<testcase classname="tests.test_b" name="test_untimed"/>
+<testcase classname="tests.test_b" name="test_mid" time="1"/>
+</testsuite>"#;
+
+        let timings = testcase_timings(&Document::parse(xml).unwrap());
+
+        assert_eq!(
+            render_slowest(timings, 2),
+            "   time  test
+12.500s  tests.test_a.test_slow
+ 1.000s  tests.test_b.test_mid
+13.750s  total (4 testcases)
+Note: 1 testcases have no `time` attribute; counted as 0s
+"
+        );
+    }
+
+    #[test]
+    fn collects_every_failure_child() {
+        let xml = r#"<testsuite><testcase classname="pkg.test" name="test_it"><failure message="first"/><failure message="second"/><error message="teardown"/></testcase></testsuite>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let case = doc
+            .descendants()
+            .find(|n| n.has_tag_name("testcase"))
+            .unwrap();
+        let messages: Vec<String> = children_named(&case, &["failure", "error"])
+            .iter()
+            .map(|child| pick_message(child, "Test failed"))
+            .collect();
+
+        assert_eq!(messages, vec!["first", "second", "teardown"]);
+    }
+
+    #[test]
+    fn annotates_suite_level_errors() {
+        let xml = r#"<testsuites><testsuite name="pytest" errors="1"><error message="ImportError while importing test module"><![CDATA[Traceback:
+  File "/repo/tests/test_broken.py", line 3, in <module>
+    import missing]]></error></testsuite></testsuites>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let cwd = PathBuf::from("/repo");
+        let out: Vec<String> = suite_error_findings(&doc, None)
+            .iter()
+            .map(|f| build_annotation(f.level, f.file.as_deref(), f.line, &f.text(), &cwd))
+            .collect();
+        assert_eq!(
+            out,
+            vec![
+                "::error file=tests/test_broken.py,line=3::pytest: ImportError while importing test module"
+            ]
+        );
+    }
+
+    #[test]
+    fn only_files_skips_cases_outside_the_set() {
+        let filter = FileFilter {
+            files: vec![PathBuf::from("tests/test_a.py")],
+        };
+        let xml = r#"<testsuite><testcase name="test_a" file="/repo/tests/test_a.py"><failure/></testcase><testcase name="test_b" file="tests/test_b.py"><failure/></testcase><testcase name="test_c"><failure/></testcase></testsuite>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let kept: Vec<&str> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("testcase"))
+            .filter(|case| {
+                let (file, _) = derive_location(case, None);
+                FileFilter::allows(Some(&filter), file.as_deref())
+            })
+            .filter_map(|case| case.attribute("name"))
+            .collect();
+
+        assert_eq!(kept, vec!["test_a"]);
+        assert!(FileFilter::allows(None, None));
+    }
+
+    #[test]
+    fn validate_accepts_junit_report() {
+        let xml = r#"<testsuites><testsuite name="pytest"><testcase classname="pkg.test" name="test_it"/></testsuite></testsuites>"#;
+        let doc = Document::parse(xml).unwrap();
+        assert!(validate_report(&doc).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_unrelated_xml() {
+        let xml = r#"<project><dependency name="foo"/></project>"#;
+        let doc = Document::parse(xml).unwrap();
+        let err = validate_report(&doc).unwrap_err().to_string();
+        assert!(err.contains("`project`"), "unexpected error: {err}");
+
+        let empty = Document::parse(r#"<testsuite name="pytest"></testsuite>"#).unwrap();
+        assert!(validate_report(&empty).is_err());
+    }
+
     #[test]
     fn escape_for_github_replaces_specials() {
         let input = "line1%\r\nline2";
@@ -239,4 +1130,205 @@ line2
         let out = build_annotation("error", Some(&file), Some(12), msg, &cwd);
         assert_eq!(out, "::error file=tests/test_example.py,line=12::fail!");
     }
+
+    #[test]
+    fn checkstyle_groups_findings_by_file() {
+        let xml = r#"<testsuite><testcase classname="t" name="test_a" file="/repo/tests/test_a.py" line="3"><failure message="a &lt;1&gt;"/></testcase><testcase classname="t" name="test_b" file="/repo/tests/test_b.py" line="7"><error message="b"/></testcase><testcase classname="t" name="test_a2" file="/repo/tests/test_a.py" line="9"><failure message="a2"/></testcase><testcase name="test_c"><failure message="c"/></testcase></testsuite>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let findings = case_findings(&doc, false, None);
+        let out = render_checkstyle(&findings, &PathBuf::from("/repo"));
+
+        assert_eq!(
+            out,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<checkstyle version="4.3">
+  <file name="tests/test_a.py">
+    <error line="3" severity="error" message="t.test_a: a &lt;1&gt;" source="pytest"/>
+    <error line="9" severity="error" message="t.test_a2: a2" source="pytest"/>
+  </file>
+  <file name="tests/test_b.py">
+    <error line="7" severity="error" message="t.test_b: b" source="pytest"/>
+  </file>
+  <file name="(unknown)">
+    <error severity="error" message="test_c: c" source="pytest"/>
+  </file>
+</checkstyle>
+"#
+        );
+    }
+
+    #[test]
+    fn gitlab_report_fingerprints_test_and_file() {
+        let xml = r#"<testsuite><testcase classname="t" name="test_a" file="/repo/tests/test_a.py" line="3"><failure message="a"/></testcase><testcase classname="t" name="test_b"><skipped message="later"/></testcase><testcase classname="t" name="test_a" file="/repo/tests/test_a.py" line="9"><error message="b"/></testcase></testsuite>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let findings = case_findings(&doc, true, None);
+        let report: serde_json::Value =
+            serde_json::from_str(&render_gitlab(&findings, &PathBuf::from("/repo")).unwrap())
+                .unwrap();
+
+        assert_eq!(report[0]["description"], "t.test_a: a");
+        assert_eq!(report[0]["severity"], "major");
+        assert_eq!(report[0]["location"]["path"], "tests/test_a.py");
+        assert_eq!(report[0]["location"]["lines"]["begin"], 3);
+        assert_eq!(report[1]["severity"], "minor");
+        assert_eq!(report[1]["location"]["path"], "(unknown)");
+        assert_eq!(
+            report[0]["fingerprint"],
+            fingerprint(&["t.test_a", "tests/test_a.py", "0"])
+        );
+        assert_ne!(report[0]["fingerprint"], report[1]["fingerprint"]);
+        // A second finding for the same test and file still gets its own fingerprint.
+        assert_eq!(
+            report[2]["fingerprint"],
+            fingerprint(&["t.test_a", "tests/test_a.py", "1"])
+        );
+        // Fixed function of its input, so it is stable across runs and builds.
+        assert_eq!(fingerprint(&["a"]), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn sarif_log_has_one_result_per_finding() {
+        let xml = r#"<testsuite><testcase classname="tests.test_a" name="test_a" file="/repo/tests/test_a.py" line="3"><failure message="boom"/></testcase><testcase classname="tests.test_b" name="test_b"><skipped message="later"/></testcase></testsuite>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let findings = case_findings(&doc, true, None);
+        let log = sarif_log(&findings, &PathBuf::from("/repo"));
+
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "testdiff");
+        assert!(run["tool"]["driver"]["version"].is_string());
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+
+        assert_eq!(results[0]["ruleId"], "tests.test_a");
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[0]["message"]["text"], "boom");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "tests/test_a.py");
+        assert_eq!(location["region"]["startLine"], 3);
+
+        assert_eq!(results[1]["ruleId"], "tests.test_b");
+        assert_eq!(results[1]["level"], "warning");
+        assert!(results[1].get("locations").is_none());
+    }
+
+    #[test]
+    fn rerun_testcases_keep_their_last_outcome() {
+        let xml = r#"<testsuites><testsuite name="run1"><testcase classname="t" name="test_flaky" file="tests/test_f.py" line="3"><failure message="flaked"/></testcase><testcase classname="t" name="test_broken"><error message="first"/></testcase></testsuite><testsuite name="run2"><testcase classname="t" name="test_flaky" file="tests/test_f.py" line="3"/><testcase classname="t" name="test_broken"><failure message="second"/></testcase></testsuite></testsuites>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let findings = case_findings(&doc, false, None);
+        let texts: Vec<_> = findings.iter().map(Finding::text).collect();
+        assert_eq!(texts, vec!["t.test_broken: second"]);
+    }
+
+    #[test]
+    fn failures_in_only_some_runs_are_flaky() {
+        let run = |a: &str, b: &str| {
+            format!(
+                r#"<testsuite><testcase classname="t" name="test_a" file="tests/test_a.py" line="3">{a}</testcase><testcase classname="t" name="test_b">{b}</testcase><testcase classname="t" name="test_ok"/></testsuite>"#
+            )
+        };
+        let fail = |msg: &str| format!(r#"<failure message="{msg}"/>"#);
+        let xmls = [
+            run(&fail("a1"), &fail("b1")),
+            run("", &fail("b2")),
+            run(&fail("a3"), &fail("b3")),
+        ];
+        let docs: Vec<_> = xmls.iter().map(|x| Document::parse(x).unwrap()).collect();
+
+        let findings = merge_runs(&docs, false, None);
+        let summary: Vec<_> = findings.iter().map(|f| (f.level, f.text())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "warning",
+                    "t.test_a: flaky (failed in 2 of 3 runs): a3".to_string()
+                ),
+                ("error", "t.test_b: b3".to_string()),
+            ]
+        );
+
+        // A single report is unaffected.
+        assert_eq!(
+            merge_runs(&docs[..1], false, None),
+            report_findings(&docs[0], false, None)
+        );
+    }
+
+    #[test]
+    fn json_lists_findings_with_their_kind() {
+        let xml = r#"<testsuites><testsuite name="suite"><error message="ImportError: nope"/><testcase classname="t" name="test_a" file="/repo/tests/test_a.py" line="3"><failure message="a"/></testcase><testcase classname="t" name="test_b"><error message="b"/></testcase><testcase classname="t" name="test_c"><skipped message="later"/></testcase></testsuite></testsuites>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let cwd = PathBuf::from("/repo");
+        let without_skips = findings_json(&case_findings(&doc, false, None), &cwd);
+        assert_eq!(without_skips["findings"].as_array().unwrap().len(), 2);
+
+        let mut findings = case_findings(&doc, true, None);
+        findings.extend(suite_error_findings(&doc, None));
+        let out = findings_json(&findings, &cwd);
+        assert_eq!(out["schemaVersion"], 1);
+        assert_eq!(
+            out["findings"],
+            serde_json::json!([
+                {"name": "test_a", "classname": "t", "file": "tests/test_a.py", "line": 3, "kind": "failure", "message": "a"},
+                {"name": "test_b", "classname": "t", "file": null, "line": null, "kind": "error", "message": "b"},
+                {"name": "test_c", "classname": "t", "file": null, "line": null, "kind": "skipped", "message": "later"},
+                {"name": "suite", "classname": null, "file": null, "line": null, "kind": "error", "message": "ImportError: nope"},
+            ])
+        );
+    }
+
+    #[test]
+    fn summary_table_keeps_the_first_message_line() {
+        let xml = r#"<testsuite><testcase classname="t" name="test_a"><failure message="assert x == y&#10;  where x = 1"/></testcase></testsuite>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let findings = case_findings(&doc, true, None);
+        let summary = render_summary(&findings, &PathBuf::from("/repo"));
+        assert!(summary.contains("| t.test_a |  |  | assert x == y |\n| **Total** |"));
+    }
+
+    #[test]
+    fn summary_collapses_tracebacks_into_details() {
+        let xml = r#"<testsuite><testcase classname="t" name="test_a"><failure message="assert 1 &lt; 0"><![CDATA[Traceback:
+  File "tests/test_a.py", line 3, in <module>
+    assert 1 < 0]]></failure></testcase><testcase classname="t" name="test_b"><error message="boom"/></testcase><testcase classname="t" name="test_c"><skipped message="later"/></testcase></testsuite>"#;
+
+        let doc = Document::parse(xml).unwrap();
+        let findings = case_findings(&doc, true, None);
+        assert_eq!(
+            render_summary(&findings, &PathBuf::from("/repo")),
+            "### Test failures (2)
+
+| Test | File | Line | Message |
+| --- | --- | --- | --- |
+| t.test_a | tests/test_a.py | 3 | assert 1 &lt; 0 |
+| t.test_b |  |  | boom |
+| **Total** | | | 2 failed |
+
+<details><summary>t.test_a: assert 1 &lt; 0</summary>
+
+```
+Traceback:
+  File \"tests/test_a.py\", line 3, in &lt;module&gt;
+    assert 1 &lt; 0
+```
+
+</details>
+
+<details><summary>t.test_b: boom</summary>
+
+No traceback recorded.
+
+</details>
+
+"
+        );
+    }
 }
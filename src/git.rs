@@ -1,62 +1,226 @@
 use std::collections::BTreeSet;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::{Context, Result};
+use pathdiff::diff_paths;
 
-use crate::SelectArgs;
+use crate::{SelectArgs, diag};
+
+// The diffs behind the `--git-*` flags run through `git` subprocesses by default, or through
+// libgit2 with the `libgit2` feature; `git_toplevel`, `show_at_rev` and `commit_changed` always
+// use the `git` binary.
+#[cfg(not(feature = "libgit2"))]
+mod command;
+#[cfg(not(feature = "libgit2"))]
+use command as backend;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+#[cfg(feature = "libgit2")]
+use libgit2 as backend;
+
+/// What one diff compares, as in the `git diff` invocations behind each flag.
+#[derive(Clone, Copy)]
+enum DiffTarget<'a> {
+    /// The index against HEAD (`--git-staged`).
+    Staged,
+    /// The working tree, staged or not, against HEAD (`--git-worktree`).
+    Worktree,
+    /// A commit against HEAD (`--git-diff`, `--git-merge-base`, `--since-last-run`).
+    Range(&'a str),
+}
 
 pub fn gather_git_changed(args: &SelectArgs, cwd: &Path) -> Result<Vec<PathBuf>> {
     let mut paths = Vec::new();
 
     if args.git_staged {
-        paths.extend(run_git_name_only(
-            cwd,
-            &["diff", "--name-only", "--cached"],
-        )?)
+        paths.extend(backend::diff_changed(cwd, DiffTarget::Staged, args)?)
     }
 
     if args.git_worktree {
         // staged + unstaged vs HEAD
-        paths.extend(run_git_name_only(cwd, &["diff", "--name-only", "HEAD"])?)
+        paths.extend(backend::diff_changed(cwd, DiffTarget::Worktree, args)?)
     }
 
-    let mut diff_ref = args.git_diff.clone();
-    let merge_base = args.git_merge_base.as_deref();
-    if diff_ref.is_none() && merge_base.is_some() {
-        diff_ref = Some(merge_base.unwrap().to_string());
+    if let Some(base) = diff_base(args, cwd)? {
+        paths.extend(backend::diff_changed(cwd, DiffTarget::Range(&base), args)?)
     }
 
-    if let Some(base) = diff_ref {
-        let base = if let Some(_mb) = merge_base {
-            let mb_sha = run_git_single(cwd, &["merge-base", &base, "HEAD"])?;
-            mb_sha.trim().to_string()
-        } else {
-            base
-        };
-        paths.extend(run_git_name_only(
-            cwd,
-            &["diff", "--name-only", &format!("{base}..HEAD")],
-        )?)
+    if args.since_last_run {
+        let state = state_path(args, cwd);
+        match read_state(&state)? {
+            Some(sha) => paths.extend(backend::diff_changed(cwd, DiffTarget::Range(&sha), args)?),
+            None => {
+                if !args.quiet {
+                    diag::info(format_args!(
+                        "no previous run recorded in {}; nothing to diff.",
+                        state.display()
+                    ));
+                }
+            }
+        }
     }
 
-    let mut unique = BTreeSet::new();
-    for p in paths {
-        let path = if p.is_absolute() { p } else { cwd.join(p) };
-        unique.insert(path);
-    }
+    // Diffs name files relative to the toplevel, whichever directory git ran in.
+    let top = git_toplevel(cwd).unwrap_or_else(|| cwd.to_path_buf());
+    let unique: BTreeSet<PathBuf> = paths.iter().map(|p| join_git_path(&top, p)).collect();
     Ok(unique.into_iter().collect())
 }
 
-fn run_git_name_only(cwd: &Path, args: &[&str]) -> Result<Vec<PathBuf>> {
-    let out = run_git_single(cwd, args)?;
-    Ok(out
-        .lines()
-        .filter(|l| !l.trim().is_empty())
-        .map(PathBuf::from)
+/// A path git reported, joined onto `dir` (the toplevel) once (absolute paths are kept) with `.` components
+/// such as a leading `./` dropped. Names are otherwise taken byte for byte: whitespace around
+/// or inside them is part of the file name.
+fn join_git_path(dir: &Path, path: &Path) -> PathBuf {
+    let path: PathBuf = path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    if path.is_absolute() {
+        path
+    } else {
+        dir.join(path)
+    }
+}
+
+/// Resolve the ref that `--git-diff`/`--git-merge-base` compare against, if any. With
+/// `--git-merge-base` or `--git-three-dot` that is the merge-base with HEAD, so diffing from
+/// it gives `git diff <ref>...HEAD`.
+pub fn diff_base(args: &SelectArgs, cwd: &Path) -> Result<Option<String>> {
+    if args.git_merge_base.is_none() && !args.git_three_dot {
+        return Ok(args.git_diff.clone());
+    }
+    // An explicit `--git-diff` ref is what the merge-base is taken against.
+    let Some(refs) = args.git_diff.as_deref().or(args.git_merge_base.as_deref()) else {
+        return Ok(None);
+    };
+    let base = nearest_merge_base(cwd, refs, args.quiet)?;
+    diag::debug(format_args!("diffing from merge-base {base}"));
+    Ok(Some(base))
+}
+
+/// The merge-base with HEAD of each comma-separated ref in `refs`, keeping the one fewest
+/// commits behind HEAD: in a fork, that is the upstream branch HEAD was actually cut from.
+/// Refs that do not resolve (or share no history with HEAD) are skipped.
+fn nearest_merge_base(cwd: &Path, refs: &str, quiet: bool) -> Result<String> {
+    let mut nearest: Option<(usize, String)> = None;
+    let mut skipped = Vec::new();
+    for candidate in refs.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let Ok(base) = backend::merge_base(cwd, candidate) else {
+            skipped.push(candidate);
+            continue;
+        };
+        let behind = backend::commits_since(cwd, &base)?;
+        if nearest.as_ref().is_none_or(|(best, _)| behind < *best) {
+            nearest = Some((behind, base));
+        }
+    }
+    let Some((_, base)) = nearest else {
+        anyhow::bail!(
+            "None of the --git-merge-base refs resolve to a commit sharing history with HEAD: {refs}"
+        );
+    };
+    if !quiet {
+        for candidate in skipped {
+            diag::warning(format_args!(
+                "--git-merge-base ref `{candidate}` does not resolve; skipping it"
+            ));
+        }
+    }
+    Ok(base)
+}
+
+/// The repository toplevel containing `cwd`, or `None` outside a git work tree.
+pub fn git_toplevel(cwd: &Path) -> Option<PathBuf> {
+    let out = run_git_single(cwd, &["rev-parse", "--show-toplevel"]).ok()?;
+    let top = out.trim();
+    (!top.is_empty()).then(|| PathBuf::from(top))
+}
+
+/// Contents of `path` at `rev`, or `None` if the file does not exist there.
+pub fn show_at_rev(cwd: &Path, rev: &str, path: &Path) -> Option<String> {
+    let rel = diff_paths(path, cwd).unwrap_or_else(|| path.to_path_buf());
+    let spec = format!("{rev}:./{}", rel.display());
+    run_git_single(cwd, &["show", &spec]).ok()
+}
+
+/// Files touched by `commit` relative to its first parent (everything, for a root commit),
+/// as absolute paths under the repository toplevel.
+pub fn commit_changed(cwd: &Path, commit: &str) -> Result<Vec<PathBuf>> {
+    let top = git_toplevel(cwd).unwrap_or_else(|| cwd.to_path_buf());
+    let out = run_git_bytes(
+        cwd,
+        &[
+            "diff-tree",
+            "--no-commit-id",
+            "--name-only",
+            "-z",
+            "-r",
+            "--root",
+            commit,
+        ],
+    )?;
+    Ok(parse_nul_paths(&out)
+        .iter()
+        .map(|p| join_git_path(&top, p))
         .collect())
 }
 
+pub const DEFAULT_STATE_FILE: &str = ".testdiff-state";
+
+pub fn state_path(args: &SelectArgs, cwd: &Path) -> PathBuf {
+    let path = args
+        .state_file
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_FILE));
+    if path.is_absolute() {
+        path
+    } else {
+        cwd.join(path)
+    }
+}
+
+fn read_state(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read state file {}", path.display()))?;
+    let sha = contents.trim();
+    Ok((!sha.is_empty()).then(|| sha.to_string()))
+}
+
+/// Record HEAD as the last-run commit for `--since-last-run`.
+pub fn record_state(path: &Path, cwd: &Path) -> Result<()> {
+    let head = backend::head_sha(cwd)?;
+    std::fs::write(path, format!("{head}\n"))
+        .with_context(|| format!("Failed to write state file {}", path.display()))
+}
+
+/// Paths from NUL-separated `--name-only -z` output.
+fn parse_nul_paths(out: &[u8]) -> Vec<PathBuf> {
+    nul_fields(out).map(path_from_bytes).collect()
+}
+
+fn nul_fields(out: &[u8]) -> impl Iterator<Item = &[u8]> {
+    out.split(|&b| b == 0).filter(|field| !field.is_empty())
+}
+
+/// Git paths are bytes; keep them exact on Unix instead of round-tripping through UTF-8.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
 fn run_git_single(cwd: &Path, args: &[&str]) -> Result<String> {
+    Ok(String::from_utf8_lossy(&run_git_bytes(cwd, args)?).into_owned())
+}
+
+fn run_git_bytes(cwd: &Path, args: &[&str]) -> Result<Vec<u8>> {
     let output = std::process::Command::new("git")
         .args(args)
         .current_dir(cwd)
@@ -72,5 +236,374 @@ fn run_git_single(cwd: &Path, args: &[&str]) -> Result<String> {
         );
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+    use clap::Parser;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=testdiff",
+                "-c",
+                "user.email=testdiff@example.com",
+            ])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn commit_file(dir: &Path, name: &str, contents: &str) {
+        fs::write(dir.join(name), contents).unwrap();
+        git(dir, &["add", name]);
+        git(dir, &["commit", "-q", "-m", name]);
+    }
+
+    fn select_args(argv: &[&str]) -> SelectArgs {
+        let mut full = vec!["testdiff"];
+        full.extend_from_slice(argv);
+        Cli::try_parse_from(full).unwrap().select
+    }
+
+    #[test]
+    fn git_paths_join_once_without_dot_segments() {
+        let cwd = Path::new("/repo");
+        assert_eq!(
+            join_git_path(cwd, Path::new("./pkg/a.py")),
+            PathBuf::from("/repo/pkg/a.py")
+        );
+        assert_eq!(
+            join_git_path(cwd, Path::new("pkg/./with space.py")),
+            PathBuf::from("/repo/pkg/with space.py")
+        );
+        assert_eq!(
+            join_git_path(cwd, Path::new(" padded .py ")),
+            PathBuf::from("/repo/ padded .py ")
+        );
+        assert_eq!(
+            join_git_path(cwd, Path::new("/elsewhere/b.py")),
+            PathBuf::from("/elsewhere/b.py")
+        );
+    }
+
+    // Windows does not allow file names ending in a space.
+    #[cfg(unix)]
+    #[test]
+    fn surrounding_spaces_in_names_are_kept() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, " padded .py ", "x = 1\n");
+        fs::write(repo.join(" padded .py "), "x = 2\n").unwrap();
+
+        let changed = gather_git_changed(&select_args(&["--git-worktree"]), &repo).unwrap();
+        assert_eq!(changed, vec![repo.join(" padded .py ")]);
+        assert!(changed[0].exists());
+    }
+
+    #[test]
+    fn parse_nul_paths_splits_on_nul_only() {
+        let out = b"pkg/foo.py\0tests/test_foo.py\0\0";
+        assert_eq!(
+            parse_nul_paths(out),
+            vec![
+                PathBuf::from("pkg/foo.py"),
+                PathBuf::from("tests/test_foo.py")
+            ]
+        );
+    }
+
+    #[test]
+    fn renamed_module_still_selects_its_importers() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::create_dir_all(repo.join("pkg")).unwrap();
+        fs::create_dir_all(repo.join("tests")).unwrap();
+        commit_file(&repo, "pkg/__init__.py", "");
+        commit_file(&repo, "pkg/foo.py", "def helper():\n    return 1\n");
+        commit_file(&repo, "tests/test_foo.py", "from pkg import foo\n");
+
+        git(&repo, &["mv", "pkg/foo.py", "pkg/bar.py"]);
+        git(&repo, &["commit", "-q", "-m", "rename"]);
+
+        let changed = gather_git_changed(&select_args(&["--git-diff", "HEAD~1"]), &repo).unwrap();
+        assert_eq!(
+            changed,
+            vec![repo.join("pkg/bar.py"), repo.join("pkg/foo.py")]
+        );
+
+        let root = camino::Utf8PathBuf::from_path_buf(repo.clone()).unwrap();
+        let changed: Vec<_> = changed
+            .into_iter()
+            .map(|p| camino::Utf8PathBuf::from_path_buf(p).unwrap())
+            .collect();
+        let impacted = testdiff::ProjectIndex::build(&root)
+            .unwrap()
+            .impacted_tests(&changed, None, None, true, false)
+            .unwrap();
+        let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(names, vec!["tests/test_foo.py"]);
+    }
+
+    #[test]
+    fn non_ascii_paths_are_not_quoted() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::create_dir_all(repo.join("tests")).unwrap();
+        commit_file(&repo, "tests/tëst_ünïcode.py", "x = 1\n");
+        commit_file(&repo, "with space.py", "y = 1\n");
+
+        fs::write(repo.join("tests/tëst_ünïcode.py"), "x = 2\n").unwrap();
+        fs::write(repo.join("with space.py"), "y = 2\n").unwrap();
+
+        let changed = gather_git_changed(&select_args(&["--git-worktree"]), &repo).unwrap();
+        assert_eq!(
+            changed,
+            vec![
+                repo.join("tests/tëst_ünïcode.py"),
+                repo.join("with space.py")
+            ]
+        );
+        assert!(changed.iter().all(|p| p.exists()));
+
+        let args = select_args(&["--git-worktree", "--git-ignore-whitespace"]);
+        assert_eq!(gather_git_changed(&args, &repo).unwrap(), changed);
+    }
+
+    #[test]
+    fn pathspec_limits_diffed_paths() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::create_dir_all(repo.join("services/api")).unwrap();
+        fs::create_dir_all(repo.join("services/web")).unwrap();
+        commit_file(&repo, "services/api/app.py", "a = 1\n");
+        commit_file(&repo, "services/web/app.py", "w = 1\n");
+        commit_file(&repo, "root.py", "r = 1\n");
+
+        fs::write(repo.join("services/api/app.py"), "a = 2\n").unwrap();
+        fs::write(repo.join("services/web/app.py"), "w = 2\n").unwrap();
+        fs::write(repo.join("root.py"), "r = 2\n").unwrap();
+
+        let all = gather_git_changed(&select_args(&["--git-worktree"]), &repo).unwrap();
+        assert_eq!(all.len(), 3);
+
+        let scoped = gather_git_changed(
+            &select_args(&[
+                "--git-worktree",
+                "--git-pathspec",
+                "services/api",
+                "--git-pathspec",
+                "*.md",
+            ]),
+            &repo,
+        )
+        .unwrap();
+        assert_eq!(scoped, vec![repo.join("services/api/app.py")]);
+    }
+
+    #[test]
+    fn diff_filter_omits_deleted_files() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, "kept.py", "k = 1\n");
+        commit_file(&repo, "gone.py", "g = 1\n");
+
+        fs::write(repo.join("kept.py"), "k = 2\n").unwrap();
+        fs::write(repo.join("new.py"), "n = 1\n").unwrap();
+        git(&repo, &["add", "new.py"]);
+        fs::remove_file(repo.join("gone.py")).unwrap();
+
+        let all = gather_git_changed(&select_args(&["--git-worktree"]), &repo).unwrap();
+        assert_eq!(all.len(), 3);
+
+        let filtered = gather_git_changed(
+            &select_args(&["--git-worktree", "--git-diff-filter", "AM"]),
+            &repo,
+        )
+        .unwrap();
+        assert_eq!(filtered, vec![repo.join("kept.py"), repo.join("new.py")]);
+    }
+
+    #[test]
+    fn ignore_whitespace_drops_reindented_files() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, "fmt.py", "def f():\n    return 1\n");
+        commit_file(&repo, "real.py", "x = 1\n");
+
+        fs::write(repo.join("fmt.py"), "def f():\n  return  1   \n").unwrap();
+        fs::write(repo.join("real.py"), "x = 2\n").unwrap();
+
+        let all = gather_git_changed(&select_args(&["--git-worktree"]), &repo).unwrap();
+        assert_eq!(all, vec![repo.join("fmt.py"), repo.join("real.py")]);
+
+        let args = select_args(&["--git-worktree", "--git-ignore-whitespace"]);
+        let changed = gather_git_changed(&args, &repo).unwrap();
+        assert_eq!(changed, vec![repo.join("real.py")]);
+    }
+
+    #[test]
+    fn ignore_whitespace_keeps_empty_added_and_deleted_files() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, "gone.py", "");
+        commit_file(&repo, "fmt.py", "x = 1\n");
+
+        fs::remove_file(repo.join("gone.py")).unwrap();
+        fs::create_dir_all(repo.join("pkg")).unwrap();
+        fs::write(repo.join("pkg/__init__.py"), "").unwrap();
+        git(&repo, &["add", "pkg/__init__.py"]);
+        fs::write(repo.join("fmt.py"), "x  =  1\n").unwrap();
+
+        let args = select_args(&["--git-worktree", "--git-ignore-whitespace"]);
+        let changed = gather_git_changed(&args, &repo).unwrap();
+        assert_eq!(
+            changed,
+            vec![repo.join("gone.py"), repo.join("pkg/__init__.py")]
+        );
+    }
+
+    #[test]
+    fn paths_from_a_subdirectory_are_joined_onto_the_toplevel() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::create_dir_all(repo.join("pkg")).unwrap();
+        commit_file(&repo, "pkg/core.py", "c = 1\n");
+        commit_file(&repo, "root.py", "r = 1\n");
+        fs::write(repo.join("pkg/core.py"), "c = 2\n").unwrap();
+        fs::write(repo.join("root.py"), "r = 2\n").unwrap();
+
+        let args = select_args(&["--git-worktree"]);
+        let changed = gather_git_changed(&args, &repo.join("pkg")).unwrap();
+        assert_eq!(
+            changed,
+            vec![repo.join("pkg/core.py"), repo.join("root.py")]
+        );
+    }
+
+    #[test]
+    fn ignore_whitespace_applies_the_diff_filter() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, "gone.py", "g = 1\n");
+        commit_file(&repo, "edited.py", "e = 1\n");
+
+        fs::remove_file(repo.join("gone.py")).unwrap();
+        fs::write(repo.join("edited.py"), "e = 2\n").unwrap();
+        fs::write(repo.join("added.py"), "a = 1\n").unwrap();
+        git(&repo, &["add", "added.py"]);
+
+        // Both backends: `git diff` and libgit2 filter the whitespace-insensitive diff alike.
+        let select = |filter: &str| {
+            let args = select_args(&[
+                "--git-worktree",
+                "--git-ignore-whitespace",
+                "--git-diff-filter",
+                filter,
+            ]);
+            gather_git_changed(&args, &repo).unwrap()
+        };
+        assert_eq!(
+            select("d"),
+            vec![repo.join("added.py"), repo.join("edited.py")]
+        );
+        assert_eq!(select("D"), vec![repo.join("gone.py")]);
+        assert_eq!(
+            select("AM"),
+            vec![repo.join("added.py"), repo.join("edited.py")]
+        );
+    }
+
+    #[test]
+    fn merge_base_picks_the_nearest_of_several_refs() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, "old.py", "o = 1\n");
+        git(&repo, &["branch", "release"]);
+        commit_file(&repo, "upstream.py", "u = 1\n");
+        git(&repo, &["branch", "upstream"]);
+        commit_file(&repo, "mine.py", "m = 1\n");
+
+        let args = select_args(&["--git-merge-base", "release, upstream"]);
+        let changed = gather_git_changed(&args, &repo).unwrap();
+        assert_eq!(changed, vec![repo.join("mine.py")]);
+
+        let args = select_args(&["--git-merge-base", "missing,release", "--quiet"]);
+        let changed = gather_git_changed(&args, &repo).unwrap();
+        assert_eq!(
+            changed,
+            vec![repo.join("mine.py"), repo.join("upstream.py")]
+        );
+
+        let args = select_args(&["--git-merge-base", "missing,gone"]);
+        let err = gather_git_changed(&args, &repo).unwrap_err().to_string();
+        assert!(err.contains("missing,gone"), "{err}");
+    }
+
+    #[test]
+    fn three_dot_ignores_commits_on_the_moved_base_branch() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, "base.py", "b = 1\n");
+        git(&repo, &["branch", "target"]);
+        commit_file(&repo, "feature.py", "f = 1\n");
+
+        // The target branch moves on after the feature branch diverged.
+        git(&repo, &["checkout", "-q", "target"]);
+        commit_file(&repo, "upstream.py", "u = 1\n");
+        git(&repo, &["checkout", "-q", "-"]);
+
+        let two_dot = gather_git_changed(&select_args(&["--git-diff", "target"]), &repo).unwrap();
+        assert_eq!(
+            two_dot,
+            vec![repo.join("feature.py"), repo.join("upstream.py")]
+        );
+
+        let args = select_args(&["--git-diff", "target", "--git-three-dot"]);
+        let three_dot = gather_git_changed(&args, &repo).unwrap();
+        assert_eq!(three_dot, vec![repo.join("feature.py")]);
+    }
+
+    #[test]
+    fn since_last_run_diffs_from_recorded_commit() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        commit_file(&repo, "a.py", "a = 1\n");
+
+        let args = select_args(&["--since-last-run", "--quiet"]);
+        let state = state_path(&args, &repo);
+
+        // First run: nothing recorded yet.
+        assert!(gather_git_changed(&args, &repo).unwrap().is_empty());
+        record_state(&state, &repo).unwrap();
+
+        commit_file(&repo, "b.py", "b = 1\n");
+
+        // Second run only sees what changed since the first.
+        let changed = gather_git_changed(&args, &repo).unwrap();
+        assert_eq!(changed, vec![repo.join("b.py")]);
+
+        record_state(&state, &repo).unwrap();
+        assert!(gather_git_changed(&args, &repo).unwrap().is_empty());
+    }
 }
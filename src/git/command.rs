@@ -0,0 +1,173 @@
+//! The diffs behind the `--git-*` flags, computed by running `git`.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use super::{DiffTarget, nul_fields, path_from_bytes, run_git_bytes, run_git_single};
+use crate::SelectArgs;
+
+/// `git diff --name-status --find-renames -z <target> [-- <pathspec>...]`; pathspecs let git do
+/// the scoping. A rename yields both its old path (seeded like a deleted file) and its new one.
+/// `-z` keeps paths raw: without it git quotes non-ASCII names with octal escapes.
+///
+/// `--git-diff-filter` is handed to git as-is, so a rename only shows up when `R` passes it.
+///
+/// With `--git-ignore-whitespace` this also runs `git diff -w --numstat`: `--name-status`
+/// lists a file whatever `-w` says, while numstat reports `0 0` for whitespace-only edits.
+pub(super) fn diff_changed(
+    cwd: &Path,
+    target: DiffTarget<'_>,
+    select: &SelectArgs,
+) -> Result<Vec<PathBuf>> {
+    if select.git_ignore_whitespace {
+        // Without rename detection numstat prints plain paths instead of `{old => new}`.
+        let numstat = run_diff(
+            cwd,
+            target,
+            select,
+            &["--numstat", "--ignore-all-space", "--no-renames"],
+        )?;
+        let statuses = run_diff(cwd, target, select, &["--name-status", "--no-renames"])?;
+        Ok(whitespace_changed(&numstat, &statuses))
+    } else {
+        let out = run_diff(cwd, target, select, &["--name-status", "--find-renames"])?;
+        Ok(parse_name_status(&out))
+    }
+}
+
+/// `git diff <mode...> -z [--diff-filter=...] <target> [-- <pathspec>...]`.
+fn run_diff(
+    cwd: &Path,
+    target: DiffTarget<'_>,
+    select: &SelectArgs,
+    mode: &[&str],
+) -> Result<Vec<u8>> {
+    let range;
+    let mut args = vec!["diff"];
+    args.extend_from_slice(mode);
+    args.push("-z");
+    let filter = select
+        .git_diff_filter
+        .as_ref()
+        .map(|f| format!("--diff-filter={f}"));
+    args.extend(filter.as_deref());
+    match target {
+        DiffTarget::Staged => args.push("--cached"),
+        DiffTarget::Worktree => args.push("HEAD"),
+        DiffTarget::Range(from) => {
+            range = format!("{from}..HEAD");
+            args.push(&range);
+        }
+    }
+    if !select.git_pathspec.is_empty() {
+        args.push("--");
+        args.extend(select.git_pathspec.iter().map(String::as_str));
+    }
+    run_git_bytes(cwd, &args)
+}
+
+/// The paths of `git diff --name-status --no-renames -z` output (`statuses`, in git's order)
+/// that `git diff -w --numstat -z` output (`numstat`) shows with a changed line, plus every
+/// added or deleted file: numstat has `0 0` for those when they are empty.
+fn whitespace_changed(numstat: &[u8], statuses: &[u8]) -> Vec<PathBuf> {
+    let edited: HashSet<PathBuf> = parse_numstat_changed(numstat).into_iter().collect();
+    let mut paths = Vec::new();
+    let mut fields = nul_fields(statuses);
+    while let (Some(status), Some(path)) = (fields.next(), fields.next()) {
+        let path = path_from_bytes(path);
+        if matches!(status.first(), Some(b'A' | b'D')) || edited.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// Paths from `git diff --numstat -z` output (`added\tdeleted\tpath` records) with at least
+/// one added or deleted line (binary files, reported as `-`, always count).
+fn parse_numstat_changed(out: &[u8]) -> Vec<PathBuf> {
+    nul_fields(out)
+        .filter_map(|record| {
+            let mut cols = record.splitn(3, |&b| b == b'\t');
+            let added = cols.next()?;
+            let deleted = cols.next()?;
+            let path = cols.next().filter(|p| !p.is_empty())?;
+            (added != b"0" || deleted != b"0").then(|| path_from_bytes(path))
+        })
+        .collect()
+}
+
+/// Paths from `git diff --name-status -z` output, a flat list of NUL-terminated fields: a
+/// status followed by its path, or by two paths for renames (`R<score>`: both sides count)
+/// and copies (`C<score>`: only the copy does).
+fn parse_name_status(out: &[u8]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut fields = nul_fields(out);
+    while let Some(status) = fields.next() {
+        match status.first() {
+            Some(b'R') => paths.extend(fields.by_ref().take(2).map(path_from_bytes)),
+            Some(b'C') => paths.extend(fields.by_ref().take(2).skip(1).map(path_from_bytes)),
+            _ => paths.extend(fields.next().map(path_from_bytes)),
+        }
+    }
+    paths
+}
+
+/// `git merge-base <base> HEAD`.
+pub(super) fn merge_base(cwd: &Path, base: &str) -> Result<String> {
+    Ok(run_git_single(cwd, &["merge-base", base, "HEAD"])?
+        .trim()
+        .to_string())
+}
+
+/// `git rev-list --count <base>..HEAD`.
+pub(super) fn commits_since(cwd: &Path, base: &str) -> Result<usize> {
+    let out = run_git_single(cwd, &["rev-list", "--count", &format!("{base}..HEAD")])?;
+    out.trim()
+        .parse()
+        .with_context(|| format!("Unexpected git rev-list output: {out}"))
+}
+
+/// `git rev-parse HEAD`.
+pub(super) fn head_sha(cwd: &Path) -> Result<String> {
+    Ok(run_git_single(cwd, &["rev-parse", "HEAD"])?
+        .trim()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_name_status_keeps_both_sides_of_renames() {
+        let out =
+            b"M\0pkg/a.py\0R087\0pkg/foo.py\0pkg/bar.py\0C100\0pkg/x.py\0pkg/y.py\0D\0gone.py\0";
+        assert_eq!(
+            parse_name_status(out),
+            [
+                "pkg/a.py",
+                "pkg/foo.py",
+                "pkg/bar.py",
+                "pkg/y.py",
+                "gone.py"
+            ]
+            .map(PathBuf::from)
+            .to_vec()
+        );
+    }
+
+    #[test]
+    fn whitespace_mode_keeps_empty_added_and_deleted_files() {
+        let numstat = b"0\t0\tfmt.py\x000\t0\tgone.py\x00\
+            1\t1\treal.py\x000\t0\tpkg/__init__.py\x00";
+        let statuses = b"M\0fmt.py\0D\0gone.py\0A\0pkg/__init__.py\0M\0real.py\0";
+        assert_eq!(
+            whitespace_changed(numstat, statuses),
+            ["gone.py", "pkg/__init__.py", "real.py"]
+                .map(PathBuf::from)
+                .to_vec()
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! The diffs behind the `--git-*` flags, computed in-process with libgit2 so no `git` binary
+//! is needed. Each function matches its counterpart in `command.rs`: paths come back relative
+//! to the repository toplevel, pathspecs are relative to `cwd`, renames count on both sides.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use git2::{Delta, Diff, DiffFindOptions, DiffOptions, Oid, Patch, Repository};
+
+use super::{DiffTarget, path_from_bytes};
+use crate::SelectArgs;
+
+pub(super) fn diff_changed(
+    cwd: &Path,
+    target: DiffTarget<'_>,
+    select: &SelectArgs,
+) -> Result<Vec<PathBuf>> {
+    let repo = open(cwd)?;
+    let mut opts = DiffOptions::new();
+    let prefix = cwd_prefix(&repo, cwd);
+    for spec in &select.git_pathspec {
+        opts.pathspec(format!("{prefix}{spec}"));
+    }
+    opts.ignore_whitespace(select.git_ignore_whitespace);
+
+    let mut diff = match target {
+        // Before the first commit the index is compared with the empty tree, like git does.
+        DiffTarget::Staged => {
+            let head = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+            repo.diff_tree_to_index(head.as_ref(), None, Some(&mut opts))?
+        }
+        DiffTarget::Worktree => {
+            let head = repo.head()?.peel_to_tree()?;
+            repo.diff_tree_to_workdir_with_index(Some(&head), Some(&mut opts))?
+        }
+        DiffTarget::Range(from) => {
+            let old = repo
+                .revparse_single(from)
+                .with_context(|| format!("Unknown git revision `{from}`"))?
+                .peel_to_tree()?;
+            let new = repo.head()?.peel_to_tree()?;
+            repo.diff_tree_to_tree(Some(&old), Some(&new), Some(&mut opts))?
+        }
+    };
+    let filter = select.git_diff_filter.as_deref();
+    if select.git_ignore_whitespace {
+        return whitespace_changed(&diff, filter);
+    }
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+    let mut paths = Vec::new();
+    for delta in diff.deltas() {
+        let Some(status) = status_letter(delta.status()) else {
+            continue;
+        };
+        if filter.is_some_and(|f| !passes_filter(f, status)) {
+            continue;
+        }
+        let old = delta.old_file().path_bytes().map(path_from_bytes);
+        let new = delta.new_file().path_bytes().map(path_from_bytes);
+        match status {
+            'R' => paths.extend(old.into_iter().chain(new)),
+            'D' => paths.extend(old),
+            _ => paths.extend(new),
+        }
+    }
+    Ok(paths)
+}
+
+/// Files from a whitespace-insensitive diff with at least one added or deleted line; binary
+/// files always count, as in `git diff -w --numstat`, and so do added and deleted files, even
+/// empty ones. Renames are not detected, so `filter` sees their two sides as `D` and `A`.
+fn whitespace_changed(diff: &Diff<'_>, filter: Option<&str>) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for idx in 0..diff.deltas().len() {
+        let Some(patch) = Patch::from_diff(diff, idx)? else {
+            continue;
+        };
+        let delta = patch.delta();
+        let Some(status) = status_letter(delta.status()) else {
+            continue;
+        };
+        if filter.is_some_and(|f| !passes_filter(f, status)) {
+            continue;
+        }
+        let (_, added, deleted) = patch.line_stats()?;
+        let added_or_deleted = matches!(delta.status(), Delta::Added | Delta::Deleted);
+        if added + deleted == 0 && !delta.flags().is_binary() && !added_or_deleted {
+            continue;
+        }
+        let file = match delta.status() {
+            Delta::Deleted => delta.old_file(),
+            _ => delta.new_file(),
+        };
+        paths.extend(file.path_bytes().map(path_from_bytes));
+    }
+    Ok(paths)
+}
+
+pub(super) fn merge_base(cwd: &Path, base: &str) -> Result<String> {
+    let repo = open(cwd)?;
+    let base_id = repo
+        .revparse_single(base)
+        .with_context(|| format!("Unknown git revision `{base}`"))?
+        .peel_to_commit()?
+        .id();
+    let head_id = repo.head()?.peel_to_commit()?.id();
+    let merge_base = repo
+        .merge_base(base_id, head_id)
+        .with_context(|| format!("No merge base between `{base}` and HEAD"))?;
+    Ok(merge_base.to_string())
+}
+
+pub(super) fn commits_since(cwd: &Path, base: &str) -> Result<usize> {
+    let repo = open(cwd)?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.hide(Oid::from_str(base)?)?;
+    Ok(walk.collect::<Result<Vec<_>, _>>()?.len())
+}
+
+pub(super) fn head_sha(cwd: &Path) -> Result<String> {
+    Ok(open(cwd)?.head()?.peel_to_commit()?.id().to_string())
+}
+
+fn open(cwd: &Path) -> Result<Repository> {
+    Repository::discover(cwd)
+        .with_context(|| format!("Failed to open a git repository at {}", cwd.display()))
+}
+
+/// `cwd` relative to the work tree, with a trailing `/` (empty at the toplevel): git resolves
+/// pathspecs against the current directory, libgit2 against the toplevel.
+fn cwd_prefix(repo: &Repository, cwd: &Path) -> String {
+    let Some(workdir) = repo.workdir().and_then(|w| w.canonicalize().ok()) else {
+        return String::new();
+    };
+    let Ok(cwd) = cwd.canonicalize() else {
+        return String::new();
+    };
+    let Ok(rel) = cwd.strip_prefix(&workdir) else {
+        return String::new();
+    };
+    rel.components()
+        .map(|c| format!("{}/", c.as_os_str().to_string_lossy()))
+        .collect()
+}
+
+/// The `git diff --name-status` letter for a delta; `None` for entries git does not list.
+fn status_letter(status: Delta) -> Option<char> {
+    Some(match status {
+        Delta::Added => 'A',
+        Delta::Deleted => 'D',
+        Delta::Modified => 'M',
+        Delta::Renamed => 'R',
+        Delta::Copied => 'C',
+        Delta::Typechange => 'T',
+        Delta::Conflicted => 'U',
+        Delta::Unreadable => 'X',
+        Delta::Unmodified | Delta::Ignored | Delta::Untracked => return None,
+    })
+}
+
+/// Whether `status` passes a `--diff-filter` value: uppercase letters select statuses and
+/// lowercase letters exclude them; with only exclusions every other status passes.
+fn passes_filter(filter: &str, status: char) -> bool {
+    let mut selects = filter.chars().filter(char::is_ascii_uppercase).peekable();
+    let selected = selects.peek().is_none() || selects.any(|c| c == status);
+    let excluded = filter
+        .chars()
+        .filter(char::is_ascii_lowercase)
+        .any(|c| c.to_ascii_uppercase() == status);
+    selected && !excluded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+    use clap::Parser;
+    use git2::{IndexAddOption, Signature};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn select_args(argv: &[&str]) -> SelectArgs {
+        let mut full = vec!["testdiff"];
+        full.extend_from_slice(argv);
+        Cli::try_parse_from(full).unwrap().select
+    }
+
+    fn stage_all(repo: &Repository) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+    }
+
+    fn commit_all(repo: &Repository, message: &str) {
+        stage_all(repo);
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let sig = Signature::now("testdiff", "testdiff@example.com").unwrap();
+        let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap();
+    }
+
+    fn init() -> (tempfile::TempDir, PathBuf, Repository) {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        (tmp, dir, repo)
+    }
+
+    #[test]
+    fn staged_worktree_and_range_diffs() {
+        let (_tmp, dir, repo) = init();
+        fs::write(dir.join("a.py"), "a = 1\n").unwrap();
+        fs::write(dir.join("b.py"), "b = 1\n").unwrap();
+        commit_all(&repo, "first");
+
+        fs::write(dir.join("c.py"), "c = 1\n").unwrap();
+        stage_all(&repo);
+        fs::write(dir.join("a.py"), "a = 2\n").unwrap();
+
+        let args = select_args(&[]);
+        let staged = diff_changed(&dir, DiffTarget::Staged, &args).unwrap();
+        assert_eq!(staged, vec![PathBuf::from("c.py")]);
+        let worktree = diff_changed(&dir, DiffTarget::Worktree, &args).unwrap();
+        assert_eq!(worktree, vec![PathBuf::from("a.py"), PathBuf::from("c.py")]);
+
+        commit_all(&repo, "second");
+        let range = diff_changed(&dir, DiffTarget::Range("HEAD~1"), &args).unwrap();
+        assert_eq!(range, vec![PathBuf::from("a.py"), PathBuf::from("c.py")]);
+    }
+
+    #[test]
+    fn renames_report_both_sides() {
+        let (_tmp, dir, repo) = init();
+        fs::create_dir_all(dir.join("pkg")).unwrap();
+        fs::write(dir.join("pkg/foo.py"), "def helper():\n    return 1\n").unwrap();
+        commit_all(&repo, "first");
+
+        fs::rename(dir.join("pkg/foo.py"), dir.join("pkg/bar.py")).unwrap();
+        commit_all(&repo, "rename");
+
+        let args = select_args(&[]);
+        let changed = diff_changed(&dir, DiffTarget::Range("HEAD~1"), &args).unwrap();
+        assert_eq!(
+            changed,
+            vec![PathBuf::from("pkg/foo.py"), PathBuf::from("pkg/bar.py")]
+        );
+
+        let args = select_args(&["--git-diff-filter", "r"]);
+        let changed = diff_changed(&dir, DiffTarget::Range("HEAD~1"), &args).unwrap();
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn pathspecs_are_relative_to_cwd() {
+        let (_tmp, dir, repo) = init();
+        fs::create_dir_all(dir.join("services/api")).unwrap();
+        fs::write(dir.join("services/api/app.py"), "a = 1\n").unwrap();
+        fs::write(dir.join("root.py"), "r = 1\n").unwrap();
+        commit_all(&repo, "first");
+        fs::write(dir.join("services/api/app.py"), "a = 2\n").unwrap();
+        fs::write(dir.join("root.py"), "r = 2\n").unwrap();
+
+        let args = select_args(&["--git-pathspec", "api"]);
+        let changed = diff_changed(&dir.join("services"), DiffTarget::Worktree, &args).unwrap();
+        assert_eq!(changed, vec![PathBuf::from("services/api/app.py")]);
+    }
+
+    #[test]
+    fn merge_base_finds_the_fork_point() {
+        let (_tmp, dir, repo) = init();
+        fs::write(dir.join("a.py"), "a = 1\n").unwrap();
+        commit_all(&repo, "first");
+        let fork = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("side", &fork, false).unwrap();
+
+        fs::write(dir.join("a.py"), "a = 2\n").unwrap();
+        commit_all(&repo, "second");
+
+        assert_eq!(merge_base(&dir, "side").unwrap(), fork.id().to_string());
+        assert_ne!(head_sha(&dir).unwrap(), fork.id().to_string());
+    }
+
+    #[test]
+    fn diff_filter_letters_select_and_exclude() {
+        assert!(passes_filter("AM", 'M'));
+        assert!(!passes_filter("AM", 'D'));
+        assert!(passes_filter("d", 'M'));
+        assert!(!passes_filter("d", 'D'));
+        assert!(!passes_filter("Ad", 'M'));
+    }
+}
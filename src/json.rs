@@ -0,0 +1,15 @@
+use serde_json::{Map, Value};
+
+/// Version of the JSON documents `testdiff` prints, reported as their top-level
+/// `schemaVersion`. It is shared by every JSON output (selection, dry-run, format, graph, ...)
+/// and bumped whenever any of them changes shape incompatibly: a field removed, renamed or
+/// retyped. Adding a field does not bump it, so consumers should ignore unknown keys.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Wrap `fields` into a top-level JSON document carrying `schemaVersion`.
+pub fn document(fields: Map<String, Value>) -> Value {
+    let mut doc = Map::new();
+    doc.insert("schemaVersion".to_string(), Value::from(SCHEMA_VERSION));
+    doc.extend(fields);
+    Value::Object(doc)
+}
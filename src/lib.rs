@@ -0,0 +1,86 @@
+//! Test selection for Python projects: index a project's import graph and rank the tests a
+//! set of changed files can affect. The `testdiff` binary is a command-line front end to
+//! [`select_impacted`].
+//!
+//! ```no_run
+//! use camino::{Utf8Path, Utf8PathBuf};
+//! use testdiff::{SelectOptions, select_impacted};
+//!
+//! let root = Utf8Path::new("/work/project");
+//! let changed = vec![Utf8PathBuf::from("/work/project/pkg/core.py")];
+//! for test in select_impacted(root, &changed, &SelectOptions::default())? {
+//!     println!("{}", test.path);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+
+// `diag` and `paths` are compiled into the `testdiff` binary as well; the library only uses
+// part of `diag`.
+#[allow(dead_code)]
+mod diag;
+mod paths;
+mod priority;
+mod project;
+
+pub use diag::{Level, set_max_level};
+pub use priority::{PreviousFailures, Priority};
+pub use project::{
+    ImpactedOptions, IndexOptions, ModuleInfo, ProjectIndex, TestResult, Warning, WarningSummary,
+};
+
+/// Settings for [`select_impacted`]: how to index the project and how to select from it.
+#[derive(Clone, Debug, Default)]
+pub struct SelectOptions {
+    pub index: IndexOptions,
+    pub impacted: ImpactedOptions,
+}
+
+/// Index the project at `root` and return the tests impacted by `changed` (absolute paths
+/// under `root`), most relevant first. This is the selection the CLI prints; files that are
+/// neither Python sources nor inside `options.impacted.fixture_dirs` select nothing.
+///
+/// Unlike the CLI, this does not read the `[tool.testdiff]` table of the project's
+/// `pyproject.toml`: `options` is used as given. Warnings (unless `options.impacted.quiet`)
+/// go to stderr, up to the level set with [`set_max_level`].
+pub fn select_impacted(
+    root: &Utf8Path,
+    changed: &[Utf8PathBuf],
+    options: &SelectOptions,
+) -> Result<Vec<TestResult>> {
+    let index = ProjectIndex::build_with(root, &options.index)?;
+    index.impacted_tests_with(changed, &options.impacted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn select_impacted_returns_importing_tests() {
+        let tmp = tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("pkg/__init__.py"), "").unwrap();
+        fs::write(root.join("pkg/core.py"), "").unwrap();
+        fs::write(root.join("tests/test_core.py"), "from pkg import core\n").unwrap();
+        fs::write(root.join("tests/test_other.py"), "").unwrap();
+
+        let changed = vec![root.join("pkg/core.py"), root.join("README.md")];
+        let options = SelectOptions {
+            impacted: ImpactedOptions {
+                quiet: true,
+                ..ImpactedOptions::default()
+            },
+            ..SelectOptions::default()
+        };
+        let selected = select_impacted(&root, &changed, &options).unwrap();
+        let paths: Vec<_> = selected.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(paths, vec!["tests/test_core.py"]);
+    }
+}
@@ -1,18 +1,38 @@
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf, Prefix};
 
-use anyhow::Result;
-use camino::Utf8PathBuf;
-use clap::{Args as ClapArgs, Parser, Subcommand};
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args as ClapArgs, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use shellexpand;
 
+mod collect;
+mod config;
+mod cosmetic;
+mod diag;
 mod format;
 mod git;
-mod priority;
-mod project;
+mod json;
+mod paths;
+mod query;
+mod run;
+mod shard;
+mod template;
+mod watch;
 
+use collect::{PytestPatterns, test_items};
+use config::FileConfig;
 use format::FormatArgs;
-use git::gather_git_changed;
-use project::{ProjectIndex, TestResult};
+use git::{diff_base, gather_git_changed, git_toplevel, record_state, show_at_rev, state_path};
+use paths::{canonicalize_lenient, fixture_scope, is_python_file};
+use query::{DiffSelectionArgs, GraphArgs, ImpactArgs, PathOfArgs};
+use shard::Shard;
+use template::Template;
+use testdiff::{
+    ImpactedOptions, IndexOptions, PreviousFailures, ProjectIndex, TestResult, WarningSummary,
+};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -33,24 +53,60 @@ pub struct Cli {
 pub enum Command {
     /// Format a pytest JUnit XML report as GitHub Actions annotations
     Format(FormatArgs),
+    /// Print the file path of one or more indexed modules
+    PathOf(PathOfArgs),
+    /// Print every module that transitively imports a module, with distances
+    #[command(visible_alias = "deps")]
+    Impact(ImpactArgs),
+    /// Compare the tests selected for two commits' changes
+    DiffSelection(DiffSelectionArgs),
+    /// Print the reverse-dependency import graph (Graphviz DOT)
+    Graph(GraphArgs),
 }
 
-#[derive(ClapArgs, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One line per test (see --template)
+    Text,
+    /// Tab-separated `seed<TAB>test_module<TAB>distance<TAB>path` rows, with a header
+    Tsv,
+    /// `{"schemaVersion", "root", "tests": [{"path", "distance", "filename_match", "rank"}]}`
+    Json,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
 #[command(next_help_heading = "Selection options")]
 pub struct SelectArgs {
-    /// Comma-separated list of changed files (relative to CWD or absolute)
+    /// Comma-separated list of changed files (relative to CWD or absolute); `@file` reads one path per line from `file`
     #[arg(long, value_delimiter = ',')]
     changed: Vec<String>,
 
+    /// Read changed files, one per line, from FILE (`-` for stdin) and treat them like --changed.
+    /// An empty list means nothing changed
+    #[arg(long, value_name = "FILE")]
+    changed_from: Option<String>,
+
+    /// Comma-separated files to treat as changed for a what-if selection; they need not exist
+    /// (missing files are mapped to modules like deleted ones). Same `@file` syntax as --changed
+    #[arg(long, value_delimiter = ',')]
+    assume_changed: Vec<String>,
+
     /// Diff against this Git ref (e.g., origin/main) to populate changed files
     #[arg(long)]
     git_diff: Option<String>,
 
+    /// Diff --git-diff's ref with three-dot semantics (`git diff REF...HEAD`): only what HEAD
+    /// changed since it forked from REF, not commits that landed on REF afterwards. The plain
+    /// two-dot diff compares against REF's current tip
+    #[arg(long, requires = "git_diff")]
+    git_three_dot: bool,
+
     /// Use staged changes (`git diff --cached`) to populate changed files
     #[arg(long)]
     git_staged: bool,
 
-    /// Use merge-base with this ref (implies git-diff if not provided explicitly)
+    /// Use merge-base with this ref (implies git-diff if not provided explicitly). A
+    /// comma-separated list uses whichever ref's merge-base is nearest to HEAD
     #[arg(long)]
     git_merge_base: Option<String>,
 
@@ -58,116 +114,758 @@ pub struct SelectArgs {
     #[arg(long)]
     git_worktree: bool,
 
-    /// Project root to scan (defaults to current directory)
+    /// Only consider git changes matching this pathspec, e.g. `services/api/` (repeatable;
+    /// passed to `git diff` after `--`)
+    #[arg(long, value_name = "SPEC")]
+    git_pathspec: Vec<String>,
+
+    /// Ignore whitespace when diffing (`git diff -w`), so files that were only reindented or
+    /// reformatted are not reported as changed
+    #[arg(long)]
+    git_ignore_whitespace: bool,
+
+    /// Only consider git changes with these statuses, e.g. `AM` or `d` to drop deletions
+    /// (passed to `git diff --diff-filter`)
+    #[arg(long, value_name = "FILTER")]
+    git_diff_filter: Option<String>,
+
+    /// Diff from the commit recorded in the state file by the previous successful run to HEAD
+    #[arg(long)]
+    since_last_run: bool,
+
+    /// State file holding the last-run commit (default: `.testdiff-state`). Updated after each
+    /// successful run when given or when `--since-last-run` is used.
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Translate changed files with extension EXT into their generated Python sibling, e.g.
+    /// `proto=_pb2.py` maps `foo.proto` to `foo_pb2.py` (repeatable)
+    #[arg(long, value_name = "EXT=SUFFIX")]
+    map_ext: Vec<String>,
+
+    /// Comma-separated directory names (e.g. `fixtures`); a changed data file inside one selects
+    /// every test under the directory containing it
+    #[arg(long, value_delimiter = ',')]
+    fixture_dirs: Vec<String>,
+
+    /// Comma-separated root-relative directories whose `conftest.py` is suite-wide: a change
+    /// there selects every test, like a change to the root `conftest.py`
+    #[arg(long, value_delimiter = ',', value_name = "DIR")]
+    global_conftest_dirs: Vec<String>,
+
+    /// Comma-separated root-relative import roots (e.g. `src`) that module names are computed
+    /// from. Default: the `where` list of `[tool.setuptools.packages.find]`, else the import root
+    /// declared in `setup.cfg`, else `src/` if present
+    #[arg(long, value_delimiter = ',', value_name = "DIR", global = true)]
+    source_root: Vec<String>,
+
+    /// Directory name (e.g. `generated`) never indexed, on top of the built-in list such as
+    /// `.venv` and `node_modules`; matched on the basename at any depth (repeatable)
+    #[arg(long = "skip-dir", value_name = "NAME", global = true)]
+    extra_skip_dirs: Vec<String>,
+
+    /// Count imports inside `if TYPE_CHECKING:` blocks as dependencies (ignored by default,
+    /// since they only exist for type hints)
+    #[arg(long, global = true)]
+    type_checking_imports: bool,
+
+    /// Heuristic: record the files modules open by literal path (`open("tests/data.json")`,
+    /// `Path(...)`, `base / "data.json"`), so a changed data file selects the tests reading it
+    #[arg(long, global = true)]
+    data_deps: bool,
+
+    /// Match changed paths to indexed files ignoring case (automatic when the root is on a
+    /// case-insensitive filesystem)
+    #[arg(long)]
+    ignore_path_case: bool,
+
+    /// Select tests for one symbol you touched, as `module:name` (e.g. `pkg.foo:bar`): importers
+    /// of the module are kept only if their source references `name`
+    #[arg(long, value_name = "MODULE:NAME")]
+    changed_symbol: Option<String>,
+
+    /// Project root to scan (defaults to current directory). Repeat for several independent
+    /// projects: each changed file goes to the deepest root containing it
     #[arg(long)]
-    root: Option<PathBuf>,
+    root: Vec<PathBuf>,
+
+    /// Use the git toplevel (`git rev-parse --show-toplevel`) as the project root, skipping the
+    /// pyproject.toml heuristic. Falls back to the usual root detection outside a git repo.
+    #[arg(long)]
+    root_from_git: bool,
+
+    /// Abort if the root contains more than this many Python files (guards against a mistaken --root)
+    #[arg(long)]
+    max_files: Option<usize>,
 
     /// Maximum number of test files to output (most relevant first)
     #[arg(long)]
     max: Option<usize>,
 
+    /// Print only shard I of N of the selection (e.g. `2/4`), for splitting tests across
+    /// parallel CI jobs; applied after sorting and --max
+    #[arg(long, value_name = "I/N")]
+    shard: Option<Shard>,
+
+    /// JUnit XML report of a previous run whose testcase `time`s balance --shard by duration
+    /// instead of test count
+    #[arg(long, value_name = "REPORT", requires = "shard")]
+    durations: Option<PathBuf>,
+
+    /// Only output tests with at least this filename confidence: 0 = name matches a changed
+    /// module, 1 = partial match, 2 = any (composes with --max)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=2))]
+    min_filename_match: Option<u8>,
+
+    /// Only output tests whose root-relative path matches this glob, e.g. `tests/integration/**`
+    /// (repeatable; any pattern may match). Applied before --max
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Drop tests whose root-relative path matches this glob, e.g. `tests/slow/**` (repeatable;
+    /// wins over --include). Applied before --max
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// JSON file mapping root-relative test paths to recent failure counts; among otherwise
+    /// equal tests, those that failed more often are listed first
+    #[arg(long)]
+    history: Option<PathBuf>,
+
+    /// JUnit XML report of the previous run; tests that failed or errored in it are listed
+    /// first, ahead of every other ranking criterion
+    #[arg(long, value_name = "REPORT")]
+    previous_failures: Option<PathBuf>,
+
+    /// Among equally ranked tests, list the most recently modified files first (one stat per file)
+    #[arg(long)]
+    order_by_mtime: bool,
+
     /// Limit graph distance from changed modules (0 = only tests directly in changed modules). If omitted, no distance cap.
     #[arg(long)]
     distance_limit: Option<usize>,
 
+    /// Distance cap for modules guessed from deleted or unindexed files (e.g. `1` = only their
+    /// direct importers); changes to indexed files keep --distance-limit
+    #[arg(long)]
+    deleted_distance_limit: Option<usize>,
+
+    /// Only select direct importers of changed modules (plus changed tests); skips the transitive walk
+    #[arg(long)]
+    no_transitive: bool,
+
+    /// Triage mode: only seed from changed test files (and select their importers)
+    #[arg(long)]
+    changed_tests_only: bool,
+
+    /// A changed `__init__.py` only selects tests that import the package itself, instead of
+    /// every module in the package's subtree
+    #[arg(long)]
+    strict_package_init: bool,
+
     /// Dry run: print diagnostics about changed files and selection, do not output plain list
     #[arg(long)]
     dry_run: bool,
 
+    /// Output format; `tsv` lists every changed module that reaches each test
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Terminate each output entry with a NUL byte instead of a newline, for
+    /// `testdiff --print0 | xargs -0 pytest` with paths containing spaces
+    #[arg(long, conflicts_with_all = ["dry_run", "format", "k_expression"])]
+    print0: bool,
+
+    /// Print pytest node ids (`path::Class::test`) instead of file paths, honouring the
+    /// `python_classes`/`python_functions` settings from the project's pytest config
+    #[arg(long, conflicts_with = "dry_run")]
+    node_ids: bool,
+
+    /// Print a single `pytest -k` expression OR-ing the selected test functions/classes, for
+    /// `pytest -k "$(testdiff --k-expression)"`
+    #[arg(long, conflicts_with_all = ["dry_run", "node_ids"])]
+    k_expression: bool,
+
+    /// Output line template; placeholders: {path}, {distance}, {filename_match}, {module}
+    #[arg(
+        long,
+        default_value = "{path}",
+        conflicts_with_all = ["dry_run", "format", "node_ids", "k_expression", "run"]
+    )]
+    template: String,
+
+    /// Print a report of every unresolved import grouped by top-level name (first- vs third-party guess)
+    #[arg(long)]
+    explain_unresolved: bool,
+
+    /// Number of threads used to parse the project (default: available parallelism); `--jobs 1`
+    /// parses serially for reproducible debugging
+    #[arg(long)]
+    jobs: Option<NonZeroUsize>,
+
+    /// Cache parse results in DIR and re-parse only files whose mtime or size changed since
+    /// the previous run. An unreadable or outdated cache is ignored and rebuilt
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<Utf8PathBuf>,
+
+    /// Show a progress bar while indexing (only when stderr is a terminal)
+    #[arg(long)]
+    progress: bool,
+
     /// Treat any warning as an error (non-zero exit)
     #[arg(long)]
     warn_as_error: bool,
 
+    /// Print the effective settings (and where each came from) as JSON, then exit
+    #[arg(long)]
+    dump_config: bool,
+
+    /// Keep running: print the selection again after every batch of `.py` changes under the
+    /// root (stop with Ctrl-C). Most useful with --git-worktree
+    #[arg(long)]
+    watch: bool,
+
     /// Suppress warnings to stderr
     #[arg(long)]
     quiet: bool,
+
+    /// Show info messages on stderr (`-vv` adds debug messages too); --quiet wins
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Run pytest on the selected tests instead of printing them, forwarding any arguments
+    /// after `--`, and exit with pytest's exit code. Nothing is run when no tests are selected
+    #[arg(long, conflicts_with_all = ["dry_run", "print0", "k_expression", "format"])]
+    run: bool,
+
+    /// Command used by --run (default: `$TESTDIFF_PYTEST`, else `pytest`), split on whitespace,
+    /// e.g. `python -m pytest` or `uv run pytest`
+    #[arg(long, value_name = "COMMAND", requires = "run")]
+    pytest: Option<String>,
+
+    /// Extra pytest arguments for --run, given after `--`
+    #[arg(last = true, value_name = "PYTEST_ARGS", requires = "run")]
+    pytest_args: Vec<String>,
+
+    /// Heuristic: skip changed Python files whose only edits are comments, docstrings, or
+    /// whitespace. Compares each file on disk against its version at the git diff base (or HEAD).
+    #[arg(long)]
+    ignore_cosmetic: bool,
+
+    /// Maximum number of warnings printed to stderr (0 = no cap). `--warn-as-error` still counts all of them.
+    #[arg(long, default_value_t = 50)]
+    max_warnings: usize,
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+impl SelectArgs {
+    fn records_state(&self) -> bool {
+        self.since_last_run || self.state_file.is_some()
+    }
+}
+
+fn main() {
+    if let Err(err) = run_cli() {
+        diag::error(format_args!("{err:?}"));
+        std::process::exit(1);
+    }
+}
+
+fn run_cli() -> Result<()> {
+    // Keep the raw matches around: `--dump-config` reports where each value came from.
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    set_verbosity(&cli.select);
 
-    if let Some(Command::Format(args)) = cli.command {
-        return format::format_junit(&args);
+    match cli.command {
+        Some(Command::Format(args)) => return format::format_junit(&args),
+        Some(Command::PathOf(args)) => return query::path_of(&args, &cli.select),
+        Some(Command::Impact(args)) => return query::impact(&args, &cli.select),
+        Some(Command::DiffSelection(args)) => return query::diff_selection(&args, &cli.select),
+        Some(Command::Graph(args)) => return query::graph(&args, &cli.select),
+        None => {}
     }
 
     let args = cli.select;
     let cwd = std::env::current_dir()?;
-    let mut changed_abs = absolutize_changed(&args.changed, &cwd)?;
+    if args.dump_config {
+        return config::dump_config(&args, &matches, &cwd);
+    }
+    if args.watch {
+        return watch::watch(args, &cwd);
+    }
+    let state_file = args.records_state().then(|| state_path(&args, &cwd));
 
-    if changed_abs.is_empty() {
-        changed_abs = gather_git_changed(&args, &cwd)?;
-    } else if args.git_staged || args.git_diff.is_some() || args.git_merge_base.is_some() {
-        let git_paths = gather_git_changed(&args, &cwd)?;
-        changed_abs.extend(git_paths);
+    let code = run_selection(args, &cwd)?;
+    if code != 0 {
+        std::process::exit(code);
     }
 
-    // Limit the selection set to Python sources; config/shell/etc. should not trigger any tests.
-    changed_abs = filter_python_files(changed_abs);
+    // Only a successful run advances the recorded commit.
+    if let Some(path) = state_file {
+        record_state(&path, &cwd)?;
+    }
 
-    if changed_abs.is_empty() {
+    Ok(())
+}
+
+/// Show stderr messages up to the level `-v`/`--quiet` ask for. The library keeps its own
+/// level for the messages it prints during selection, so both are set.
+fn set_verbosity(args: &SelectArgs) {
+    if args.quiet {
+        diag::set_max_level(diag::Level::Error);
+        testdiff::set_max_level(testdiff::Level::Error);
+    } else {
+        // The unresolved-import report is printed at info level.
+        let verbose = if args.explain_unresolved {
+            args.verbose.max(1)
+        } else {
+            args.verbose
+        };
+        diag::set_max_level(diag::Level::from_verbosity(verbose));
+        testdiff::set_max_level(testdiff::Level::from_verbosity(verbose));
+    }
+}
+
+/// Select (and print, or with `--run` execute) the impacted tests; returns the exit code.
+fn run_selection(args: SelectArgs, cwd: &Path) -> Result<i32> {
+    // Validate before doing any work so template typos fail fast.
+    let template = Template::parse(&args.template)?;
+    let changed_abs = changed_files(&args, cwd)?;
+
+    let changed_symbol = args
+        .changed_symbol
+        .as_deref()
+        .map(parse_changed_symbol)
+        .transpose()?;
+
+    if changed_abs.is_empty() && changed_symbol.is_none() {
         if !args.quiet {
-            eprintln!("Info: no changed Python files detected; skipping.");
+            diag::info("no changed Python files detected; skipping.");
         }
-        return Ok(());
+        return Ok(0);
     }
 
-    let root = choose_root(args.root, &changed_abs, &cwd)?;
-    let changed_paths = normalize_changed(&changed_abs)?;
+    let groups = choose_roots(&explicit_roots(&args, cwd), &changed_abs, cwd, args.quiet)?;
+    for (root, files) in &groups {
+        diag::debug(format_args!("root {root}: {} changed files", files.len()));
+    }
+    let mut selections = select_per_root(&args, groups, changed_symbol)?;
+    if let Some(shard) = args.shard {
+        let durations = match &args.durations {
+            Some(path) => Some(format::load_durations(path)?),
+            None => None,
+        };
+        shard.retain(selections.iter_mut().map(|sel| &mut sel.tests), |test| {
+            durations
+                .as_ref()
+                .and_then(|d| d.seconds(&test.path, &test.module))
+        });
+    }
 
-    let project = ProjectIndex::build(&root)?;
-    let impacted = project.impacted_tests(
-        &changed_paths,
-        args.max,
-        args.distance_limit,
-        args.quiet,
-        args.warn_as_error,
-    )?;
+    if args.run {
+        let command =
+            run::pytest_command(args.pytest.as_deref(), std::env::var(run::PYTEST_ENV).ok())?;
+        let mut tests: Vec<PathBuf> = Vec::new();
+        for sel in &selections {
+            if args.node_ids {
+                let patterns = PytestPatterns::load(&sel.root)?;
+                tests.extend(
+                    sel.tests
+                        .iter()
+                        .flat_map(|res| node_ids(&sel.root, &res.path, &patterns))
+                        .map(|id| sel.root.join(id).into_std_path_buf()),
+                );
+            } else {
+                tests.extend(
+                    sel.tests
+                        .iter()
+                        .map(|res| sel.root.join(&res.path).into_std_path_buf()),
+                );
+            }
+        }
+        if tests.is_empty() && !args.quiet {
+            diag::info("no tests selected; not running pytest.");
+        }
+        return run::run_pytest(&command, cwd, &tests, &args.pytest_args);
+    }
 
-    if args.dry_run {
-        print_dry_run(&root, &changed_paths, &impacted);
+    // Paths relative to different roots can collide, so with several roots the plain outputs
+    // print them relative to the current directory (as `--run` passes them) instead.
+    let anchor = (selections.len() > 1).then_some(cwd);
+    if args.format == OutputFormat::Tsv {
+        for (i, sel) in selections.iter().enumerate() {
+            let shown = |path: &str| shown_path(&sel.root, path, anchor);
+            // One header for the whole output.
+            for line in tsv_lines(&sel.tests, shown)
+                .into_iter()
+                .skip(usize::from(i > 0))
+            {
+                println!("{line}");
+            }
+        }
+    } else if args.format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&selection_json(&selections))?
+        );
+    } else if args.dry_run {
+        for sel in &selections {
+            let patterns = PytestPatterns::load(&sel.root)?;
+            print_dry_run(&sel.root, &sel.changed, &sel.tests, &patterns);
+        }
+    } else if args.k_expression {
+        let mut expressions = Vec::new();
+        for sel in &selections {
+            let patterns = PytestPatterns::load(&sel.root)?;
+            let expression = k_expression(&sel.root, &sel.tests, &patterns);
+            if !expression.is_empty() {
+                expressions.push(expression);
+            }
+        }
+        if !expressions.is_empty() {
+            println!("{}", expressions.join(" or "));
+        }
+    } else if args.node_ids {
+        let mut ids = Vec::new();
+        for sel in &selections {
+            let patterns = PytestPatterns::load(&sel.root)?;
+            for res in &sel.tests {
+                let shown = shown_path(&sel.root, &res.path, anchor);
+                ids.extend(
+                    node_ids(&sel.root, &res.path, &patterns)
+                        .into_iter()
+                        .map(|id| format!("{shown}{}", &id[res.path.len()..])),
+                );
+            }
+        }
+        write_entries(&mut std::io::stdout().lock(), ids, args.print0)?;
     } else {
-        for res in impacted {
-            println!("{}", res.path);
+        let lines = selections.iter().flat_map(|sel| {
+            sel.tests
+                .iter()
+                .map(|res| template.render(res, &shown_path(&sel.root, &res.path, anchor)))
+        });
+        write_entries(&mut std::io::stdout().lock(), lines, args.print0)?;
+    }
+
+    Ok(0)
+}
+
+/// The tests selected under one project root; test paths are relative to `root`.
+struct RootSelection {
+    root: Utf8PathBuf,
+    changed: Vec<Utf8PathBuf>,
+    tests: Vec<TestResult>,
+}
+
+/// Run the selection for each `(root, changed files)` group, with that root's
+/// `[tool.testdiff]` settings. Groups without changed files are skipped unless
+/// `--changed-symbol` names the change. Ends with one warning summary for all the roots;
+/// `--warn-as-error` and `--max` then apply to the roots together.
+fn select_per_root(
+    args: &SelectArgs,
+    groups: Vec<(Utf8PathBuf, Vec<PathBuf>)>,
+    changed_symbol: Option<(String, String)>,
+) -> Result<Vec<RootSelection>> {
+    let history = match &args.history {
+        Some(path) => load_history(path)?,
+        None => HashMap::new(),
+    };
+    let previous_failures = match &args.previous_failures {
+        Some(path) => format::load_previous_failures(path)?,
+        None => PreviousFailures::default(),
+    };
+
+    let total_max = args.max;
+    let mut selections = Vec::new();
+    let mut summary = WarningSummary::default();
+    // Warnings from roots that treat them as errors.
+    let mut failing = 0;
+    for (root, files) in groups {
+        if files.is_empty() && changed_symbol.is_none() {
+            continue;
         }
+        let mut args = args.clone();
+        if let Some(file_config) = FileConfig::load(&root)? {
+            file_config.apply(&mut args);
+        }
+        let changed = normalize_changed(&files)?;
+        let index = ProjectIndex::build_with(&root, &index_options(&args))?;
+        let (tests, warnings) = index.impacted_tests_summarized(
+            &changed,
+            &ImpactedOptions {
+                // --max caps all the roots together, below; a root's own `max` caps just it.
+                max: if total_max.is_some() { None } else { args.max },
+                distance_limit: args.distance_limit,
+                deleted_distance_limit: args.deleted_distance_limit,
+                quiet: args.quiet,
+                warn_as_error: false,
+                max_warnings: args.max_warnings,
+                no_transitive: args.no_transitive,
+                min_filename_match: args.min_filename_match,
+                include: args.include.clone(),
+                exclude: args.exclude.clone(),
+                order_by_mtime: args.order_by_mtime,
+                global_conftest_dirs: args.global_conftest_dirs.clone(),
+                ignore_path_case: args.ignore_path_case,
+                changed_tests_only: args.changed_tests_only,
+                strict_package_init: args.strict_package_init,
+                all_seeds: args.format == OutputFormat::Tsv,
+                fixture_dirs: args.fixture_dirs.clone(),
+                explain_unresolved: args.explain_unresolved,
+                changed_symbol: changed_symbol.clone(),
+                history: history.clone(),
+                previous_failures: previous_failures.clone(),
+            },
+        )?;
+        if args.warn_as_error {
+            failing += warnings.counted();
+        }
+        summary += warnings;
+        selections.push(RootSelection {
+            root,
+            changed,
+            tests,
+        });
     }
+    if !args.quiet {
+        if let Some(line) = summary.render() {
+            diag::warning(line);
+        }
+    }
+    if failing > 0 {
+        anyhow::bail!("Warnings treated as errors ({failing} warnings)");
+    }
+    if let Some(max) = total_max {
+        truncate_across_roots(&mut selections, max);
+    }
+    Ok(selections)
+}
 
-    Ok(())
+/// Keep the `max` highest-priority tests over all `selections`. Each root's tests are already
+/// in priority order, so every root keeps a prefix of its list; ties go to the earlier root.
+fn truncate_across_roots(selections: &mut [RootSelection], max: usize) {
+    let mut ranked: Vec<_> = selections
+        .iter()
+        .enumerate()
+        .flat_map(|(i, sel)| sel.tests.iter().map(move |test| (&test.priority, i)))
+        .collect();
+    // Stable, so tests of equal priority stay in root order.
+    ranked.sort_by(|a, b| a.0.cmp(b.0));
+    let mut kept = vec![0; selections.len()];
+    for (_, i) in ranked.into_iter().take(max) {
+        kept[i] += 1;
+    }
+    for (sel, keep) in selections.iter_mut().zip(kept) {
+        sel.tests.truncate(keep);
+    }
+}
+
+/// How to index a root for `args` (with the root's `[tool.testdiff]` settings applied); the
+/// query subcommands index the same way.
+fn index_options(args: &SelectArgs) -> IndexOptions {
+    IndexOptions {
+        max_files: args.max_files,
+        progress: args.progress,
+        jobs: args.jobs,
+        source_roots: args.source_root.clone(),
+        extra_skip_dirs: args.extra_skip_dirs.clone(),
+        cache_dir: args.cache_dir.clone(),
+        type_checking_imports: args.type_checking_imports,
+        data_deps: args.data_deps,
+    }
 }
 
 fn absolutize_changed(inputs: &[String], cwd: &Path) -> Result<Vec<PathBuf>> {
     let mut paths = Vec::new();
-    for raw in inputs {
-        let expanded = shellexpand::tilde(raw).into_owned();
+    for raw in expand_argfiles(inputs, cwd)? {
+        let expanded = shellexpand::tilde(&raw).into_owned();
         let candidate = PathBuf::from(expanded);
         let path = if candidate.is_absolute() {
             candidate
         } else {
             cwd.join(candidate)
         };
-        // Prefer canonical paths; fall back to absolute on error (e.g., missing file).
-        if let Ok(real) = path.canonicalize() {
-            paths.push(real);
-        } else {
-            paths.push(path);
-        }
+        paths.push(canonicalize_lenient(path));
     }
     Ok(paths)
 }
 
-fn filter_python_files(inputs: Vec<PathBuf>) -> Vec<PathBuf> {
+/// `--changed-from`: absolute paths for the non-empty lines of `source` (`-` reads `stdin`).
+fn changed_from(source: &str, cwd: &Path, stdin: &mut dyn Read) -> Result<Vec<PathBuf>> {
+    let mut contents = String::new();
+    if source == "-" {
+        stdin
+            .read_to_string(&mut contents)
+            .context("Failed to read changed files from stdin")?;
+    } else {
+        let path = cwd.join(shellexpand::tilde(source).into_owned());
+        contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read --changed-from {}", path.display()))?;
+    }
+    let lines: Vec<String> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect();
+    absolutize_changed(&lines, cwd)
+}
+
+/// Replace `@file` entries with the non-empty lines of `file` (relative to `cwd`).
+fn expand_argfiles(inputs: &[String], cwd: &Path) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    for raw in inputs {
+        let Some(argfile) = raw.strip_prefix('@') else {
+            out.push(raw.clone());
+            continue;
+        };
+        let argfile = cwd.join(shellexpand::tilde(argfile).into_owned());
+        let contents = std::fs::read_to_string(&argfile)
+            .with_context(|| format!("Failed to read --changed argfile {}", argfile.display()))?;
+        out.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string),
+        );
+    }
+    Ok(out)
+}
+
+fn parse_ext_map(rules: &[String]) -> Result<Vec<(String, String)>> {
+    rules
+        .iter()
+        .map(|rule| {
+            let (ext, suffix) = rule
+                .split_once('=')
+                .filter(|(ext, suffix)| !ext.is_empty() && !suffix.is_empty())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Invalid --map-ext `{rule}` (expected EXT=SUFFIX)")
+                })?;
+            Ok((ext.trim_start_matches('.').to_string(), suffix.to_string()))
+        })
+        .collect()
+}
+
+/// Replace e.g. `foo.proto` by its generated `foo_pb2.py` so the generated module gets seeded.
+fn map_generated_files(inputs: Vec<PathBuf>, ext_map: &[(String, String)]) -> Vec<PathBuf> {
     inputs
         .into_iter()
-        .filter(|p| p.extension().map(|ext| ext == "py").unwrap_or(false))
+        .map(|path| {
+            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            match ext_map.iter().find(|(from, _)| from == ext) {
+                Some((_, suffix)) => path.with_file_name(format!("{stem}{suffix}")),
+                None => path,
+            }
+        })
         .collect()
 }
 
+fn load_history(path: &Path) -> Result<HashMap<String, u32>> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read history file {}", path.display()))?;
+    serde_json::from_str(&raw).with_context(|| {
+        format!(
+            "History file {} must be a JSON object of test path -> failure count",
+            path.display()
+        )
+    })
+}
+
+fn parse_changed_symbol(raw: &str) -> Result<(String, String)> {
+    match raw.split_once(':') {
+        Some((module, symbol)) if !module.is_empty() && !symbol.is_empty() => {
+            Ok((module.to_string(), symbol.to_string()))
+        }
+        _ => anyhow::bail!("Invalid --changed-symbol `{raw}` (expected MODULE:NAME)"),
+    }
+}
+
+/// The changed files to select from: `--changed`, `--changed-from` and the `--git-*` diffs,
+/// mapped to their Python sources and minus `--ignore-cosmetic` edits.
+fn changed_files(args: &SelectArgs, cwd: &Path) -> Result<Vec<PathBuf>> {
+    let mut changed_abs = absolutize_changed(&args.changed, cwd)?;
+    if let Some(source) = &args.changed_from {
+        changed_abs.extend(changed_from(source, cwd, &mut std::io::stdin().lock())?);
+    }
+
+    if changed_abs.is_empty() {
+        changed_abs = gather_git_changed(args, cwd)?;
+    } else if args.git_staged
+        || args.git_diff.is_some()
+        || args.git_merge_base.is_some()
+        || args.since_last_run
+    {
+        let git_paths = gather_git_changed(args, cwd)?;
+        changed_abs.extend(git_paths);
+    }
+
+    // Speculative files join whatever --changed/git produced; nothing about them is read.
+    changed_abs.extend(absolutize_changed(&args.assume_changed, cwd)?);
+
+    let ext_map = parse_ext_map(&args.map_ext)?;
+    changed_abs = map_generated_files(changed_abs, &ext_map);
+
+    // Data files under a configured fixtures directory select the tests around them; with
+    // --data-deps, any data file may select the modules that open it.
+    let (fixture_changes, other_changes): (Vec<PathBuf>, Vec<PathBuf>) =
+        changed_abs.into_iter().partition(|p| {
+            !is_python_file(p) && (args.data_deps || fixture_scope(p, &args.fixture_dirs).is_some())
+        });
+
+    // Limit the selection set to Python sources; config/shell/etc. should not trigger any tests.
+    changed_abs = filter_python_files(other_changes);
+    changed_abs.extend(fixture_changes);
+
+    if args.ignore_cosmetic {
+        let base = diff_base(args, cwd)?.unwrap_or_else(|| "HEAD".to_string());
+        changed_abs.retain(|path| {
+            // Fixture and data files have no comments or formatting to discount.
+            let cosmetic = is_python_file(path) && is_cosmetic_change(cwd, &base, path);
+            if cosmetic && !args.quiet {
+                diag::info(format_args!(
+                    "ignoring cosmetic-only change: {}",
+                    path.display()
+                ));
+            }
+            !cosmetic
+        });
+    }
+    Ok(changed_abs)
+}
+
+fn filter_python_files(inputs: Vec<PathBuf>) -> Vec<PathBuf> {
+    inputs.into_iter().filter(|p| is_python_file(p)).collect()
+}
+
+fn is_cosmetic_change(cwd: &Path, base: &str, path: &Path) -> bool {
+    let Ok(new) = std::fs::read_to_string(path) else {
+        return false;
+    };
+    match show_at_rev(cwd, base, path) {
+        Some(old) => cosmetic::is_cosmetic_only(&old, &new),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{choose_root, common_ancestor_dirs, filter_python_files};
+    use super::{
+        RootSelection, absolutize_changed, changed_files, changed_from, choose_root, choose_roots,
+        common_ancestor_dirs, filter_python_files, git_toplevel, k_expression, map_generated_files,
+        normalize_changed, parse_ext_map, select_per_root, selection_json, shown_path, tsv_lines,
+        write_entries,
+    };
+    use crate::collect::PytestPatterns;
     use camino::Utf8PathBuf;
+    use clap::Parser;
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
     use tempfile::tempdir;
+    use testdiff::{ImpactedOptions, ProjectIndex};
 
     #[test]
     fn filters_to_python_only() {
@@ -187,6 +885,351 @@ mod tests {
         assert_eq!(strings, vec!["foo.py", "nested/baz.py"]);
     }
 
+    #[test]
+    fn proto_change_selects_generated_module_dependents() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("repo");
+        fs::create_dir_all(root.join("protos")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("protos").join("__init__.py"), "").unwrap();
+        fs::write(
+            root.join("protos").join("user.proto"),
+            "syntax = \"proto3\";",
+        )
+        .unwrap();
+        fs::write(root.join("protos").join("user_pb2.py"), "User = object\n").unwrap();
+        fs::write(
+            root.join("tests").join("test_user.py"),
+            "from protos import user_pb2\n",
+        )
+        .unwrap();
+
+        let rules = parse_ext_map(&["proto=_pb2.py".to_string()]).unwrap();
+        let mapped = map_generated_files(vec![root.join("protos").join("user.proto")], &rules);
+        assert_eq!(mapped, vec![root.join("protos").join("user_pb2.py")]);
+
+        let utf8_root = Utf8PathBuf::from_path_buf(root.clone()).unwrap();
+        let index = ProjectIndex::build(&utf8_root).unwrap();
+        let changed = normalize_changed(&filter_python_files(mapped)).unwrap();
+        let impacted = index
+            .impacted_tests(&changed, None, None, true, false)
+            .unwrap();
+        let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(names, vec!["tests/test_user.py"]);
+
+        assert!(parse_ext_map(&["proto".to_string()]).is_err());
+    }
+
+    #[test]
+    fn tsv_lists_seed_for_every_selected_test() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("repo");
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("pkg").join("__init__.py"), "").unwrap();
+        fs::write(root.join("pkg").join("core.py"), "X = 1\n").unwrap();
+        fs::write(root.join("pkg").join("api.py"), "from pkg import core\n").unwrap();
+        fs::write(
+            root.join("tests").join("test_api.py"),
+            "from pkg import api\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("tests").join("test_core.py"),
+            "from pkg import core\n",
+        )
+        .unwrap();
+
+        let utf8_root = Utf8PathBuf::from_path_buf(root.clone()).unwrap();
+        let index = ProjectIndex::build(&utf8_root).unwrap();
+        let changed = normalize_changed(&[root.join("pkg").join("core.py")]).unwrap();
+        let impacted = index
+            .impacted_tests(&changed, None, None, true, false)
+            .unwrap();
+
+        let lines = tsv_lines(&impacted, str::to_string);
+        assert_eq!(lines[0], "seed\ttest_module\tdistance\tpath");
+        assert_eq!(lines.len(), 3);
+        for line in &lines[1..] {
+            let cols: Vec<&str> = line.split('\t').collect();
+            assert_eq!(cols.len(), 4, "bad row {line:?}");
+            assert_eq!(cols[0], "pkg.core");
+        }
+        assert!(lines.contains(&"pkg.core\ttests.test_core\t1\ttests/test_core.py".to_string()));
+        assert!(lines.contains(&"pkg.core\ttests.test_api\t2\ttests/test_api.py".to_string()));
+
+        // A test reached from two changed modules gets a row for each.
+        let changed = normalize_changed(&[
+            root.join("pkg").join("core.py"),
+            root.join("pkg").join("api.py"),
+        ])
+        .unwrap();
+        let opts = ImpactedOptions {
+            quiet: true,
+            all_seeds: true,
+            ..ImpactedOptions::default()
+        };
+        let impacted = index.impacted_tests_with(&changed, &opts).unwrap();
+        let mut rows = tsv_lines(&impacted, str::to_string).split_off(1);
+        rows.sort();
+        assert_eq!(
+            rows,
+            [
+                "pkg.api\ttests.test_api\t1\ttests/test_api.py",
+                "pkg.core\ttests.test_api\t2\ttests/test_api.py",
+                "pkg.core\ttests.test_core\t1\ttests/test_core.py",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_format_ranks_tests_in_output_order() {
+        let tmp = tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("pkg/__init__.py"), "").unwrap();
+        fs::write(root.join("pkg/core.py"), "X = 1\n").unwrap();
+        fs::write(root.join("pkg/api.py"), "from pkg import core\n").unwrap();
+        fs::write(root.join("tests/test_api.py"), "from pkg import api\n").unwrap();
+        fs::write(root.join("tests/test_core.py"), "from pkg import core\n").unwrap();
+
+        let index = ProjectIndex::build(&root).unwrap();
+        let impacted = index
+            .impacted_tests(&[root.join("pkg/core.py")], None, None, true, false)
+            .unwrap();
+
+        let selection = RootSelection {
+            root: root.clone(),
+            changed: vec![root.join("pkg/core.py")],
+            tests: impacted,
+        };
+        let doc = selection_json(&[selection]);
+        assert_eq!(doc["schemaVersion"], 1);
+        assert_eq!(doc["root"], root.as_str());
+        assert_eq!(
+            doc["tests"],
+            serde_json::json!([
+                {"path": "tests/test_core.py", "distance": 1, "filename_match": 0, "rank": 1},
+                {"path": "tests/test_api.py", "distance": 2, "filename_match": 2, "rank": 2},
+            ])
+        );
+    }
+
+    #[test]
+    fn print0_conflicts_with_other_output_modes() {
+        assert!(super::Cli::try_parse_from(["testdiff", "--print0", "--max", "3"]).is_ok());
+        assert!(super::Cli::try_parse_from(["testdiff", "--print0", "--node-ids"]).is_ok());
+        let others: [&[&str]; 4] = [
+            &["--dry-run"],
+            &["--format", "tsv"],
+            &["--format", "json"],
+            &["--k-expression"],
+        ];
+        for other in others {
+            let argv = [&["testdiff", "--print0"], other].concat();
+            let err = super::Cli::try_parse_from(argv).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn template_conflicts_with_modes_that_ignore_it() {
+        assert!(super::Cli::try_parse_from(["testdiff", "--template", "{module}"]).is_ok());
+        assert!(super::Cli::try_parse_from(["testdiff", "--format", "tsv"]).is_ok());
+        let others: [&[&str]; 6] = [
+            &["--dry-run"],
+            &["--format", "tsv"],
+            &["--format", "json"],
+            &["--node-ids"],
+            &["--k-expression"],
+            &["--run"],
+        ];
+        for other in others {
+            let argv = [&["testdiff", "--template", "{module}"], other].concat();
+            let err = super::Cli::try_parse_from(argv).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn print0_ends_entries_with_nul_bytes() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("core.py"), "").unwrap();
+        for name in ["test_a b.py", "test_c.py"] {
+            fs::write(root.join("tests").join(name), "import core\n").unwrap();
+        }
+
+        let args = super::Cli::try_parse_from(["testdiff", "--quiet", "--print0"])
+            .unwrap()
+            .select;
+        let groups = choose_roots(&[root.clone()], &[root.join("core.py")], &root, true).unwrap();
+        let selections = select_per_root(&args, groups, None).unwrap();
+        let mut paths: Vec<String> = selections[0].tests.iter().map(|t| t.path.clone()).collect();
+        paths.sort();
+
+        let mut out = Vec::new();
+        write_entries(&mut out, paths, args.print0).unwrap();
+        assert_eq!(out, b"tests/test_a b.py\0tests/test_c.py\0");
+    }
+
+    #[test]
+    fn node_ids_k_expression_and_dry_run_conflict() {
+        assert!(super::Cli::try_parse_from(["testdiff", "--node-ids", "--run"]).is_ok());
+        let err = super::Cli::try_parse_from(["testdiff", "--node-ids", "--dry-run"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        for other in ["--node-ids", "--dry-run"] {
+            let err =
+                super::Cli::try_parse_from(["testdiff", "--k-expression", other]).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn run_forwards_args_after_double_dash() {
+        let cli = super::Cli::try_parse_from([
+            "testdiff",
+            "--run",
+            "--max",
+            "2",
+            "--pytest",
+            "uv run pytest",
+            "--",
+            "-x",
+            "-q",
+        ])
+        .unwrap();
+        assert!(cli.select.run);
+        assert_eq!(cli.select.max, Some(2));
+        assert_eq!(cli.select.pytest.as_deref(), Some("uv run pytest"));
+        assert_eq!(cli.select.pytest_args, vec!["-x", "-q"]);
+
+        let err = super::Cli::try_parse_from(["testdiff", "--", "-x"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn changed_argfile_entries_mix_with_literals() {
+        let tmp = tempdir().unwrap();
+        let cwd = tmp.path().canonicalize().unwrap();
+        fs::write(cwd.join("a.py"), "").unwrap();
+        fs::write(cwd.join("b.py"), "").unwrap();
+        fs::write(cwd.join("c.py"), "").unwrap();
+        fs::write(cwd.join("changed.txt"), "b.py\n\n  c.py  \n").unwrap();
+
+        let inputs = vec!["a.py".to_string(), "@changed.txt".to_string()];
+        let paths = absolutize_changed(&inputs, &cwd).unwrap();
+        assert_eq!(
+            paths,
+            vec![cwd.join("a.py"), cwd.join("b.py"), cwd.join("c.py")]
+        );
+
+        let err = absolutize_changed(&["@missing.txt".to_string()], &cwd)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("missing.txt"), "unexpected error: {err}");
+    }
+
+    #[test]
+    fn changed_from_reads_paths_from_stdin_or_a_file() {
+        let tmp = tempdir().unwrap();
+        let cwd = tmp.path().canonicalize().unwrap();
+        fs::write(cwd.join("a.py"), "").unwrap();
+        fs::write(cwd.join("my file.py"), "").unwrap();
+        fs::write(cwd.join("list.txt"), "a.py\n").unwrap();
+
+        let paths = changed_from("-", &cwd, &mut "a.py\r\n\n  my file.py \n".as_bytes()).unwrap();
+        assert_eq!(paths, vec![cwd.join("a.py"), cwd.join("my file.py")]);
+        assert!(
+            changed_from("-", &cwd, &mut "".as_bytes())
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            changed_from("list.txt", &cwd, &mut "".as_bytes()).unwrap(),
+            vec![cwd.join("a.py")]
+        );
+    }
+
+    #[test]
+    fn assumed_missing_file_selects_tests_of_its_module() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("pkg").join("__init__.py"), "").unwrap();
+        fs::write(root.join("pkg").join("core.py"), "X = 1\n").unwrap();
+        fs::write(
+            root.join("tests").join("test_core.py"),
+            "from pkg import core\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("tests").join("test_planned.py"),
+            "from pkg import planned\n",
+        )
+        .unwrap();
+
+        let args = super::Cli::try_parse_from([
+            "testdiff",
+            "--assume-changed",
+            "pkg/core.py,pkg/planned.py",
+        ])
+        .unwrap()
+        .select;
+        let assumed = absolutize_changed(&args.assume_changed, &root).unwrap();
+        assert_eq!(
+            assumed,
+            vec![
+                root.join("pkg").join("core.py"),
+                root.join("pkg").join("planned.py")
+            ]
+        );
+
+        // Only the file that does not exist yet: `pkg.planned` resolves like a deleted module.
+        let utf8_root = Utf8PathBuf::from_path_buf(root.clone()).unwrap();
+        let index = ProjectIndex::build(&utf8_root).unwrap();
+        let changed = normalize_changed(&assumed[1..]).unwrap();
+        let impacted = index
+            .impacted_tests(&changed, None, None, true, false)
+            .unwrap();
+        let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(names, vec!["tests/test_planned.py"]);
+    }
+
+    #[test]
+    fn k_expression_ors_selected_items() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().join("repo");
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("pkg").join("__init__.py"), "").unwrap();
+        fs::write(root.join("pkg").join("core.py"), "X = 1\n").unwrap();
+        fs::write(
+            root.join("tests").join("test_core.py"),
+            "from pkg import core\n\ndef test_x():\n    pass\n\n\
+             class TestCore:\n    def test_y(self):\n        pass\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("tests").join("test_empty.py"),
+            "from pkg import core\n",
+        )
+        .unwrap();
+
+        let utf8_root = Utf8PathBuf::from_path_buf(root.clone()).unwrap();
+        let index = ProjectIndex::build(&utf8_root).unwrap();
+        let changed = normalize_changed(&[root.join("pkg").join("core.py")]).unwrap();
+        let impacted = index
+            .impacted_tests(&changed, None, None, true, false)
+            .unwrap();
+
+        let expression = k_expression(&utf8_root, &impacted, &PytestPatterns::default());
+        assert_eq!(expression, "test_x or (TestCore and test_y) or test_empty");
+    }
+
     #[test]
     fn choose_root_prefers_nearest_pyproject() {
         let tmp = tempdir().unwrap();
@@ -201,6 +1244,310 @@ mod tests {
         let root =
             choose_root(None, &[changed.clone()], &workspace).expect("root resolution failed");
         assert_eq!(root, Utf8PathBuf::from_path_buf(workspace.clone()).unwrap());
+
+        // setup.cfg-only projects are roots too.
+        let legacy = cwd.join("legacy");
+        fs::create_dir_all(legacy.join("pkg")).unwrap();
+        fs::write(legacy.join("setup.cfg"), "[options]\n").unwrap();
+        let changed = legacy.join("pkg").join("mod.py");
+        fs::write(&changed, "").unwrap();
+        let root = choose_root(None, &[changed], cwd).unwrap();
+        assert_eq!(root, Utf8PathBuf::from_path_buf(legacy).unwrap());
+    }
+
+    #[test]
+    fn root_marker_beats_pyproject_and_git() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().join("repo");
+        let service = repo.join("services").join("api");
+        let nested = service.join("src").join("pkg");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(service.join(".testdiff-root"), "").unwrap();
+        fs::write(nested.join("pyproject.toml"), "").unwrap();
+        let changed = nested.join("mod.py");
+        fs::write(&changed, "x = 1\n").unwrap();
+
+        let root = choose_root(None, &[changed.clone()], &repo).unwrap();
+        assert_eq!(root, Utf8PathBuf::from_path_buf(service).unwrap());
+
+        // Without the marker the old heuristic applies.
+        let other = repo.join("lib").join("other.py");
+        fs::create_dir_all(other.parent().unwrap()).unwrap();
+        fs::write(&other, "").unwrap();
+        let root = choose_root(None, &[other], &repo).unwrap();
+        assert_eq!(root, Utf8PathBuf::from_path_buf(repo).unwrap());
+    }
+
+    #[test]
+    fn root_from_git_ignores_nested_pyproject() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        let nested = repo.join("services").join("api");
+        fs::create_dir_all(nested.join("pkg")).unwrap();
+        fs::write(nested.join("pyproject.toml"), "").unwrap();
+        let changed = nested.join("pkg").join("mod.py");
+        fs::write(&changed, "x = 1\n").unwrap();
+        let status = std::process::Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(&repo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let heuristic = choose_root(None, &[changed.clone()], &repo).unwrap();
+        assert_eq!(
+            heuristic,
+            Utf8PathBuf::from_path_buf(nested.clone()).unwrap()
+        );
+
+        let args = super::Cli::try_parse_from(["testdiff", "--root-from-git"])
+            .unwrap()
+            .select;
+        let explicit = explicit_roots(&args, &nested);
+        let groups = choose_roots(&explicit, &[changed.clone()], &nested, true).unwrap();
+        let repo = Utf8PathBuf::from_path_buf(repo).unwrap();
+        assert_eq!(groups, vec![(repo, vec![changed])]);
+    }
+
+    /// `git init` (if needed) and commit everything under `repo`.
+    fn git_commit_all(repo: &Path) {
+        let git_args: [&[&str]; 3] = [
+            &["init", "-q"],
+            &["add", "-A"],
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@example.com",
+                "commit",
+                "-qm",
+                "commit",
+            ],
+        ];
+        for git_args in git_args {
+            let status = std::process::Command::new("git")
+                .args(git_args)
+                .current_dir(repo)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn comment_only_edit_selects_no_tests_with_ignore_cosmetic() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(repo.join("pkg")).unwrap();
+        fs::create_dir_all(repo.join("tests")).unwrap();
+        fs::write(repo.join("pkg").join("__init__.py"), "").unwrap();
+        fs::write(repo.join("pkg").join("mod.py"), "def f():\n    return 1\n").unwrap();
+        fs::write(
+            repo.join("tests").join("test_mod.py"),
+            "from pkg.mod import f\n\ndef test_f():\n    assert f() == 1\n",
+        )
+        .unwrap();
+        git_commit_all(&repo);
+        fs::write(
+            repo.join("pkg").join("mod.py"),
+            "def f():\n    # The answer.\n    return 1\n",
+        )
+        .unwrap();
+
+        let select = |extra: &[&str]| {
+            let argv = [&["testdiff", "--quiet", "--git-worktree"], extra].concat();
+            super::Cli::try_parse_from(argv).unwrap().select
+        };
+        let args = select(&[]);
+        let changed = changed_files(&args, &repo).unwrap();
+        assert_eq!(changed, vec![repo.join("pkg").join("mod.py")]);
+        let groups = choose_roots(&[], &changed, &repo, true).unwrap();
+        let selections = select_per_root(&args, groups, None).unwrap();
+        let selected: Vec<_> = selections
+            .iter()
+            .flat_map(|sel| sel.tests.iter().map(|t| t.path.clone()))
+            .collect();
+        assert_eq!(selected, vec!["tests/test_mod.py".to_string()]);
+
+        let args = select(&["--ignore-cosmetic"]);
+        assert!(changed_files(&args, &repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ignore_cosmetic_keeps_data_files() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        fs::write(repo.join("settings.cfg"), "retries = 3\n").unwrap();
+        git_commit_all(&repo);
+        // Valid Python with only a comment added, but not a Python file.
+        fs::write(repo.join("settings.cfg"), "# tuned\nretries = 3\n").unwrap();
+
+        let argv = [
+            "testdiff",
+            "--quiet",
+            "--git-worktree",
+            "--data-deps",
+            "--ignore-cosmetic",
+        ];
+        let args = super::Cli::try_parse_from(argv).unwrap().select;
+        let changed = changed_files(&args, &repo).unwrap();
+        assert_eq!(changed, vec![repo.join("settings.cfg")]);
+    }
+
+    #[test]
+    fn changes_in_separate_projects_select_from_each_root() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        for service in ["a", "b"] {
+            let dir = repo.join("services").join(service);
+            fs::create_dir_all(dir.join("pkg")).unwrap();
+            fs::create_dir_all(dir.join("tests")).unwrap();
+            fs::write(dir.join("pyproject.toml"), "").unwrap();
+            fs::write(dir.join("pkg").join("__init__.py"), "").unwrap();
+        }
+        let a = repo.join("services").join("a");
+        let b = repo.join("services").join("b");
+        fs::write(a.join("pkg").join("x.py"), "").unwrap();
+        fs::write(b.join("pkg").join("y.py"), "").unwrap();
+        fs::write(a.join("tests").join("test_x.py"), "from pkg import x\n").unwrap();
+        fs::write(b.join("tests").join("test_y.py"), "from pkg import y\n").unwrap();
+
+        let changed = vec![a.join("pkg").join("x.py"), b.join("pkg").join("y.py")];
+        let groups = choose_roots(&[], &changed, &repo, true).unwrap();
+        let roots: Vec<_> = groups.iter().map(|(root, _)| root.clone()).collect();
+        let utf8 = |p: &PathBuf| Utf8PathBuf::from_path_buf(p.clone()).unwrap();
+        assert_eq!(roots, vec![utf8(&a), utf8(&b)]);
+
+        let args = super::Cli::try_parse_from(["testdiff", "--quiet"])
+            .unwrap()
+            .select;
+        let selections = select_per_root(&args, groups, None).unwrap();
+        let selected: Vec<_> = selections
+            .iter()
+            .flat_map(|sel| sel.tests.iter().map(|t| sel.root.join(&t.path)))
+            .collect();
+        assert_eq!(
+            selected,
+            vec![
+                utf8(&a).join("tests/test_x.py"),
+                utf8(&b).join("tests/test_y.py")
+            ]
+        );
+
+        // Printed relative to the current directory, so the two roots cannot collide.
+        let shown: Vec<_> = selections
+            .iter()
+            .flat_map(|sel| {
+                sel.tests
+                    .iter()
+                    .map(|t| shown_path(&sel.root, &t.path, Some(&repo)))
+            })
+            .collect();
+        assert_eq!(
+            shown,
+            vec!["services/a/tests/test_x.py", "services/b/tests/test_y.py"]
+        );
+        assert_eq!(
+            shown_path(&selections[0].root, "tests/test_x.py", None),
+            "tests/test_x.py"
+        );
+
+        // A change outside the services joins them under the repository root.
+        let top = repo.join("tool.py");
+        fs::write(&top, "").unwrap();
+        let groups = choose_roots(&[], &[changed[0].clone(), top], &repo, true).unwrap();
+        assert_eq!(groups.len(), 1);
+
+        // Repeated --root splits the changes explicitly; paths outside every root are dropped.
+        let groups = choose_roots(
+            &[a.clone(), b.clone()],
+            &[changed[1].clone(), repo.join("tool.py")],
+            &repo,
+            true,
+        )
+        .unwrap();
+        assert_eq!(groups[0], (utf8(&a), vec![]));
+        assert_eq!(groups[1], (utf8(&b), vec![changed[1].clone()]));
+    }
+
+    #[test]
+    fn max_caps_the_selection_across_roots() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        for service in ["a", "b"] {
+            let dir = repo.join("services").join(service);
+            fs::create_dir_all(dir.join("pkg")).unwrap();
+            fs::create_dir_all(dir.join("tests")).unwrap();
+            fs::write(dir.join("pyproject.toml"), "").unwrap();
+            fs::write(dir.join("pkg").join("__init__.py"), "").unwrap();
+        }
+        let a = repo.join("services").join("a");
+        let b = repo.join("services").join("b");
+        fs::write(a.join("pkg").join("x.py"), "").unwrap();
+        fs::write(a.join("pkg").join("helper.py"), "from pkg import x\n").unwrap();
+        fs::write(b.join("pkg").join("y.py"), "").unwrap();
+        fs::write(a.join("tests").join("test_x.py"), "from pkg import x\n").unwrap();
+        fs::write(
+            a.join("tests").join("test_helper.py"),
+            "from pkg import helper\n",
+        )
+        .unwrap();
+        fs::write(b.join("tests").join("test_y.py"), "from pkg import y\n").unwrap();
+
+        let changed = vec![a.join("pkg").join("x.py"), b.join("pkg").join("y.py")];
+        let selected = |max: &str| {
+            let args = super::Cli::try_parse_from(["testdiff", "--quiet", "--max", max])
+                .unwrap()
+                .select;
+            let groups = choose_roots(&[], &changed, &repo, true).unwrap();
+            let selections = select_per_root(&args, groups, None).unwrap();
+            selections
+                .iter()
+                .flat_map(|sel| {
+                    sel.tests
+                        .iter()
+                        .map(|t| shown_path(&sel.root, &t.path, Some(&repo)))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The distance-2 test in `a` is cut, not `b`'s direct importer.
+        assert_eq!(
+            selected("2"),
+            vec!["services/a/tests/test_x.py", "services/b/tests/test_y.py"]
+        );
+        assert_eq!(selected("1"), vec!["services/a/tests/test_x.py"]);
+        assert_eq!(selected("3").len(), 3);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_drives_decide_the_common_ancestor() {
+        let path = |raw: &str| Utf8PathBuf::from(raw).into_std_path_buf();
+
+        let same_drive = [path(r"C:\work\a\x.py"), path(r"c:\work\b\y.py")];
+        assert_eq!(common_ancestor_dirs(&same_drive), Some(path(r"C:\work")));
+        // `canonicalize` yields verbatim `\\?\C:\` paths; they still match plain ones.
+        let verbatim = [path(r"\\?\C:\work\a\x.py"), path(r"C:\work\a\y.py")];
+        assert_eq!(
+            common_ancestor_dirs(&verbatim),
+            Some(path(r"\\?\C:\work\a"))
+        );
+
+        let cross_drive = [path(r"C:\a\x.py"), path(r"D:\b\y.py")];
+        assert_eq!(common_ancestor_dirs(&cross_drive), None);
+        let cwd = std::env::current_dir().unwrap();
+        let err = choose_root(None, &cross_drive, &cwd)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("different drives"), "unexpected error: {err}");
+
+        // A drive root is never the project root.
+        let at_drive_root = [path(r"C:\x.py"), path(r"C:\y.py")];
+        let root = choose_root(None, &at_drive_root, &cwd).unwrap();
+        assert_eq!(root.as_std_path(), cwd.as_path());
     }
 
     #[test]
@@ -212,51 +1559,46 @@ mod tests {
     }
 }
 
+/// Empty marker file that pins the project root, ahead of the pyproject.toml/.git heuristic.
+const ROOT_MARKER: &str = ".testdiff-root";
+
+/// Files or directories whose presence makes a directory a likely project root.
+const PROJECT_MARKERS: &[&str] = &["pyproject.toml", "setup.cfg", "setup.py", ".git"];
+
 fn choose_root(explicit: Option<PathBuf>, changed: &[PathBuf], cwd: &Path) -> Result<Utf8PathBuf> {
     // 1) explicit --root wins.
-    // 2) nearest ancestor of each changed file containing pyproject.toml or .git; pick shortest ascent.
-    // 3) common ancestor of parent dirs of changed files.
-    // 4) fallback to cwd.
+    // 2) nearest ancestor of each changed file containing a `.testdiff-root` marker.
+    // 3) nearest ancestor of each changed file containing pyproject.toml, setup.cfg, setup.py
+    //    or .git.
+    //    For 2) and 3), pick the shortest ascent.
+    // 4) common ancestor of parent dirs of changed files; an error when there is none.
+    // 5) fallback to cwd.
+    //    A filesystem root (`/`, `C:\`) is never used; cwd is used instead.
 
-    let pick_dir = |p: &Path| {
-        if p.is_dir() {
-            p.to_path_buf()
-        } else {
-            p.parent().unwrap_or(p).to_path_buf()
-        }
+    // Nearest ancestor holding any of `markers`, over all changed files.
+    let nearest_with = |markers: &[&str]| -> Option<PathBuf> {
+        changed
+            .iter()
+            .filter_map(|path| nearest_ancestor_with(path, markers))
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, best)| best)
     };
 
     let path = if let Some(root) = explicit {
-        pick_dir(&root)
+        containing_dir(&root)
+    } else if let Some(marked) = nearest_with(&[ROOT_MARKER]) {
+        marked
+    } else if let Some(best) = nearest_with(PROJECT_MARKERS) {
+        best
+    } else if let Some(common) = common_ancestor_dirs(changed) {
+        common
+    } else if !changed.is_empty() && changed.iter().all(|p| p.is_absolute()) {
+        // Absolute paths with nothing in common are on different drives (Windows).
+        anyhow::bail!(
+            "Changed files have no common directory (are they on different drives?); pass --root"
+        );
     } else {
-        let mut candidates: Vec<(usize, PathBuf)> = Vec::new();
-        for path in changed {
-            let mut depth = 0usize;
-            let mut current = pick_dir(path);
-            loop {
-                if current.join("pyproject.toml").exists() || current.join(".git").exists() {
-                    candidates.push((depth, current.clone()));
-                    break;
-                }
-                if let Some(parent) = current.parent() {
-                    if parent == current {
-                        break;
-                    }
-                    current = parent.to_path_buf();
-                    depth += 1;
-                } else {
-                    break;
-                }
-            }
-        }
-
-        if let Some((_, best)) = candidates.into_iter().min_by_key(|(d, _)| *d) {
-            best
-        } else if let Some(common) = common_ancestor_dirs(changed) {
-            common
-        } else {
-            cwd.to_path_buf()
-        }
+        cwd.to_path_buf()
     };
 
     let path = if path.parent().is_none() {
@@ -269,6 +1611,104 @@ fn choose_root(explicit: Option<PathBuf>, changed: &[PathBuf], cwd: &Path) -> Re
         .map_err(|_| anyhow::anyhow!("Project root must be valid UTF-8"))
 }
 
+/// The roots to index, each with the changed files it owns. With several `--root`s, a file
+/// goes to the deepest root containing it (files outside every root are dropped). Without
+/// `--root`, files whose own nearest roots are separate projects, none nested in another
+/// (e.g. `services/a` and `services/b` with a `pyproject.toml` each), get a root per project.
+/// Otherwise everything goes to the single root [`choose_root`] picks.
+fn choose_roots(
+    explicit: &[PathBuf],
+    changed: &[PathBuf],
+    cwd: &Path,
+    quiet: bool,
+) -> Result<Vec<(Utf8PathBuf, Vec<PathBuf>)>> {
+    if explicit.len() > 1 {
+        let mut groups = Vec::new();
+        for root in explicit {
+            let root = canonicalize_lenient(cwd.join(root));
+            groups.push((choose_root(Some(root), &[], cwd)?, Vec::new()));
+        }
+        for path in changed {
+            let owner = groups
+                .iter_mut()
+                .filter(|(root, _)| path.starts_with(root))
+                .max_by_key(|(root, _)| root.components().count());
+            match owner {
+                Some((_, files)) => files.push(path.clone()),
+                None if !quiet => diag::warning(format_args!(
+                    "{} is outside every --root; ignoring it",
+                    path.display()
+                )),
+                None => {}
+            }
+        }
+        return Ok(groups);
+    }
+
+    if explicit.is_empty() {
+        let nearest: Option<Vec<PathBuf>> = changed.iter().map(|p| project_root_of(p)).collect();
+        if let Some(nearest) = nearest {
+            let mut roots = nearest.clone();
+            roots.sort();
+            roots.dedup();
+            let nested = roots
+                .iter()
+                .any(|a| roots.iter().any(|b| a != b && b.starts_with(a)));
+            if roots.len() > 1 && !nested {
+                let mut groups = Vec::new();
+                for root in roots {
+                    let files = changed
+                        .iter()
+                        .zip(&nearest)
+                        .filter(|(_, owner)| **owner == root)
+                        .map(|(path, _)| path.clone())
+                        .collect();
+                    groups.push((choose_root(Some(root), &[], cwd)?, files));
+                }
+                return Ok(groups);
+            }
+        }
+    }
+
+    let root = choose_root(explicit.first().cloned(), changed, cwd)?;
+    Ok(vec![(root, changed.to_vec())])
+}
+
+/// `--root` (possibly repeated), else the git toplevel with `--root-from-git`.
+fn explicit_roots(args: &SelectArgs, cwd: &Path) -> Vec<PathBuf> {
+    if args.root.is_empty() && args.root_from_git {
+        git_toplevel(cwd).into_iter().collect()
+    } else {
+        args.root.clone()
+    }
+}
+
+/// The root [`choose_root`] would pick for `path` alone: its nearest marked ancestor, else
+/// its nearest ancestor with one of the [`PROJECT_MARKERS`].
+fn project_root_of(path: &Path) -> Option<PathBuf> {
+    nearest_ancestor_with(path, &[ROOT_MARKER])
+        .or_else(|| nearest_ancestor_with(path, PROJECT_MARKERS))
+        .map(|(_, root)| root)
+}
+
+/// The nearest ancestor of `path` holding any of `markers`, with how many levels up it is.
+fn nearest_ancestor_with(path: &Path, markers: &[&str]) -> Option<(usize, PathBuf)> {
+    let start = containing_dir(path);
+    start
+        .ancestors()
+        .enumerate()
+        .find(|(_, dir)| markers.iter().any(|m| dir.join(m).exists()))
+        .map(|(depth, dir)| (depth, dir.to_path_buf()))
+}
+
+fn containing_dir(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        path.parent().unwrap_or(path).to_path_buf()
+    }
+}
+
 fn common_ancestor_dirs(paths: &[PathBuf]) -> Option<PathBuf> {
     let parents: Vec<PathBuf> = paths
         .iter()
@@ -285,7 +1725,7 @@ fn common_ancestor_dirs(paths: &[PathBuf]) -> Option<PathBuf> {
         let comps = path.components().collect::<Vec<_>>();
         prefix_len = prefix_len.min(comps.len());
         for i in 0..prefix_len {
-            if first[i] != comps[i] {
+            if !same_component(&first[i], &comps[i]) {
                 prefix_len = i;
                 break;
             }
@@ -299,6 +1739,22 @@ fn common_ancestor_dirs(paths: &[PathBuf]) -> Option<PathBuf> {
     }
 }
 
+/// Path components compared for root detection. Drive prefixes match by letter, ignoring case
+/// and the `\\?\` verbatim form `canonicalize` produces on Windows.
+fn same_component(a: &Component, b: &Component) -> bool {
+    let drive = |prefix: Prefix| match prefix {
+        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => Some(letter.to_ascii_uppercase()),
+        _ => None,
+    };
+    match (a, b) {
+        (Component::Prefix(x), Component::Prefix(y)) => match (drive(x.kind()), drive(y.kind())) {
+            (Some(x), Some(y)) => x == y,
+            _ => x == y,
+        },
+        _ => a == b,
+    }
+}
+
 fn normalize_changed(inputs: &[PathBuf]) -> Result<Vec<Utf8PathBuf>> {
     let mut out = Vec::new();
     for path in inputs {
@@ -314,17 +1770,165 @@ fn normalize_changed(inputs: &[PathBuf]) -> Result<Vec<Utf8PathBuf>> {
     Ok(out)
 }
 
-fn print_dry_run(root: &Utf8PathBuf, changed: &[Utf8PathBuf], impacted: &[TestResult]) {
-    eprintln!("Root: {}", root);
-    eprintln!("Changed files ({}):", changed.len());
+/// Write `entries` to `out`, each ended by a newline, or by a NUL byte with `--print0`.
+fn write_entries(
+    out: &mut impl std::io::Write,
+    entries: impl IntoIterator<Item = String>,
+    print0: bool,
+) -> std::io::Result<()> {
+    let end = if print0 { b'\0' } else { b'\n' };
+    for entry in entries {
+        out.write_all(entry.as_bytes())?;
+        out.write_all(&[end])?;
+    }
+    Ok(())
+}
+
+/// `path` (relative to `root`) as printed: as is, or relative to `anchor` when set.
+fn shown_path(root: &Utf8Path, path: &str, anchor: Option<&Path>) -> String {
+    let Some(cwd) = anchor else {
+        return path.to_string();
+    };
+    let test = root.join(path).into_std_path_buf();
+    pathdiff::diff_paths(&test, cwd)
+        .unwrap_or(test)
+        .display()
+        .to_string()
+}
+
+/// TSV rows for `impacted`, each test path passed through `shown`.
+fn tsv_lines(impacted: &[TestResult], shown: impl Fn(&str) -> String) -> Vec<String> {
+    let mut lines = vec!["seed\ttest_module\tdistance\tpath".to_string()];
+    for res in impacted {
+        let path = shown(&res.path);
+        if res.seeds.is_empty() {
+            lines.push(format!(
+                "{}\t{}\t{}\t{path}",
+                res.seed, res.module, res.distance
+            ));
+        }
+        for (seed, distance) in &res.seeds {
+            lines.push(format!("{seed}\t{}\t{distance}\t{path}", res.module));
+        }
+    }
+    lines
+}
+
+/// The selection as a JSON document, in output order; `rank` is the 1-based position. With
+/// several roots, `root` is the first one and each test names its own.
+fn selection_json(selections: &[RootSelection]) -> serde_json::Value {
+    let several = selections.len() > 1;
+    let tests = selections
+        .iter()
+        .flat_map(|sel| sel.tests.iter().map(move |res| (&sel.root, res)))
+        .enumerate()
+        .map(|(i, (root, res))| {
+            let mut test = serde_json::json!({
+                "path": res.path,
+                "distance": res.distance,
+                "filename_match": res.priority.filename_match,
+                "rank": i + 1,
+            });
+            if several {
+                test["root"] = root.as_str().into();
+            }
+            test
+        })
+        .collect();
+    let root = selections.first().map(|sel| sel.root.as_str());
+    let mut fields = serde_json::Map::new();
+    fields.insert("root".to_string(), root.into());
+    fields.insert("tests".to_string(), serde_json::Value::Array(tests));
+    json::document(fields)
+}
+
+/// Node ids for the test items in `path`; the bare path when none are found, so the file
+/// still runs (e.g. unittest classes pytest collects by base class).
+fn node_ids(root: &Utf8Path, path: &str, patterns: &PytestPatterns) -> Vec<String> {
+    let items = file_items(root, path, patterns);
+    if items.is_empty() {
+        return vec![path.to_string()];
+    }
+    items
+        .into_iter()
+        .map(|item| format!("{path}::{item}"))
+        .collect()
+}
+
+/// `-k` terms OR-ed together: `(Class and method)` for methods, the bare name for functions,
+/// and the file stem for files without enumerable items. Names pytest's `-k` grammar cannot
+/// express (e.g. stems with spaces) are left out.
+fn k_expression(root: &Utf8Path, impacted: &[TestResult], patterns: &PytestPatterns) -> String {
+    let is_ident = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_alphanumeric() || "_:+-.[]\\/".contains(c))
+    };
+
+    let mut terms: Vec<String> = Vec::new();
+    for res in impacted {
+        let items = file_items(root, &res.path, patterns);
+        let file_terms: Vec<String> = if items.is_empty() {
+            Utf8Path::new(&res.path)
+                .file_stem()
+                .filter(|stem| is_ident(stem))
+                .map(str::to_string)
+                .into_iter()
+                .collect()
+        } else {
+            items
+                .iter()
+                .map(|item| {
+                    let parts: Vec<&str> = item.split("::").collect();
+                    if parts.len() > 1 {
+                        format!("({})", parts.join(" and "))
+                    } else {
+                        item.clone()
+                    }
+                })
+                .collect()
+        };
+        for term in file_terms {
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+    terms.join(" or ")
+}
+
+/// The collectable items of the test file at root-relative `path` (none if it is unreadable).
+fn file_items(root: &Utf8Path, path: &str, patterns: &PytestPatterns) -> Vec<String> {
+    std::fs::read_to_string(root.join(path))
+        .map(|source| test_items(&source, patterns))
+        .unwrap_or_default()
+}
+
+fn print_dry_run(
+    root: &Utf8PathBuf,
+    changed: &[Utf8PathBuf],
+    impacted: &[TestResult],
+    patterns: &PytestPatterns,
+) {
+    eprintln!("{} {}", diag::bold("Root:"), root);
+    eprintln!(
+        "{}",
+        diag::bold(&format!("Changed files ({}):", changed.len()))
+    );
     for p in changed {
         eprintln!("  - {}", p);
     }
-    eprintln!("\nSelected tests ({}):", impacted.len());
+    eprintln!(
+        "\n{}",
+        diag::bold(&format!("Selected tests ({}):", impacted.len()))
+    );
     for res in impacted {
         eprintln!(
-            "  - {} (distance={}, filename_match={})",
-            res.path, res.distance, res.priority.filename_match
+            "  - {} (distance={}, filename_match={}, items={})",
+            res.path,
+            res.distance,
+            res.priority.filename_match,
+            file_items(root, &res.path, patterns).len()
         );
     }
 }
@@ -0,0 +1,64 @@
+//! Path helpers shared by the library and the `testdiff` binary, which compiles this file
+//! as its own module too.
+
+use std::path::{Path, PathBuf};
+
+/// Directory names never scanned.
+pub const SKIPPED_DIRS: &[&str] = &[
+    ".git",
+    "target",
+    ".tox",
+    ".venv",
+    "venv",
+    "__pycache__",
+    "node_modules",
+]; // keep scan lean
+
+pub fn filter_dir(path: &Path) -> bool {
+    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+        if SKIPPED_DIRS.contains(&name) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Python sources, including `.pyi` type stubs.
+pub fn is_python_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext == "py" || ext == "pyi")
+        .unwrap_or(false)
+}
+
+/// For a file inside a directory named in `fixture_dirs` (e.g. `tests/fixtures/data.json`),
+/// the directory containing that fixtures directory (`tests`).
+pub fn fixture_scope(path: &Path, fixture_dirs: &[String]) -> Option<PathBuf> {
+    path.ancestors().skip(1).find_map(|dir| {
+        let name = dir.file_name()?.to_str()?;
+        if fixture_dirs.iter().any(|f| f == name) {
+            dir.parent().map(Path::to_path_buf)
+        } else {
+            None
+        }
+    })
+}
+
+/// Canonicalize `path`, or for a missing file (deleted or only assumed) its nearest existing
+/// ancestor, so it still lines up with the canonical root.
+pub fn canonicalize_lenient(path: PathBuf) -> PathBuf {
+    if let Ok(real) = path.canonicalize() {
+        return real;
+    }
+    let mut missing = Vec::new();
+    let mut current = path.as_path();
+    while let Some(parent) = current.parent() {
+        if let Some(name) = current.file_name() {
+            missing.push(name.to_os_string());
+        }
+        if let Ok(real) = parent.canonicalize() {
+            return missing.iter().rev().fold(real, |acc, name| acc.join(name));
+        }
+        current = parent;
+    }
+    path
+}
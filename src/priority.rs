@@ -1,13 +1,46 @@
+use std::cmp::Reverse;
 use std::collections::HashSet;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct Priority {
+    /// Failed in the previous run (from `--previous-failures`); outranks everything else.
+    pub previously_failed: Reverse<bool>,
     pub filename_match: u8,
     pub distance: usize,
+    /// Historical failure count (from `--history`); more failures sort earlier.
+    pub recent_failures: Reverse<u32>,
 }
 
-pub fn priority(path: &str, distance: usize, changed_leaves: &HashSet<String>) -> Priority {
+/// Tests that failed in a previous run, as read from its JUnit report.
+#[derive(Clone, Debug, Default)]
+pub struct PreviousFailures {
+    /// Test files as reported (absolute, or relative to wherever pytest ran).
+    pub files: Vec<PathBuf>,
+    /// JUnit `classname`s (`tests.test_foo` or `tests.test_foo.TestBar`).
+    pub classnames: Vec<String>,
+}
+
+impl PreviousFailures {
+    /// Whether the test file at root-relative `path`, indexed as `module`, failed last time.
+    pub fn contains(&self, path: &str, module: &str) -> bool {
+        self.files.iter().any(|file| file.ends_with(path))
+            || self.classnames.iter().any(|class| {
+                class == module
+                    || class
+                        .strip_prefix(module)
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+    }
+}
+
+pub fn priority(
+    path: &str,
+    distance: usize,
+    changed_leaves: &HashSet<String>,
+    recent_failures: u32,
+    previously_failed: bool,
+) -> Priority {
     let filename = Path::new(path)
         .file_name()
         .and_then(|s| s.to_str())
@@ -25,8 +58,10 @@ pub fn priority(path: &str, distance: usize, changed_leaves: &HashSet<String>) -
     }
 
     Priority {
+        previously_failed: Reverse(previously_failed),
         filename_match,
         distance,
+        recent_failures: Reverse(recent_failures),
     }
 }
 
@@ -40,22 +75,55 @@ mod tests {
 
     #[test]
     fn prioritizes_prefix_match_best() {
-        let p = priority("tests/test_foo.py", 0, &leaves(&["foo"]));
+        let p = priority("tests/test_foo.py", 0, &leaves(&["foo"]), 0, false);
         assert_eq!(p.filename_match, 0);
         assert_eq!(p.distance, 0);
     }
 
     #[test]
     fn partial_contains_is_secondary() {
-        let p = priority("tests/integration_bar_test.py", 2, &leaves(&["bar"]));
+        let p = priority(
+            "tests/integration_bar_test.py",
+            2,
+            &leaves(&["bar"]),
+            0,
+            false,
+        );
         assert_eq!(p.filename_match, 0);
         assert_eq!(p.distance, 2);
     }
 
     #[test]
     fn unrelated_files_get_low_priority() {
-        let p = priority("tests/other.py", 5, &leaves(&["foo"]));
+        let p = priority("tests/other.py", 5, &leaves(&["foo"]), 0, false);
         assert_eq!(p.filename_match, 2);
         assert_eq!(p.distance, 5);
     }
+
+    #[test]
+    fn failure_history_breaks_ties() {
+        let flaky = priority("tests/test_a.py", 1, &leaves(&["foo"]), 3, false);
+        let stable = priority("tests/test_b.py", 1, &leaves(&["foo"]), 0, false);
+        assert!(flaky < stable);
+
+        // History never outranks a closer test.
+        let closer = priority("tests/test_c.py", 0, &leaves(&["foo"]), 0, false);
+        assert!(closer < flaky);
+    }
+
+    #[test]
+    fn previous_failures_outrank_everything() {
+        let failed = priority("tests/other.py", 5, &leaves(&["foo"]), 0, true);
+        let best = priority("tests/test_foo.py", 0, &leaves(&["foo"]), 9, false);
+        assert!(failed < best);
+
+        let previous = PreviousFailures {
+            files: vec![PathBuf::from("/ci/checkout/tests/test_a.py")],
+            classnames: vec!["tests.test_b.TestB".to_string()],
+        };
+        assert!(previous.contains("tests/test_a.py", "tests.test_a"));
+        assert!(previous.contains("tests/test_b.py", "tests.test_b"));
+        assert!(!previous.contains("tests/test_bb.py", "tests.test_bb"));
+        assert!(!previous.contains("a.py", "a"));
+    }
 }
@@ -0,0 +1,188 @@
+//! On-disk cache of per-file parse results (`--cache-dir`).
+//!
+//! Only the raw imports (plus `__all__` names and opened path literals) of each file are
+//! cached, keyed by path and an mtime/size stamp. Module names are recomputed on every build
+//! because they depend on the rest of the tree (`__init__.py` files, namespace packages,
+//! source roots). The cache is rewritten from the files seen by each build, so deleted files
+//! drop out of it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde_json::{Map, Value, json};
+
+use crate::project::resolve::{ImportKind, ImportSpec};
+
+/// Bumped whenever the cache layout or what gets cached changes; older caches are ignored.
+const CACHE_VERSION: u64 = 5;
+const CACHE_FILE: &str = "index-cache.json";
+
+/// Modification time and size of a file, compared for equality to detect edits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Stamp {
+    secs: u64,
+    nanos: u32,
+    size: u64,
+}
+
+impl Stamp {
+    pub(super) fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            secs: mtime.as_secs(),
+            nanos: mtime.subsec_nanos(),
+            size: meta.len(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub(super) struct ParseCache {
+    files: HashMap<Utf8PathBuf, CachedFile>,
+}
+
+struct CachedFile {
+    stamp: Stamp,
+    imports: Vec<ImportSpec>,
+    exports: Vec<String>,
+    data_refs: Vec<String>,
+}
+
+impl ParseCache {
+    /// The cache stored in `dir` for `root`; empty when it is missing, unreadable, written
+    /// for another root or by another cache version.
+    pub(super) fn load(dir: &Utf8Path, root: &Utf8Path) -> Self {
+        fs::read_to_string(dir.join(CACHE_FILE))
+            .ok()
+            .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+            .and_then(|doc| Self::from_json(&doc, root))
+            .unwrap_or_default()
+    }
+
+    /// Cached imports, exports and path literals of `path`, if its stamp still matches.
+    pub(super) fn get(
+        &self,
+        path: &Utf8Path,
+        stamp: Stamp,
+    ) -> Option<(&[ImportSpec], &[String], &[String])> {
+        let cached = self.files.get(path)?;
+        (cached.stamp == stamp).then_some((
+            cached.imports.as_slice(),
+            cached.exports.as_slice(),
+            cached.data_refs.as_slice(),
+        ))
+    }
+
+    pub(super) fn insert(
+        &mut self,
+        path: Utf8PathBuf,
+        stamp: Stamp,
+        imports: Vec<ImportSpec>,
+        exports: Vec<String>,
+        data_refs: Vec<String>,
+    ) {
+        self.files.insert(
+            path,
+            CachedFile {
+                stamp,
+                imports,
+                exports,
+                data_refs,
+            },
+        );
+    }
+
+    pub(super) fn save(&self, dir: &Utf8Path, root: &Utf8Path) -> Result<()> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {dir}"))?;
+        let mut files = Map::new();
+        for (path, cached) in &self.files {
+            files.insert(
+                path.to_string(),
+                json!({
+                    "secs": cached.stamp.secs,
+                    "nanos": cached.stamp.nanos,
+                    "size": cached.stamp.size,
+                    "imports": cached.imports.iter().map(spec_to_json).collect::<Vec<_>>(),
+                    "exports": cached.exports,
+                    "data_refs": cached.data_refs,
+                }),
+            );
+        }
+        let doc = json!({ "version": CACHE_VERSION, "root": root.as_str(), "files": files });
+        // Write then rename so an interrupted run never leaves a truncated cache behind.
+        let path = dir.join(CACHE_FILE);
+        let tmp = dir.join(format!("{CACHE_FILE}.tmp"));
+        fs::write(&tmp, doc.to_string()).with_context(|| format!("Failed to write {tmp}"))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {path}"))
+    }
+
+    fn from_json(doc: &Value, root: &Utf8Path) -> Option<Self> {
+        if doc["version"].as_u64()? != CACHE_VERSION || doc["root"].as_str()? != root.as_str() {
+            return None;
+        }
+        let mut cache = Self::default();
+        for (path, entry) in doc["files"].as_object()? {
+            let stamp = Stamp {
+                secs: entry["secs"].as_u64()?,
+                nanos: u32::try_from(entry["nanos"].as_u64()?).ok()?,
+                size: entry["size"].as_u64()?,
+            };
+            let imports = entry["imports"]
+                .as_array()?
+                .iter()
+                .map(spec_from_json)
+                .collect::<Option<Vec<_>>>()?;
+            let strings = |key: &str| {
+                entry[key]
+                    .as_array()?
+                    .iter()
+                    .map(|name| name.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+            };
+            let exports = strings("exports")?;
+            let data_refs = strings("data_refs")?;
+            cache.insert(Utf8PathBuf::from(path), stamp, imports, exports, data_refs);
+        }
+        Some(cache)
+    }
+}
+
+fn spec_to_json(spec: &ImportSpec) -> Value {
+    let kind = match spec.kind {
+        ImportKind::Import => "import",
+        ImportKind::ImportFrom => "from",
+    };
+    json!({
+        "level": spec.level,
+        "module": spec.module,
+        "name": spec.name,
+        "kind": kind,
+        "type_checking": spec.type_checking,
+        "optional": spec.optional,
+    })
+}
+
+fn spec_from_json(value: &Value) -> Option<ImportSpec> {
+    let optional = |key: &str| match &value[key] {
+        Value::Null => Some(None),
+        Value::String(s) => Some(Some(s.clone())),
+        _ => None,
+    };
+    Some(ImportSpec {
+        level: u32::try_from(value["level"].as_u64()?).ok()?,
+        module: optional("module")?,
+        name: optional("name")?,
+        kind: match value["kind"].as_str()? {
+            "import" => ImportKind::Import,
+            "from" => ImportKind::ImportFrom,
+            _ => return None,
+        },
+        type_checking: value["type_checking"].as_bool()?,
+        optional: value["optional"].as_bool()?,
+    })
+}
@@ -1,21 +1,92 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::fs;
+use std::ops::AddAssign;
+use std::path::Path;
+use std::time::SystemTime;
 
-use anyhow::Result;
-use camino::Utf8PathBuf;
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 
-use crate::priority::{Priority, priority};
-use crate::project::resolve::module_name;
+use crate::diag;
+use crate::paths::{canonicalize_lenient, fixture_scope, is_python_file};
+use crate::priority::{PreviousFailures, Priority, priority};
+use crate::project::Warning;
+use crate::project::resolve::{import_root, module_name};
+use crate::project::symbols::references_name;
+use crate::project::unresolved::{group_unresolved, render_unresolved};
 use crate::project::utils::is_test_file;
 
-use super::index::ProjectIndex;
+use super::index::{ModuleInfo, ProjectIndex};
 
 pub struct TestResult {
     pub path: String,
+    pub module: String,
+    /// The changed (seed) module this test was first reached from.
+    pub seed: String,
+    /// Every seed reaching this test with its distance from that seed, nearest first; only
+    /// filled in with [`ImpactedOptions::all_seeds`].
+    pub seeds: Vec<(String, usize)>,
     pub priority: Priority,
     pub distance: usize,
 }
 
+/// Knobs for [`ProjectIndex::impacted_tests_with`].
+#[derive(Clone, Debug, Default)]
+pub struct ImpactedOptions {
+    /// Maximum number of tests to return (most relevant first).
+    pub max: Option<usize>,
+    /// Maximum graph distance from changed modules.
+    pub distance_limit: Option<usize>,
+    /// Tighter distance cap for seeds guessed from deleted or unindexed files; seeds from
+    /// indexed changes keep `distance_limit`.
+    pub deleted_distance_limit: Option<usize>,
+    /// Suppress warnings on stderr.
+    pub quiet: bool,
+    /// Fail if any warning was produced.
+    pub warn_as_error: bool,
+    /// Maximum number of warnings printed to stderr (0 = no cap).
+    pub max_warnings: usize,
+    /// Only direct importers of changed modules (plus changed tests); no transitive walk.
+    pub no_transitive: bool,
+    /// Seed only from changed test files; source-only changes select nothing.
+    pub changed_tests_only: bool,
+    /// Directory names (e.g. `fixtures`) whose changed data files select every test in the
+    /// enclosing directory subtree.
+    pub fixture_dirs: Vec<String>,
+    /// `(module, symbol)`: seed from `module`; tests reached from it are kept only if they
+    /// reference `symbol` by name.
+    pub changed_symbol: Option<(String, String)>,
+    /// Print every unresolved import to stderr, grouped by top-level name.
+    pub explain_unresolved: bool,
+    /// Root-relative test path -> recent failure count; breaks priority ties.
+    pub history: HashMap<String, u32>,
+    /// Tests that failed in the previous run; listed before all others.
+    pub previous_failures: PreviousFailures,
+    /// Drop tests whose `filename_match` is weaker (numerically greater) than this.
+    pub min_filename_match: Option<u8>,
+    /// Globs over root-relative test paths; when any are given, only matching tests are kept.
+    pub include: Vec<String>,
+    /// Globs over root-relative test paths; matching tests are dropped, even if included.
+    pub exclude: Vec<String>,
+    /// Among equal priorities, list the most recently modified test files first.
+    pub order_by_mtime: bool,
+    /// Root-relative directories whose `conftest.py` counts as suite-wide, like the root's own.
+    pub global_conftest_dirs: Vec<String>,
+    /// Match changed paths to indexed files ignoring case (e.g. git reporting `Pkg/Foo.py` for
+    /// `pkg/foo.py`). Implied when the root is on a case-insensitive filesystem.
+    pub ignore_path_case: bool,
+    /// A changed `__init__.py` seeds only its own module, not every module of the package.
+    pub strict_package_init: bool,
+    /// Record every seed that reaches each test in [`TestResult::seeds`] (one extra walk per
+    /// seed), not just the first.
+    pub all_seeds: bool,
+}
+
 impl ProjectIndex {
+    /// Positional shorthand for [`Self::impacted_tests_with`], kept for existing callers. New
+    /// knobs only go on [`ImpactedOptions`].
     pub fn impacted_tests(
         &self,
         changed: &[Utf8PathBuf],
@@ -24,54 +95,89 @@ impl ProjectIndex {
         quiet: bool,
         warn_as_error: bool,
     ) -> Result<Vec<TestResult>> {
+        self.impacted_tests_with(
+            changed,
+            &ImpactedOptions {
+                max,
+                distance_limit,
+                quiet,
+                warn_as_error,
+                ..ImpactedOptions::default()
+            },
+        )
+    }
+
+    /// Tests impacted by the `changed` files (absolute paths), most relevant first.
+    pub fn impacted_tests_with(
+        &self,
+        changed: &[Utf8PathBuf],
+        opts: &ImpactedOptions,
+    ) -> Result<Vec<TestResult>> {
+        Ok(self.impacted_tests_summarized(changed, opts)?.0)
+    }
+
+    /// [`Self::impacted_tests_with`], plus this selection's warnings counted by kind, for the
+    /// caller to report once per run.
+    pub fn impacted_tests_summarized(
+        &self,
+        changed: &[Utf8PathBuf],
+        opts: &ImpactedOptions,
+    ) -> Result<(Vec<TestResult>, WarningSummary)> {
+        let include = glob_set(&opts.include)?;
+        let exclude = glob_set(&opts.exclude)?;
         let mut warnings = self.warnings.clone();
 
-        let top_levels: HashSet<&str> = self
-            .modules
-            .keys()
-            .filter_map(|name| name.split('.').next())
-            .collect();
+        let changed: Vec<Utf8PathBuf> = changed.iter().map(|path| self.rebase(path)).collect();
+        let mut seeds: Vec<String> = Vec::new();
+        // Positions in `seeds` of modules guessed for deleted or unindexed files.
+        let mut deleted_seeds: HashSet<usize> = HashSet::new();
+        // Reported apart from `warnings`: a deleted file is routine, not a --warn-as-error.
+        let mut unindexed: Vec<Warning> = Vec::new();
 
-        let mut reverse: HashMap<String, HashSet<String>> = HashMap::default();
-        for info in self.modules.values() {
-            for import in &info.imports {
-                let target = self
-                    .resolve_known_module(import)
-                    .or_else(|| self.heuristic_map(import))
-                    .or_else(|| self.trim_to_known_module(import))
-                    .unwrap_or_else(|| {
-                        if top_levels.contains(import.split('.').next().unwrap_or("")) {
-                            warnings.push(format!(
-                                "Unresolved import `{}` in module `{}`",
-                                import, info.module
-                            ));
-                        }
-                        // Keep the raw import string so missing modules (e.g., deleted files)
-                        // still participate in the reverse graph.
-                        import.to_string()
-                    });
-                reverse
-                    .entry(target)
-                    .or_default()
-                    .insert(info.module.clone());
-            }
-        }
+        let fold_case = opts.ignore_path_case || case_insensitive_fs(&self.root);
+        // Only needed for unindexed changes; built on the first one.
+        let mut prefixes = None;
+        let mut folded = None;
 
-        if !quiet {
-            for w in &warnings {
-                eprintln!("Warning: {w}");
+        for path in &changed {
+            if opts.changed_tests_only && !is_test_file(path.as_std_path()) {
+                continue;
+            }
+            if self.ignore_files.is_ignored(path) {
+                continue;
+            }
+            // Root-scoped fixtures reach every test, importer or not.
+            if self.is_global_conftest(path, &opts.global_conftest_dirs) {
+                seeds.extend(self.test_modules_under(self.root.as_std_path()));
+                continue;
+            }
+            // Any other conftest serves the tests in its directory subtree, as pytest collects
+            // them; importers of it (rare) are still seeded below.
+            if path.file_name() == Some("conftest.py") {
+                if let Some(dir) = path.parent() {
+                    seeds.extend(self.test_modules_under(dir.as_std_path()));
+                }
+            }
+            let mut module = self.path_to_module.get(path);
+            if module.is_none() && fold_case {
+                let folded = folded.get_or_insert_with(|| self.folded_paths());
+                module = folded.get(&path.as_str().to_lowercase()).copied().flatten();
+            }
+            if let Some(module) = module {
+                // Re-exports and package-level state reach everything under the package.
+                if !opts.strict_package_init && path.file_stem() == Some("__init__") {
+                    seeds.extend(self.package_subtree(module));
+                }
+                seeds.push(module.clone());
+                continue;
             }
-        }
-
-        let mut impacted_modules: HashSet<String> = HashSet::new();
-        let mut distances: HashMap<String, usize> = HashMap::default();
-        let mut queue: VecDeque<String> = VecDeque::new();
 
-        for path in changed {
-            if let Some(module) = self.path_to_module.get(path) {
-                if impacted_modules.insert(module.clone()) {
-                    distances.insert(module.clone(), 0);
-                    queue.push_back(module.clone());
+            if !is_python_file(path.as_std_path()) {
+                if let Some(readers) = self.data_readers.get(path) {
+                    seeds.extend(readers.iter().cloned());
+                }
+                if let Some(scope) = fixture_scope(path.as_std_path(), &opts.fixture_dirs) {
+                    seeds.extend(self.test_modules_under(&scope));
                 }
                 continue;
             }
@@ -79,118 +185,459 @@ impl ProjectIndex {
             // Handle Python files that no longer exist or failed to parse (e.g., `git rm`).
             // We approximate a module name from the path and resolve it using the same
             // heuristics as for imports, then seed the graph from that module.
-            if path.extension().map(|ext| ext == "py").unwrap_or(false) {
-                let guessed_module = module_name(&self.root, path.as_ref());
-                let target = self
-                    .resolve_known_module(&guessed_module)
-                    .or_else(|| self.heuristic_map(&guessed_module))
-                    .or_else(|| self.trim_to_known_module(&guessed_module))
-                    .unwrap_or(guessed_module.clone());
-
-                if impacted_modules.insert(target.clone()) {
-                    distances.insert(target.clone(), 0);
-                    queue.push_back(target);
-                }
+            let prefixes = prefixes.get_or_insert_with(|| self.package_prefixes());
+            let guessed_module = self.guess_module(path, prefixes);
+            let target = self
+                .resolve(&guessed_module)
+                .unwrap_or(guessed_module.clone());
+            deleted_seeds.insert(seeds.len());
+            seeds.push(target);
+            unindexed.push(Warning::UnindexedChange {
+                path: path.clone(),
+                guessed_module,
+            });
+        }
 
-                if !quiet {
-                    eprintln!(
-                        "Warning: changed file not indexed (using module `{}`): {}",
-                        guessed_module, path
-                    );
-                }
+        // The symbol's module, and the symbol tests reached from it must reference.
+        let mut symbol_filter: Option<(String, &str)> = None;
+        if let Some((module, symbol)) = &opts.changed_symbol {
+            let Some(target) = self.resolve(module) else {
+                anyhow::bail!(
+                    "Module `{module}` is not indexed under {}; check --root",
+                    self.root
+                );
+            };
+            seeds.push(target.clone());
+            symbol_filter = Some((target, symbol.as_str()));
+        }
+
+        let top_levels: HashSet<&str> = self
+            .modules
+            .keys()
+            .filter_map(|name| name.split('.').next())
+            .collect();
+
+        // Without transitive traversal only edges into the seeds can ever be followed.
+        let frontier: Option<HashSet<&str>> = opts
+            .no_transitive
+            .then(|| seeds.iter().map(String::as_str).collect());
+
+        let (reverse, unresolved) = self.reverse_graph(frontier.as_ref());
+        for (import, module) in &unresolved {
+            if top_levels.contains(import.split('.').next().unwrap_or("")) {
+                warnings.push(Warning::UnresolvedImport {
+                    import: import.clone(),
+                    module: module.clone(),
+                });
             }
         }
+        let summary = WarningSummary::of(warnings.iter().chain(&unindexed));
 
-        while let Some(module) = queue.pop_front() {
-            let current_dist = distances.get(&module).copied().unwrap_or(0);
-            if let Some(limit) = distance_limit {
-                if current_dist >= limit {
-                    continue; // prune beyond limit
-                }
+        if !opts.quiet {
+            for line in render_warnings(&warnings, opts.max_warnings) {
+                diag::warning(line);
+            }
+            for warning in &unindexed {
+                diag::warning(warning);
             }
+        }
 
-            if let Some(children) = reverse.get(&module) {
-                for dep in children {
-                    if impacted_modules.insert(dep.clone()) {
-                        let dist = current_dist + 1;
-                        distances.insert(dep.clone(), dist);
-                        queue.push_back(dep.clone());
-                    }
-                }
+        if opts.explain_unresolved {
+            for line in render_unresolved(&group_unresolved(&unresolved, &top_levels)) {
+                diag::info(line);
             }
         }
 
+        let distance_limit = if opts.no_transitive {
+            Some(opts.distance_limit.map_or(1, |limit| limit.min(1)))
+        } else {
+            opts.distance_limit
+        };
+
+        let (distances, origins) = match opts.deleted_distance_limit {
+            Some(cap) => {
+                let (deleted, indexed): (Vec<_>, Vec<_>) = seeds
+                    .iter()
+                    .enumerate()
+                    .partition(|(i, _)| deleted_seeds.contains(i));
+                let walk = |seeds: Vec<(usize, &String)>, limit| {
+                    let seeds: Vec<String> = seeds.into_iter().map(|(_, s)| s.clone()).collect();
+                    walk_importers(&reverse, &seeds, limit)
+                };
+                let cap = distance_limit.map_or(cap, |limit| limit.min(cap));
+                merge_walks(walk(indexed, distance_limit), walk(deleted, Some(cap)))
+            }
+            None => walk_importers(&reverse, &seeds, distance_limit),
+        };
+        let reaching = if opts.all_seeds {
+            let limited: Vec<(&String, Option<usize>)> = seeds
+                .iter()
+                .enumerate()
+                .map(|(i, seed)| match opts.deleted_distance_limit {
+                    Some(cap) if deleted_seeds.contains(&i) => (
+                        seed,
+                        Some(distance_limit.map_or(cap, |limit| limit.min(cap))),
+                    ),
+                    _ => (seed, distance_limit),
+                })
+                .collect();
+            reaching_seeds(&reverse, &limited)
+        } else {
+            HashMap::new()
+        };
+
         let changed_leaves: HashSet<String> = distances
-            .iter()
-            .filter(|(m, _)| impacted_modules.contains(*m))
-            .filter_map(|(m, _)| m.split('.').last().map(str::to_string))
+            .keys()
+            .filter_map(|m| m.split('.').last().map(str::to_string))
             .collect();
 
         let mut tests: Vec<TestResult> = Vec::new();
 
-        for module in impacted_modules {
-            if let Some(info) = self.modules.get(&module) {
-                if is_test_file(info.path.as_std_path()) {
+        // Visit in name order so nothing downstream can depend on hash iteration order.
+        let mut reached: Vec<&String> = distances.keys().collect();
+        reached.sort();
+        for module in reached {
+            if let Some(info) = self.modules.get(module) {
+                // Only parse for the symbol once we know this is a test; tests reached from
+                // another changed file are kept as they are.
+                if is_test_file(info.path.as_std_path())
+                    && symbol_filter.as_ref().is_none_or(|(target, symbol)| {
+                        origins.get(module) != Some(target)
+                            || references_name(info.path.as_std_path(), symbol)
+                    })
+                {
                     if let Ok(rel) = info.path.strip_prefix(&self.root) {
                         let p = priority(
                             rel.as_str(),
-                            distances.get(&module).copied().unwrap_or(usize::MAX),
+                            distances.get(module).copied().unwrap_or(usize::MAX),
                             &changed_leaves,
+                            opts.history.get(rel.as_str()).copied().unwrap_or(0),
+                            opts.previous_failures.contains(rel.as_str(), module),
                         );
                         tests.push(TestResult {
                             path: rel.to_string(),
+                            module: module.clone(),
+                            seed: origins.get(module).cloned().unwrap_or_default(),
+                            seeds: reaching.get(module).cloned().unwrap_or_default(),
                             priority: p,
-                            distance: distances.get(&module).copied().unwrap_or(usize::MAX),
+                            distance: distances.get(module).copied().unwrap_or(usize::MAX),
                         });
                     } else {
                         let p = priority(
                             info.path.as_str(),
-                            distances.get(&module).copied().unwrap_or(usize::MAX),
+                            distances.get(module).copied().unwrap_or(usize::MAX),
                             &changed_leaves,
+                            opts.history.get(info.path.as_str()).copied().unwrap_or(0),
+                            opts.previous_failures.contains(info.path.as_str(), module),
                         );
                         tests.push(TestResult {
                             path: info.path.to_string(),
+                            module: module.clone(),
+                            seed: origins.get(module).cloned().unwrap_or_default(),
+                            seeds: reaching.get(module).cloned().unwrap_or_default(),
                             priority: p,
-                            distance: distances.get(&module).copied().unwrap_or(usize::MAX),
+                            distance: distances.get(module).copied().unwrap_or(usize::MAX),
                         });
                     }
                 }
             }
         }
 
+        if let Some(threshold) = opts.min_filename_match {
+            tests.retain(|t| t.priority.filename_match <= threshold);
+        }
+        if let Some(include) = &include {
+            tests.retain(|t| include.is_match(&t.path));
+        }
+        if let Some(exclude) = &exclude {
+            tests.retain(|t| !exclude.is_match(&t.path));
+        }
+
+        // Stat each selected file once up front rather than on every comparison.
+        let mtimes: HashMap<String, SystemTime> = if opts.order_by_mtime {
+            tests
+                .iter()
+                .filter_map(|t| {
+                    let modified = fs::metadata(self.root.join(&t.path))
+                        .and_then(|m| m.modified())
+                        .ok()?;
+                    Some((t.path.clone(), modified))
+                })
+                .collect()
+        } else {
+            HashMap::default()
+        };
+
+        // A total order (paths are unique), so the `max` cut below is the same on every run.
         tests.sort_by(|a, b| {
             a.priority
                 .cmp(&b.priority)
+                .then_with(|| mtimes.get(&b.path).cmp(&mtimes.get(&a.path)))
                 .then_with(|| a.path.cmp(&b.path))
         });
-        if let Some(limit) = max {
+        if let Some(limit) = opts.max {
             tests.truncate(limit);
         }
-        if warn_as_error && !warnings.is_empty() {
+        if opts.warn_as_error && !warnings.is_empty() {
             anyhow::bail!(
                 "Warnings treated as errors ({} warnings). First: {}",
                 warnings.len(),
                 warnings[0]
             );
         }
-        Ok(tests)
+        Ok((tests, summary))
     }
 
-    fn heuristic_map(&self, import: &str) -> Option<String> {
-        let candidate = import.replace('.', "/");
-        let file = self.root.join(format!("{candidate}.py"));
-        if file.exists() {
-            if let Some(module) = self.path_to_module.get(&file) {
-                return Some(module.clone());
+    /// Indexed paths lowercased, each with its module; `None` where several paths fold to the
+    /// same one (which only a case-sensitive filesystem allows).
+    fn folded_paths(&self) -> HashMap<String, Option<&String>> {
+        let mut folded = HashMap::new();
+        for (path, module) in &self.path_to_module {
+            folded
+                .entry(path.as_str().to_lowercase())
+                .and_modify(|known| *known = None)
+                .or_insert(Some(module));
+        }
+        folded
+    }
+
+    /// `path` spelled under `self.root`, the prefix indexed paths share. A changed path may
+    /// reach the root through a symlink the root's own spelling avoids, or the other way
+    /// round (macOS's `/tmp` is `/private/tmp`); both sides are compared canonicalized.
+    fn rebase(&self, path: &Utf8Path) -> Utf8PathBuf {
+        if path.starts_with(&self.root) {
+            return path.to_owned();
+        }
+        let Ok(canonical_root) = self.root.canonicalize_utf8() else {
+            return path.to_owned();
+        };
+        let canonical = canonicalize_lenient(path.as_std_path().to_path_buf());
+        canonical
+            .strip_prefix(&canonical_root)
+            .ok()
+            .and_then(Utf8Path::from_path)
+            .map_or_else(|| path.to_owned(), |relative| self.root.join(relative))
+    }
+
+    /// Module name for a changed file that is not indexed (deleted, or failed to parse).
+    /// Walking up for `__init__.py` breaks once a whole package directory is gone, so this
+    /// borrows the module prefix of an indexed file in the nearest surviving ancestor
+    /// directory; the guess then matches what the index would have named the file under any
+    /// layout (`src/`, nested roots, ...). Falls back to [`module_name`].
+    fn guess_module(&self, path: &Utf8Path, prefixes: &HashMap<&Utf8Path, Vec<String>>) -> String {
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            let Some(mut parts) = prefixes.get(dir).cloned() else {
+                continue;
+            };
+            let Ok(rel) = path.strip_prefix(dir) else {
+                break;
+            };
+            parts.extend(rel.components().map(|c| c.as_str().to_string()));
+            if let Some(last) = parts.last_mut() {
+                if let Some(stem) = last
+                    .strip_suffix(".py")
+                    .or_else(|| last.strip_suffix(".pyi"))
+                {
+                    *last = stem.to_string();
+                }
             }
+            if parts.last().is_some_and(|last| last == "__init__") {
+                parts.pop();
+            }
+            return parts.join(".");
+        }
+        module_name(import_root(&self.root, &self.source_roots, path), path)
+    }
+
+    /// Every indexed module below package `package` (excluding the package itself), sorted.
+    fn package_subtree(&self, package: &str) -> Vec<String> {
+        if package.is_empty() {
+            return Vec::new();
         }
-        let init = self.root.join(format!("{candidate}/__init__.py"));
-        if init.exists() {
-            if let Some(module) = self.path_to_module.get(&init) {
-                return Some(module.clone());
+        let prefix = format!("{package}.");
+        let mut modules: Vec<String> = self
+            .modules
+            .keys()
+            .filter(|name| name.starts_with(&prefix))
+            .cloned()
+            .collect();
+        modules.sort();
+        modules
+    }
+
+    /// Dotted prefix that indexed files directly inside each directory share, e.g. `["pkg"]`
+    /// for `src/pkg` when `src/pkg/__init__.py` is indexed as `pkg`. Should the files of a
+    /// directory disagree, its `__init__.py` decides, then the smallest path.
+    fn package_prefixes(&self) -> HashMap<&Utf8Path, Vec<String>> {
+        fn rank(info: &ModuleInfo) -> (bool, &Utf8Path) {
+            (
+                info.path.file_stem() != Some("__init__"),
+                info.path.as_path(),
+            )
+        }
+        let mut chosen: HashMap<&Utf8Path, &ModuleInfo> = HashMap::new();
+        for info in self.modules.values() {
+            let Some(dir) = info.path.parent() else {
+                continue;
+            };
+            let best = chosen.entry(dir).or_insert(info);
+            if rank(info) < rank(*best) {
+                *best = info;
             }
         }
-        None
+        chosen
+            .into_iter()
+            .map(|(dir, info)| {
+                let mut parts: Vec<String> = info.module.split('.').map(str::to_string).collect();
+                if info.path.file_stem() != Some("__init__") {
+                    parts.pop();
+                }
+                (dir, parts)
+            })
+            .collect()
+    }
+
+    fn is_global_conftest(&self, path: &Utf8PathBuf, global_dirs: &[String]) -> bool {
+        if path.file_name() != Some("conftest.py") {
+            return false;
+        }
+        let Some(dir) = path.parent() else {
+            return false;
+        };
+        dir == self.root
+            || global_dirs
+                .iter()
+                .any(|global| dir == self.root.join(global.trim_end_matches('/')))
+    }
+
+    /// Every module that transitively imports `module` (not just tests), with its distance,
+    /// sorted by distance then name. The module itself is not included.
+    pub fn importers_of(
+        &self,
+        module: &str,
+        distance_limit: Option<usize>,
+    ) -> Result<Vec<(String, usize)>> {
+        let Some(target) = self.resolve(module) else {
+            anyhow::bail!(
+                "Module `{module}` is not indexed under {}; check --root",
+                self.root
+            );
+        };
+        let (reverse, _) = self.reverse_graph(None);
+        let (distances, _) = walk_importers(&reverse, &[target], distance_limit);
+
+        let mut importers: Vec<(String, usize)> = distances
+            .into_iter()
+            .filter(|(_, dist)| *dist > 0)
+            .collect();
+        importers.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(importers)
+    }
+
+    /// Edges of the reverse import graph that selection walks, as sorted `(imported, importer)`
+    /// pairs between indexed modules. With `from`, only the part reachable from that module
+    /// (its transitive importers) is kept.
+    pub fn import_edges(&self, from: Option<&str>) -> Vec<(String, String)> {
+        let (reverse, _) = self.reverse_graph(None);
+        let reachable = from.map(|module| walk_importers(&reverse, &[module.to_string()], None).0);
+        let mut edges: Vec<(String, String)> = reverse
+            .into_iter()
+            .filter(|(imported, _)| self.modules.contains_key(imported))
+            .filter(|(imported, _)| reachable.as_ref().is_none_or(|r| r.contains_key(imported)))
+            .flat_map(|(imported, importers)| {
+                importers
+                    .into_iter()
+                    .map(move |importer| (imported.clone(), importer))
+            })
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    /// Whether the indexed module `module` is a test file.
+    pub fn is_test_module(&self, module: &str) -> bool {
+        self.modules
+            .get(module)
+            .is_some_and(|info| is_test_file(info.path.as_std_path()))
+    }
+
+    /// Imported module -> importing modules. Edges are limited to targets in `frontier` when
+    /// given. Also returns the `(import, importer)` pairs that did not resolve to an indexed
+    /// module, sorted by importer and then import; those keep the raw import string as their
+    /// target.
+    pub(crate) fn reverse_graph(
+        &self,
+        frontier: Option<&HashSet<&str>>,
+    ) -> (HashMap<String, HashSet<String>>, Vec<(String, String)>) {
+        let mut unresolved: Vec<(String, String)> = Vec::new();
+        let mut reverse: HashMap<String, HashSet<String>> = HashMap::default();
+        for info in self.modules.values() {
+            for import in &info.imports {
+                let target = self.resolve(import).unwrap_or_else(|| {
+                    if !info.optional_imports.contains(import) {
+                        unresolved.push((import.clone(), info.module.clone()));
+                    }
+                    // Keep the raw import string so missing modules (e.g., deleted files)
+                    // still participate in the reverse graph.
+                    import.to_string()
+                });
+                if let Some(frontier) = frontier {
+                    if !frontier.contains(target.as_str()) {
+                        continue;
+                    }
+                }
+                reverse
+                    .entry(target)
+                    .or_default()
+                    .insert(info.module.clone());
+            }
+        }
+        unresolved.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+        (reverse, unresolved)
+    }
+
+    /// Indexed test modules whose file lives under `dir`, sorted by module name.
+    fn test_modules_under(&self, dir: &Path) -> Vec<String> {
+        let mut found: Vec<String> = self
+            .modules
+            .values()
+            .filter(|info| {
+                info.path.as_std_path().starts_with(dir) && is_test_file(info.path.as_std_path())
+            })
+            .map(|info| info.module.clone())
+            .collect();
+        found.sort();
+        found
+    }
+
+    /// Resolve a dotted import to an indexed module: exact match, then a file-path lookup under
+    /// the root, then the longest indexed prefix. `None` if nothing in the project matches.
+    ///
+    /// `from pkg import name` is recorded as `pkg.name`, so the tiers also disambiguate it:
+    /// a submodule `pkg/name.py` matches exactly, while a symbol defined in `pkg/__init__.py`
+    /// is not a module and falls back to the package `pkg`.
+    pub fn resolve(&self, import: &str) -> Option<String> {
+        self.resolve_known_module(import)
+            .or_else(|| self.heuristic_map(import))
+            .or_else(|| self.trim_to_known_module(import))
+    }
+
+    fn heuristic_map(&self, import: &str) -> Option<String> {
+        let candidate = import.replace('.', "/");
+        ["py", "pyi"]
+            .iter()
+            .flat_map(|ext| {
+                [
+                    format!("{candidate}.{ext}"),
+                    format!("{candidate}/__init__.{ext}"),
+                ]
+            })
+            .map(|rel| self.root.join(rel))
+            .filter(|path| path.exists())
+            .find_map(|path| self.path_to_module.get(&path).cloned())
     }
 
     fn resolve_known_module(&self, import: &str) -> Option<String> {
@@ -211,3 +658,210 @@ impl ProjectIndex {
         None
     }
 }
+
+/// Probe whether `root` lives on a case-insensitive filesystem (macOS, Windows defaults) by
+/// looking it up again with the case of its final component flipped: only then is the probe
+/// the same file (device and inode) as `root`.
+fn case_insensitive_fs(root: &Utf8Path) -> bool {
+    let Some(name) = root.file_name() else {
+        return false;
+    };
+    let flipped: String = name
+        .chars()
+        .map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                c.to_uppercase().next().unwrap_or(c)
+            }
+        })
+        .collect();
+    if flipped == name {
+        return false;
+    }
+    same_file::is_same_file(root, root.with_file_name(flipped)).unwrap_or(false)
+}
+
+/// Breadth-first walk from `seeds` along importer edges. Returns each reached module's
+/// distance and the seed it was first reached from.
+fn walk_importers(
+    reverse: &HashMap<String, HashSet<String>>,
+    seeds: &[String],
+    distance_limit: Option<usize>,
+) -> (HashMap<String, usize>, HashMap<String, String>) {
+    let mut distances: HashMap<String, usize> = HashMap::default();
+    let mut origins: HashMap<String, String> = HashMap::default();
+    let mut queue: VecDeque<String> = VecDeque::new();
+
+    for module in seeds {
+        if !distances.contains_key(module) {
+            distances.insert(module.clone(), 0);
+            origins.insert(module.clone(), module.clone());
+            queue.push_back(module.clone());
+        }
+    }
+
+    while let Some(module) = queue.pop_front() {
+        let current_dist = distances.get(&module).copied().unwrap_or(0);
+        if let Some(limit) = distance_limit {
+            if current_dist >= limit {
+                continue; // prune beyond limit
+            }
+        }
+
+        if let Some(children) = reverse.get(&module) {
+            let origin = origins.get(&module).cloned().unwrap_or_default();
+            // Sorted, so a module reachable from several seeds at the same distance always
+            // credits the same one.
+            let mut children: Vec<&String> = children.iter().collect();
+            children.sort();
+            for dep in children {
+                if !distances.contains_key(dep) {
+                    distances.insert(dep.clone(), current_dist + 1);
+                    origins.insert(dep.clone(), origin.clone());
+                    queue.push_back(dep.clone());
+                }
+            }
+        }
+    }
+    (distances, origins)
+}
+
+/// Every seed reaching each module with its shortest distance from that seed, nearest first
+/// (then by name): one [`walk_importers`] per seed, each with its own distance limit.
+fn reaching_seeds(
+    reverse: &HashMap<String, HashSet<String>>,
+    seeds: &[(&String, Option<usize>)],
+) -> HashMap<String, Vec<(String, usize)>> {
+    let mut shortest: HashMap<(String, String), usize> = HashMap::new();
+    for (seed, limit) in seeds {
+        let (distances, _) = walk_importers(reverse, std::slice::from_ref(*seed), *limit);
+        for (module, distance) in distances {
+            let known = shortest
+                .entry((module, (*seed).clone()))
+                .or_insert(distance);
+            *known = (*known).min(distance);
+        }
+    }
+    let mut reaching: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+    for ((module, seed), distance) in shortest {
+        reaching.entry(module).or_default().push((seed, distance));
+    }
+    for edges in reaching.values_mut() {
+        edges.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    }
+    reaching
+}
+
+/// Combine two [`walk_importers`] results, keeping each module's shorter distance and the
+/// origin that goes with it (`first`'s on ties).
+fn merge_walks(
+    first: (HashMap<String, usize>, HashMap<String, String>),
+    second: (HashMap<String, usize>, HashMap<String, String>),
+) -> (HashMap<String, usize>, HashMap<String, String>) {
+    let (mut distances, mut origins) = first;
+    let (more_distances, mut more_origins) = second;
+    for (module, distance) in more_distances {
+        if distances.get(&module).is_none_or(|&known| distance < known) {
+            if let Some(origin) = more_origins.remove(&module) {
+                origins.insert(module.clone(), origin);
+            }
+            distances.insert(module, distance);
+        }
+    }
+    (distances, origins)
+}
+
+/// Warnings of a selection by kind, for the summary line at the end of a run.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WarningSummary {
+    pub unresolved: usize,
+    /// Deleted or unparsable changed files; reported, but not counted by `warn_as_error`.
+    pub unindexed: usize,
+    /// Everything the index reported (parse failures, name collisions, ...).
+    pub other: usize,
+}
+
+impl AddAssign for WarningSummary {
+    fn add_assign(&mut self, rhs: Self) {
+        self.unresolved += rhs.unresolved;
+        self.unindexed += rhs.unindexed;
+        self.other += rhs.other;
+    }
+}
+
+impl WarningSummary {
+    fn of<'a>(warnings: impl IntoIterator<Item = &'a Warning>) -> Self {
+        let mut summary = Self::default();
+        for warning in warnings {
+            match warning {
+                Warning::UnresolvedImport { .. } => summary.unresolved += 1,
+                Warning::UnindexedChange { .. } => summary.unindexed += 1,
+                _ => summary.other += 1,
+            }
+        }
+        summary
+    }
+
+    /// The warnings `warn_as_error` fails on: all but the unindexed files.
+    pub fn counted(&self) -> usize {
+        self.unresolved + self.other
+    }
+
+    /// `N warnings (M unresolved imports[, J other]); K unindexed files`, leaving out an empty
+    /// half; `None` without any. `N` is [`counted`](Self::counted).
+    pub fn render(&self) -> Option<String> {
+        let total = self.counted();
+        let plural = |n: usize, noun: &str| {
+            if n == 1 {
+                format!("{n} {noun}")
+            } else {
+                format!("{n} {noun}s")
+            }
+        };
+        let mut parts = Vec::new();
+        if total > 0 {
+            let mut counted = format!(
+                "{} ({}",
+                plural(total, "warning"),
+                plural(self.unresolved, "unresolved import")
+            );
+            if self.other > 0 {
+                counted.push_str(&format!(", {} other", self.other));
+            }
+            counted.push(')');
+            parts.push(counted);
+        }
+        if self.unindexed > 0 {
+            parts.push(plural(self.unindexed, "unindexed file"));
+        }
+        (!parts.is_empty()).then(|| parts.join("; "))
+    }
+}
+
+/// Render warning messages for stderr (without the `Warning:` prefix), keeping at most `max_warnings` of them (0 = no cap).
+/// The remainder is summarized in a trailing "… and N more" line.
+pub(crate) fn render_warnings(warnings: &[impl Display], max_warnings: usize) -> Vec<String> {
+    let shown = if max_warnings == 0 {
+        warnings.len()
+    } else {
+        warnings.len().min(max_warnings)
+    };
+    let mut lines: Vec<String> = warnings[..shown].iter().map(|w| w.to_string()).collect();
+    if shown < warnings.len() {
+        lines.push(format!("… and {} more", warnings.len() - shown));
+    }
+    lines
+}
+
+/// One matcher ORing `patterns`, or `None` when there are none.
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("Invalid glob `{pattern}`"))?);
+    }
+    Ok(Some(builder.build()?))
+}
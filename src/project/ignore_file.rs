@@ -0,0 +1,45 @@
+//! `.testdiffignore`: gitignore-style patterns for files that never impact tests.
+
+use std::path::Path;
+
+use camino::Utf8Path;
+use ignore::gitignore::Gitignore;
+
+use crate::project::Warning;
+
+/// File name looked up in every scanned directory, like `.gitignore`.
+pub(crate) const IGNORE_FILE: &str = ".testdiffignore";
+
+/// The `.testdiffignore` files seen while indexing. The walker applies them to the tree
+/// itself; this applies them to changed paths, which need not exist any more.
+#[derive(Default)]
+pub struct IgnoreFiles {
+    /// Deepest directory first, so a nested file overrides its ancestors like git does.
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreFiles {
+    /// Load the ignore file at `path`; unreadable or malformed lines are reported in `warnings`.
+    pub(super) fn add(&mut self, path: &Path, warnings: &mut Vec<Warning>) {
+        let (matcher, err) = Gitignore::new(path);
+        if let Some(err) = err {
+            warnings.push(Warning::Config {
+                detail: format!("Problem reading {}: {err}", path.display()),
+            });
+        }
+        self.matchers.push(matcher);
+        self.matchers
+            .sort_by_key(|m| std::cmp::Reverse(m.path().components().count()));
+    }
+
+    /// Whether a `.testdiffignore` excludes `path` (or one of its directories).
+    pub fn is_ignored(&self, path: &Utf8Path) -> bool {
+        let path = path.as_std_path();
+        self.matchers
+            .iter()
+            .filter(|m| path.starts_with(m.path()))
+            .map(|m| m.matched_path_or_any_parents(path, false))
+            .find(|m| !m.is_none())
+            .is_some_and(|m| m.is_ignore())
+    }
+}
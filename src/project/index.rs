@@ -1,122 +1,563 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::io::IsTerminal;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use ignore::WalkBuilder;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::ThreadPoolBuilder;
+use rayon::prelude::*;
 use ruff_python_ast as ast;
 use ruff_python_ast::visitor::{self, Visitor};
 use ruff_python_parser::parse_module;
 
-use crate::project::resolve::{ImportSpec, module_name, resolve_import};
-use crate::project::utils::{filter_dir, is_python_file};
+use crate::paths::is_python_file;
+use crate::project::Warning;
+use crate::project::cache::{ParseCache, Stamp};
+use crate::project::ignore_file::{IGNORE_FILE, IgnoreFiles};
+use crate::project::resolve::{
+    ImportSpec, escapes_package, module_name, namespace_module_name, resolve_data_ref,
+    resolve_import,
+};
+use crate::project::utils::{filter_dir_with, is_stub_file};
 
 pub struct ModuleInfo {
     pub module: String,
     pub path: Utf8PathBuf,
     pub imports: Vec<String>,
+    /// Imports only made inside `try: ... except ImportError:`; they stay in the graph but are
+    /// not reported when they fail to resolve.
+    pub optional_imports: HashSet<String>,
+}
+
+/// A parsed file whose imports are resolved only once every module name is settled, since
+/// relative imports depend on the (possibly namespace-qualified) name of the importer.
+struct ParsedFile {
+    module: String,
+    path: Utf8PathBuf,
+    imports: Vec<ImportSpec>,
+    /// Names listed in a module-level `__all__`.
+    exports: Vec<String>,
+    /// String literals opened as files (`open("...")`, `Path("...")`, `base / "..."`).
+    data_refs: Vec<String>,
+    /// Set when a cache is in use, so the file can be written back to it.
+    stamp: Option<Stamp>,
 }
 
 pub struct ProjectIndex {
     pub root: Utf8PathBuf,
     pub modules: HashMap<String, ModuleInfo>,
     pub path_to_module: HashMap<Utf8PathBuf, String>,
-    pub warnings: Vec<String>,
+    /// Import roots below `root` (e.g. `src/`) that module names are relative to.
+    pub source_roots: Vec<Utf8PathBuf>,
+    /// `.testdiffignore` patterns; changed files they match seed nothing.
+    pub ignore_files: IgnoreFiles,
+    /// Data file -> modules whose source opens it by a literal path (only with `data_deps`).
+    pub data_readers: HashMap<Utf8PathBuf, Vec<String>>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Knobs for [`ProjectIndex::build_with`].
+#[derive(Clone, Debug, Default)]
+pub struct IndexOptions {
+    /// Abort once more than this many Python files are found (guards against huge roots).
+    pub max_files: Option<usize>,
+    /// Show a progress bar on stderr while parsing (ignored when stderr is not a terminal).
+    pub progress: bool,
+    /// Parser threads; `None` uses the available parallelism and `Some(1)` parses serially on
+    /// the calling thread.
+    pub jobs: Option<NonZeroUsize>,
+    /// Root-relative import roots (e.g. `src`); empty detects them from `pyproject.toml`'s
+    /// `[tool.setuptools.packages.find]` or a `src/` directory.
+    pub source_roots: Vec<String>,
+    /// Directory names to skip in addition to the built-in list, matched on the basename.
+    pub extra_skip_dirs: Vec<String>,
+    /// Directory holding the parse cache; files whose mtime and size are unchanged since
+    /// the last build reuse their cached imports instead of being parsed again.
+    pub cache_dir: Option<Utf8PathBuf>,
+    /// Keep imports made under `if TYPE_CHECKING:` as graph edges (dropped by default, since
+    /// they are not runtime dependencies).
+    pub type_checking_imports: bool,
+    /// Record the data files modules open by literal path (`open("tests/data.json")`), so a
+    /// changed data file selects the modules reading it. Only paths that exist are kept.
+    pub data_deps: bool,
 }
 
 impl ProjectIndex {
     pub fn build(root: &Utf8Path) -> Result<Self> {
+        Self::build_with(root, &IndexOptions::default())
+    }
+
+    pub fn build_with(root: &Utf8Path, opts: &IndexOptions) -> Result<Self> {
         let mut modules = HashMap::default();
         let mut path_to_module = HashMap::default();
         let mut warnings = Vec::new();
+        let mut files: Vec<PathBuf> = Vec::new();
+        let mut ignore_files = IgnoreFiles::default();
+
+        let source_roots = if opts.source_roots.is_empty() {
+            detect_source_roots(root).unwrap_or_else(|err| {
+                warnings.push(Warning::Config {
+                    detail: format!("Ignoring source roots from pyproject.toml: {err:#}"),
+                });
+                Vec::new()
+            })
+        } else {
+            opts.source_roots.iter().map(|dir| root.join(dir)).collect()
+        };
 
+        // Walk in name order so file order (and with it warning order and collision
+        // tie-breaks) does not depend on the filesystem's directory listing order.
         for entry in WalkBuilder::new(root)
+            .sort_by_file_name(|a, b| a.cmp(b))
             .hidden(false)
             .ignore(true)
             .git_ignore(true)
             .git_exclude(true)
             .parents(true)
-            .filter_entry(|e| filter_dir(e.path()))
+            .add_custom_ignore_filename(IGNORE_FILE)
+            .filter_entry(|e| filter_dir_with(e.path(), &opts.extra_skip_dirs))
             .build()
         {
             let entry = match entry {
                 Ok(e) => e,
                 Err(err) => {
-                    warnings.push(format!("Skipping entry: {err}"));
+                    warnings.push(Warning::WalkError {
+                        detail: err.to_string(),
+                    });
                     continue;
                 }
             };
+            if entry.file_name() == IGNORE_FILE {
+                ignore_files.add(entry.path(), &mut warnings);
+            }
             if !is_python_file(entry.path()) {
                 continue;
             }
 
-            match Self::parse_file(root, entry.path(), &mut warnings) {
-                Ok(Some(info)) => {
-                    path_to_module.insert(info.path.clone(), info.module.clone());
-                    modules.insert(info.module.clone(), info);
+            files.push(entry.into_path());
+            if let Some(limit) = opts.max_files {
+                if files.len() > limit {
+                    anyhow::bail!(
+                        "More than {limit} Python files under {root}; use --root to narrow the scan or raise --max-files"
+                    );
                 }
+            }
+        }
+
+        let progress = if opts.progress && std::io::stderr().is_terminal() {
+            let bar = ProgressBar::new(files.len() as u64);
+            bar.set_style(
+                ProgressStyle::with_template("Indexing {bar:40} {pos}/{len} files")
+                    .expect("progress template should be valid"),
+            );
+            bar
+        } else {
+            ProgressBar::hidden()
+        };
+
+        let cache = opts
+            .cache_dir
+            .as_deref()
+            .map(|dir| ParseCache::load(dir, root));
+        let parse_one = |path: &PathBuf| {
+            let mut file_warnings = Vec::new();
+            let result = Self::parse_file(
+                root,
+                &source_roots,
+                cache.as_ref(),
+                path,
+                &mut file_warnings,
+            );
+            progress.inc(1);
+            (result, file_warnings)
+        };
+        // Results come back in walk order either way, so merging below is deterministic.
+        let parsed: Vec<_> = if opts.jobs.is_some_and(|jobs| jobs.get() == 1) {
+            files.iter().map(parse_one).collect()
+        } else {
+            ThreadPoolBuilder::new()
+                .num_threads(opts.jobs.map_or(0, NonZeroUsize::get))
+                .build()
+                .context("Failed to start the parser thread pool")?
+                .install(|| files.par_iter().map(parse_one).collect())
+        };
+        progress.finish_and_clear();
+
+        let mut parsed_files = Vec::new();
+        for (path, (result, file_warnings)) in files.iter().zip(parsed) {
+            warnings.extend(file_warnings);
+            match result {
+                Ok(Some(file)) => parsed_files.push(file),
                 Ok(None) => {}
-                Err(err) => warnings.push(format!("{}: {err}", entry.path().display())),
+                Err(err) => warnings.push(Warning::ReadError {
+                    path: path.clone(),
+                    detail: err.to_string(),
+                }),
+            }
+        }
+
+        if let Some(dir) = &opts.cache_dir {
+            // Rebuilt from this walk only, which evicts files deleted since the last run.
+            let mut fresh = ParseCache::default();
+            for file in &parsed_files {
+                if let Some(stamp) = file.stamp {
+                    fresh.insert(
+                        file.path.clone(),
+                        stamp,
+                        file.imports.clone(),
+                        file.exports.clone(),
+                        file.data_refs.clone(),
+                    );
+                }
+            }
+            if let Err(err) = fresh.save(dir, root) {
+                warnings.push(Warning::Config {
+                    detail: format!("Index cache not updated: {err:#}"),
+                });
             }
         }
 
+        let imported = imported_names(&parsed_files);
+        let named: Vec<(String, ParsedFile)> = parsed_files
+            .into_iter()
+            .map(|file| {
+                let import_root = import_root(root, &source_roots, &file.path);
+                let module =
+                    namespace_module_name(import_root, &file.path, &file.module, &imported)
+                        .unwrap_or_else(|| file.module.clone());
+                (module, file)
+            })
+            .collect();
+        // `from pkg import *` also imports the submodules a package lists in `__all__`.
+        let package_exports: HashMap<String, Vec<String>> = named
+            .iter()
+            .filter(|(_, file)| is_package_init(&file.path) && !file.exports.is_empty())
+            .map(|(module, file)| (module.clone(), file.exports.clone()))
+            .collect();
+        let known: HashSet<String> = named.iter().map(|(module, _)| module.clone()).collect();
+        let mut data_readers: HashMap<Utf8PathBuf, Vec<String>> = HashMap::new();
+
+        for (module, file) in named {
+            let is_package = is_package_init(&file.path);
+            let mut imports = Vec::new();
+            let mut optional = HashSet::new();
+            let mut required = HashSet::new();
+            for imp in &file.imports {
+                if imp.type_checking && !opts.type_checking_imports {
+                    continue;
+                }
+                let Some(target) = resolve_import(&module, is_package, imp) else {
+                    if escapes_package(&module, is_package, imp) {
+                        warnings.push(Warning::EscapingRelativeImport {
+                            import: format!(
+                                "{}{}",
+                                ".".repeat(imp.level as usize),
+                                imp.module.as_deref().unwrap_or_default()
+                            ),
+                            module: module.clone(),
+                        });
+                    }
+                    continue;
+                };
+                let start = imports.len();
+                if imp.name.as_deref() == Some("*") {
+                    if let Some(exports) = package_exports.get(&target) {
+                        imports.extend(exports.iter().map(|name| format!("{target}.{name}")));
+                    }
+                }
+                // `import a.b.c` runs `a/__init__.py` and `a/b/__init__.py` first, and so does
+                // `from a.b import c`.
+                imports.extend(
+                    parent_packages(&target)
+                        .filter(|parent| known.contains(*parent))
+                        .map(str::to_string),
+                );
+                imports.push(target);
+                let bucket = if imp.optional {
+                    &mut optional
+                } else {
+                    &mut required
+                };
+                bucket.extend(imports[start..].iter().cloned());
+            }
+            if opts.data_deps {
+                for literal in &file.data_refs {
+                    for data in resolve_data_ref(root, &file.path, literal) {
+                        let readers = data_readers.entry(data).or_default();
+                        if !readers.contains(&module) {
+                            readers.push(module.clone());
+                        }
+                    }
+                }
+            }
+            let info = ModuleInfo {
+                module,
+                path: file.path,
+                imports,
+                optional_imports: optional.difference(&required).cloned().collect(),
+            };
+            path_to_module.insert(info.path.clone(), info.module.clone());
+            insert_module(&mut modules, info, &mut warnings);
+        }
+
         Ok(Self {
             root: root.to_owned(),
             modules,
             path_to_module,
+            source_roots,
+            ignore_files,
+            data_readers,
             warnings,
         })
     }
 
     fn parse_file(
         root: &Utf8Path,
+        source_roots: &[Utf8PathBuf],
+        cache: Option<&ParseCache>,
         path: &Path,
-        warnings: &mut Vec<String>,
-    ) -> Result<Option<ModuleInfo>> {
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Option<ParsedFile>> {
         let utf8_path = match Utf8PathBuf::from_path_buf(path.to_path_buf()) {
             Ok(p) => p,
             Err(_) => return Ok(None),
         };
+        let module = module_name(import_root(root, source_roots, &utf8_path), &utf8_path);
+
+        let stamp = cache.and_then(|_| Stamp::of(path));
+        if let (Some(cache), Some(stamp)) = (cache, stamp) {
+            if let Some((imports, exports, data_refs)) = cache.get(&utf8_path, stamp) {
+                return Ok(Some(ParsedFile {
+                    module,
+                    path: utf8_path,
+                    imports: imports.to_vec(),
+                    exports: exports.to_vec(),
+                    data_refs: data_refs.to_vec(),
+                    stamp: Some(stamp),
+                }));
+            }
+        }
 
         let source = fs::read_to_string(path)
             .with_context(|| format!("Failed to read {}", path.display()))?;
 
-        let parsed = match parse_module(&source) {
+        // Editors on Windows may save a UTF-8 BOM, which the parser rejects.
+        let source = source.strip_prefix('\u{feff}').unwrap_or(&source);
+        let parsed = match parse_module(source) {
             Ok(parsed) => parsed,
             Err(err) => {
-                warnings.push(format!("Failed to parse {}: {err}", path.display()));
+                warnings.push(Warning::ParseError {
+                    path: path.to_path_buf(),
+                    detail: err.to_string(),
+                });
                 return Ok(None);
             }
         };
 
         let mut collector = ImportCollector::default();
+        let mut exports = Vec::new();
         for stmt in &parsed.syntax().body {
             collector.visit_stmt(stmt);
+            collect_dunder_all(stmt, &mut exports);
         }
 
-        let module = module_name(root, &utf8_path);
-        let is_package = utf8_path
-            .file_stem()
-            .map(|s| s == "__init__")
-            .unwrap_or(false);
-        let imports = collector
-            .imports
-            .into_iter()
-            .filter_map(|imp| resolve_import(&module, is_package, &imp))
-            .collect();
-
-        Ok(Some(ModuleInfo {
+        Ok(Some(ParsedFile {
             module,
             path: utf8_path,
-            imports,
+            imports: collector.imports,
+            exports,
+            data_refs: collector.data_refs,
+            stamp,
         }))
     }
 }
 
+/// `a` and `a.b` for `a.b.c`, outermost first.
+fn parent_packages(module: &str) -> impl Iterator<Item = &str> {
+    module.match_indices('.').map(|(end, _)| &module[..end])
+}
+
+fn is_package_init(path: &Utf8Path) -> bool {
+    path.file_stem().map(|s| s == "__init__").unwrap_or(false)
+}
+
+/// Insert `info`, resolving dotted-name collisions (e.g. `pkg.py` next to `pkg/__init__.py`)
+/// deterministically: the package wins, otherwise the lexicographically smaller path.
+/// Both paths stay in `path_to_module`, so either one can still seed selection.
+///
+/// A `.pyi` stub next to its implementation is expected, not a collision: the implementation
+/// is kept without a warning, and editing the stub seeds the same module.
+fn insert_module(
+    modules: &mut HashMap<String, ModuleInfo>,
+    info: ModuleInfo,
+    warnings: &mut Vec<Warning>,
+) {
+    let Some(existing) = modules.get(&info.module) else {
+        modules.insert(info.module.clone(), info);
+        return;
+    };
+
+    let stub = |m: &ModuleInfo| is_stub_file(m.path.as_std_path());
+    if stub(&info) != stub(existing) {
+        if stub(existing) {
+            modules.insert(info.module.clone(), info);
+        }
+        return;
+    }
+
+    let key = |m: &ModuleInfo| (!is_package_init(&m.path), m.path.clone());
+    let replace = key(&info) < key(existing);
+    let (kept, dropped) = if replace {
+        (&info.path, &existing.path)
+    } else {
+        (&existing.path, &info.path)
+    };
+    warnings.push(Warning::ModuleCollision {
+        module: info.module.clone(),
+        kept: kept.clone(),
+        dropped: dropped.clone(),
+    });
+
+    if replace {
+        modules.insert(info.module.clone(), info);
+    }
+}
+
+/// Extend `exports` with the string literals of a module-level `__all__ = [...]`,
+/// `__all__ += [...]` or `__all__: list[str] = [...]` (lists or tuples).
+fn collect_dunder_all(stmt: &ast::Stmt, exports: &mut Vec<String>) {
+    let is_all = |target: &ast::Expr| matches!(target, ast::Expr::Name(name) if name.id.as_str() == "__all__");
+    let (value, replace) = match stmt {
+        ast::Stmt::Assign(assign) if assign.targets.iter().any(is_all) => (&*assign.value, true),
+        ast::Stmt::AnnAssign(ast::StmtAnnAssign {
+            target,
+            value: Some(value),
+            ..
+        }) if is_all(target) => (&**value, true),
+        ast::Stmt::AugAssign(ast::StmtAugAssign {
+            target,
+            op: ast::Operator::Add,
+            value,
+            ..
+        }) if is_all(target) => (&**value, false),
+        _ => return,
+    };
+    let elts = match value {
+        ast::Expr::List(list) => &list.elts,
+        ast::Expr::Tuple(tuple) => &tuple.elts,
+        _ => return,
+    };
+    if replace {
+        exports.clear();
+    }
+    exports.extend(elts.iter().filter_map(|elt| match elt {
+        ast::Expr::StringLiteral(s) => Some(s.value.to_str().to_string()),
+        _ => None,
+    }));
+}
+
+/// Every dotted prefix named by an absolute import anywhere in the project (`a.b.c` yields
+/// `a`, `a.b` and `a.b.c`); this is what decides whether namespace directories are part of
+/// a module's name.
+fn imported_names(files: &[ParsedFile]) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for spec in files.iter().flat_map(|f| &f.imports) {
+        if spec.level > 0 {
+            continue;
+        }
+        let Some(target) = resolve_import("", false, spec) else {
+            continue;
+        };
+        let mut prefix = String::new();
+        for part in target.split('.') {
+            if !prefix.is_empty() {
+                prefix.push('.');
+            }
+            prefix.push_str(part);
+            names.insert(prefix.clone());
+        }
+    }
+    names
+}
+
 #[derive(Default)]
 struct ImportCollector {
     imports: Vec<ImportSpec>,
+    /// Inside the body of an `if TYPE_CHECKING:` block.
+    type_checking: bool,
+    /// Inside the body of a `try:` with an `except ImportError:` handler.
+    optional: bool,
+    /// String literals used as file paths; resolved against the tree only with `data_deps`.
+    data_refs: Vec<String>,
+}
+
+/// A callee that takes a file path first: `open`, `Path`/`PurePath` and friends, bare or as an
+/// attribute (`pathlib.Path`, `io.open`, `Image.open`).
+fn opens_path(func: &ast::Expr) -> bool {
+    let name = match func {
+        ast::Expr::Name(name) => name.id.as_str(),
+        ast::Expr::Attribute(attr) => attr.attr.as_str(),
+        _ => return false,
+    };
+    matches!(
+        name,
+        "open" | "Path" | "PurePath" | "PosixPath" | "WindowsPath"
+    )
+}
+
+/// The literal tail of a `/` chain, e.g. `fixtures/data.json` for
+/// `Path(__file__).parent / "fixtures" / "data.json"`.
+fn joined_literal(expr: &ast::Expr) -> Option<String> {
+    match expr {
+        ast::Expr::StringLiteral(s) => Some(s.value.to_str().to_string()),
+        ast::Expr::BinOp(ast::ExprBinOp {
+            left,
+            op: ast::Operator::Div,
+            right,
+            ..
+        }) => {
+            let tail = joined_literal(right)?;
+            Some(match joined_literal(left) {
+                Some(head) => format!("{head}/{tail}"),
+                None => tail,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A `try` handler that catches `ImportError` or `ModuleNotFoundError` (alone or in a tuple).
+fn catches_import_error(handler: &ast::ExceptHandler) -> bool {
+    let ast::ExceptHandler::ExceptHandler(handler) = handler;
+    let is_import_error = |expr: &ast::Expr| {
+        matches!(expr, ast::Expr::Name(name)
+            if matches!(name.id.as_str(), "ImportError" | "ModuleNotFoundError"))
+    };
+    match handler.type_.as_deref() {
+        Some(ast::Expr::Tuple(tuple)) => tuple.elts.iter().any(is_import_error),
+        Some(expr) => is_import_error(expr),
+        None => false,
+    }
+}
+
+/// `TYPE_CHECKING` or `typing.TYPE_CHECKING` (also `typing_extensions.TYPE_CHECKING`).
+fn is_type_checking_guard(test: &ast::Expr) -> bool {
+    match test {
+        ast::Expr::Name(name) => name.id.as_str() == "TYPE_CHECKING",
+        ast::Expr::Attribute(attr) => {
+            attr.attr.as_str() == "TYPE_CHECKING"
+                && matches!(
+                    attr.value.as_ref(),
+                    ast::Expr::Name(module)
+                        if matches!(module.id.as_str(), "typing" | "typing_extensions")
+                )
+        }
+        _ => false,
+    }
 }
 
 impl<'a> Visitor<'a> for ImportCollector {
@@ -129,6 +570,8 @@ impl<'a> Visitor<'a> for ImportCollector {
                         module: Some(alias.name.to_string()),
                         name: None,
                         kind: super::resolve::ImportKind::Import,
+                        type_checking: self.type_checking,
+                        optional: self.optional,
                     });
                 }
             }
@@ -144,12 +587,66 @@ impl<'a> Visitor<'a> for ImportCollector {
                         module: module.as_ref().map(|m| m.to_string()),
                         name: Some(alias.name.to_string()),
                         kind: super::resolve::ImportKind::ImportFrom,
+                        type_checking: self.type_checking,
+                        optional: self.optional,
                     });
                 }
             }
+            ast::Stmt::If(ast::StmtIf {
+                test,
+                body,
+                elif_else_clauses,
+                ..
+            }) if is_type_checking_guard(test) => {
+                let outer = std::mem::replace(&mut self.type_checking, true);
+                self.visit_body(body);
+                self.type_checking = outer;
+                // `else:` branches run when not type checking, so they stay runtime imports.
+                for clause in elif_else_clauses {
+                    self.visit_elif_else_clause(clause);
+                }
+                return;
+            }
+            ast::Stmt::Try(ast::StmtTry {
+                body,
+                handlers,
+                orelse,
+                finalbody,
+                ..
+            }) if handlers.iter().any(catches_import_error) => {
+                let outer = std::mem::replace(&mut self.optional, true);
+                self.visit_body(body);
+                self.optional = outer;
+                // Fallbacks in the handlers (and `else`/`finally`) are regular imports.
+                for handler in handlers {
+                    self.visit_except_handler(handler);
+                }
+                self.visit_body(orelse);
+                self.visit_body(finalbody);
+                return;
+            }
             _ => {}
         }
 
         visitor::walk_stmt(self, stmt);
     }
+
+    fn visit_expr(&mut self, expr: &'a ast::Expr) {
+        match expr {
+            ast::Expr::Call(call) if opens_path(&call.func) => {
+                if let Some(ast::Expr::StringLiteral(s)) = call.arguments.args.first() {
+                    self.data_refs.push(s.value.to_str().to_string());
+                }
+            }
+            ast::Expr::BinOp(ast::ExprBinOp {
+                op: ast::Operator::Div,
+                right,
+                ..
+            }) if matches!(right.as_ref(), ast::Expr::StringLiteral(_)) => {
+                self.data_refs.extend(joined_literal(expr));
+            }
+            _ => {}
+        }
+        visitor::walk_expr(self, expr);
+    }
 }
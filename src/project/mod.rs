@@ -1,9 +1,15 @@
-pub mod graph;
-pub mod index;
+mod cache;
+mod graph;
+mod ignore_file;
+mod index;
 mod resolve;
+mod symbols;
 #[cfg(test)]
 mod tests;
-pub(crate) mod utils;
+mod unresolved;
+mod utils;
+mod warning;
 
-pub use graph::TestResult;
-pub use index::ProjectIndex;
+pub use graph::{ImpactedOptions, TestResult, WarningSummary};
+pub use index::{IndexOptions, ModuleInfo, ProjectIndex};
+pub use warning::Warning;
@@ -1,4 +1,7 @@
-use camino::Utf8Path;
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 
 #[derive(Clone, Copy)]
 pub(super) enum ImportKind {
@@ -12,6 +15,164 @@ pub(super) struct ImportSpec {
     pub module: Option<String>,
     pub name: Option<String>,
     pub kind: ImportKind,
+    /// Nested in an `if TYPE_CHECKING:` block, i.e. only needed for type hints.
+    pub type_checking: bool,
+    /// In the body of a `try:` that catches `ImportError`, i.e. allowed to be missing.
+    pub optional: bool,
+}
+
+/// Directories that act as import roots inside `root`: the `where` entries of
+/// `[tool.setuptools.packages.find]` in `pyproject.toml`, else the `where` of
+/// `[options.packages.find]` or the root `package_dir` of `[options]` in `setup.cfg`, otherwise
+/// `src/` when it exists.
+pub(super) fn detect_source_roots(root: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let pyproject = root.join("pyproject.toml");
+    if pyproject.exists() {
+        let raw = std::fs::read_to_string(&pyproject)
+            .with_context(|| format!("Failed to read {pyproject}"))?;
+        let table: toml::Table =
+            toml::from_str(&raw).with_context(|| format!("Failed to parse {pyproject}"))?;
+        let wheres = table
+            .get("tool")
+            .and_then(|t| t.get("setuptools"))
+            .and_then(|s| s.get("packages"))
+            .and_then(|p| p.get("find"))
+            .and_then(|f| f.get("where"))
+            .and_then(|w| w.as_array());
+        if let Some(wheres) = wheres {
+            return Ok(wheres
+                .iter()
+                .filter_map(|w| w.as_str())
+                .map(|w| root.join(w))
+                .collect());
+        }
+    }
+
+    let setup_cfg = root.join("setup.cfg");
+    if setup_cfg.exists() {
+        let raw = std::fs::read_to_string(&setup_cfg)
+            .with_context(|| format!("Failed to read {setup_cfg}"))?;
+        if let Some(dirs) = setup_cfg_source_roots(&raw) {
+            return Ok(dirs.into_iter().map(|dir| root.join(dir)).collect());
+        }
+    }
+
+    let src = root.join("src");
+    Ok(if src.is_dir() { vec![src] } else { Vec::new() })
+}
+
+/// Import roots declared in a `setup.cfg`: `where` under `[options.packages.find]`, else the
+/// directory mapped to the root package (`= src`) by `package_dir` under `[options]`.
+fn setup_cfg_source_roots(raw: &str) -> Option<Vec<String>> {
+    let entries = |value: String| -> Vec<String> {
+        value
+            .split(['\n', ','])
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    if let Some(wheres) = ini_value(raw, "options.packages.find", "where").map(entries) {
+        return Some(wheres);
+    }
+    let package_dir = ini_value(raw, "options", "package_dir").map(entries)?;
+    package_dir
+        .iter()
+        .filter_map(|entry| entry.split_once('='))
+        .find(|(package, _)| package.trim().is_empty())
+        .map(|(_, dir)| vec![dir.trim().to_string()])
+}
+
+/// The value of `key` in `[section]` of an INI file, with indented continuation lines joined
+/// by newlines. Comments (`#`, `;`) and blank lines are skipped.
+fn ini_value(raw: &str, section: &str, key: &str) -> Option<String> {
+    let mut in_section = false;
+    let mut value: Option<String> = None;
+    for line in raw.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(['#', ';']) {
+            continue;
+        }
+        let continuation = line.starts_with([' ', '\t']);
+        if let Some(value) = value.as_mut().filter(|_| continuation) {
+            value.push('\n');
+            value.push_str(trimmed);
+            continue;
+        }
+        if value.is_some() {
+            break;
+        }
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            in_section = name.trim() == section;
+        } else if in_section {
+            let Some((name, rest)) = trimmed.split_once(['=', ':']) else {
+                continue;
+            };
+            if name.trim() == key {
+                value = Some(rest.trim().to_string());
+            }
+        }
+    }
+    value
+}
+
+/// The directory module names of `path` are relative to: the deepest source root containing
+/// it, or `root` itself.
+pub(super) fn import_root<'a>(
+    root: &'a Utf8Path,
+    source_roots: &'a [Utf8PathBuf],
+    path: &Utf8Path,
+) -> &'a Utf8Path {
+    source_roots
+        .iter()
+        .filter(|dir| path.starts_with(dir))
+        .max_by_key(|dir| dir.components().count())
+        .map_or(root, |dir| dir.as_path())
+}
+
+/// The existing files under `root` that the path literal `literal` in the module at `path` may
+/// name: relative to the module's directory, or to `root` (where pytest usually runs).
+pub(super) fn resolve_data_ref(
+    root: &Utf8Path,
+    path: &Utf8Path,
+    literal: &str,
+) -> Vec<Utf8PathBuf> {
+    let relative = Utf8Path::new(literal);
+    if literal.is_empty() || relative.is_absolute() {
+        return Vec::new();
+    }
+    let mut found = Vec::new();
+    for base in [path.parent().unwrap_or(root), root] {
+        let Some(candidate) = normalize_lexically(&base.join(relative)) else {
+            continue;
+        };
+        if candidate.starts_with(root) && candidate.is_file() && !found.contains(&candidate) {
+            found.push(candidate);
+        }
+    }
+    found
+}
+
+/// `path` with `.` and `..` components folded away; `None` if it climbs above its start.
+fn normalize_lexically(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let mut out = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    Some(out)
+}
+
+/// A regular package: `__init__.py`, or only an `__init__.pyi` for stub-only packages.
+fn is_package_dir(dir: &Utf8Path) -> bool {
+    dir.join("__init__.py").exists() || dir.join("__init__.pyi").exists()
 }
 
 pub(super) fn module_name(root: &Utf8Path, path: &Utf8Path) -> String {
@@ -19,7 +180,7 @@ pub(super) fn module_name(root: &Utf8Path, path: &Utf8Path) -> String {
     let mut current = path.parent();
 
     while let Some(dir) = current {
-        if dir.join("__init__.py").exists() {
+        if is_package_dir(dir) {
             if let Some(name) = dir.file_name() {
                 package_parts.push(name.to_string());
             }
@@ -49,13 +210,65 @@ pub(super) fn module_name(root: &Utf8Path, path: &Utf8Path) -> String {
     let rel = path.strip_prefix(root).unwrap_or(path);
     let mut components: Vec<String> = rel.components().map(|c| c.as_str().to_string()).collect();
     if let Some(last) = components.last_mut() {
-        if let Some(stripped) = last.strip_suffix(".py") {
+        if let Some(stripped) = last
+            .strip_suffix(".py")
+            .or_else(|| last.strip_suffix(".pyi"))
+        {
             *last = stripped.to_string();
         }
     }
     components.join(".")
 }
 
+/// PEP 420 name for `path` (already named `module` by [`module_name`]) when its top-level
+/// regular package sits inside implicit namespace directories, e.g. `company.foo.bar` for
+/// `company/foo/bar.py` with `company/foo/__init__.py` but no `company/__init__.py`.
+///
+/// The namespace directories are only prepended when some absolute import in the project
+/// (`imported` holds every dotted prefix of those) names the package through them, so plain
+/// subdirectories keep today's names. The longest such prefix wins, which is also the name
+/// `module_name` gives once the namespace directory gains an `__init__.py`.
+pub(super) fn namespace_module_name(
+    root: &Utf8Path,
+    path: &Utf8Path,
+    module: &str,
+    imported: &HashSet<String>,
+) -> Option<String> {
+    let top_package = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| is_package_dir(dir))
+        .last()?;
+    let namespaces: Vec<&str> = top_package
+        .parent()?
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|c| c.as_str())
+        .collect();
+    let package = module.split('.').next().filter(|p| !p.is_empty())?;
+
+    (0..namespaces.len()).find_map(|start| {
+        let prefix = namespaces[start..].join(".");
+        imported
+            .contains(&format!("{prefix}.{package}"))
+            .then(|| format!("{prefix}.{module}"))
+    })
+}
+
+/// How many trailing parts of the importing module a relative import drops: a package
+/// (`__init__`) is its own anchor, any other module is anchored at its parent package.
+fn package_pops(is_package: bool, spec: &ImportSpec) -> usize {
+    spec.level as usize - usize::from(is_package)
+}
+
+/// A relative import with more leading dots than `current_module` has enclosing packages,
+/// like `from ... import x` in `pkg.mod` (Python's "attempted relative import beyond
+/// top-level package").
+pub(super) fn escapes_package(current_module: &str, is_package: bool, spec: &ImportSpec) -> bool {
+    spec.level > 0 && package_pops(is_package, spec) >= current_module.split('.').count()
+}
+
 pub(super) fn resolve_import(
     current_module: &str,
     is_package: bool,
@@ -66,13 +279,12 @@ pub(super) fn resolve_import(
 
     let base = if relative {
         let mut parts: Vec<&str> = current_module.split('.').collect();
-        // If current module is a package (__init__), don't pop the last part when handling relative imports.
-        if !(is_package && spec.level == 1) {
-            let pops = spec.level.min(parts.len() as u32) as usize;
-            for _ in 0..pops {
-                parts.pop();
-            }
+        // Outside its package, an over-deep import fails at runtime; resolving it to some
+        // other module would hide that.
+        if escapes_package(current_module, is_package, spec) {
+            return None;
         }
+        parts.truncate(parts.len() - package_pops(is_package, spec));
         parts
     } else {
         Vec::new()
@@ -86,7 +298,8 @@ pub(super) fn resolve_import(
         }
     }
 
-    // `from x import y` should resolve to `x.y` (unless y is "*").
+    // `from x import y` should resolve to `x.y` (unless y is "*"). Whether `y` is a submodule
+    // or a symbol of `x` is only known once the index exists; see `ProjectIndex::resolve`.
     if let ImportKind::ImportFrom = spec.kind {
         if let Some(name) = &spec.name {
             if name != "*" {
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+
+use ruff_python_ast as ast;
+use ruff_python_ast::visitor::{self, Visitor};
+use ruff_python_parser::parse_module;
+
+/// Whether the module at `path` mentions `name` as a bare name, an attribute, or an imported
+/// name. Unreadable or unparsable files count as a match so they are never silently dropped.
+pub(crate) fn references_name(path: &Path, name: &str) -> bool {
+    let Ok(source) = fs::read_to_string(path) else {
+        return true;
+    };
+    let Ok(parsed) = parse_module(&source) else {
+        return true;
+    };
+
+    let mut finder = NameFinder { name, found: false };
+    for stmt in &parsed.syntax().body {
+        finder.visit_stmt(stmt);
+        if finder.found {
+            return true;
+        }
+    }
+    false
+}
+
+struct NameFinder<'n> {
+    name: &'n str,
+    found: bool,
+}
+
+impl<'a> Visitor<'a> for NameFinder<'_> {
+    fn visit_stmt(&mut self, stmt: &'a ast::Stmt) {
+        if let ast::Stmt::ImportFrom(ast::StmtImportFrom { names, .. }) = stmt {
+            if names.iter().any(|alias| alias.name.as_str() == self.name) {
+                self.found = true;
+            }
+        }
+        visitor::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a ast::Expr) {
+        match expr {
+            ast::Expr::Name(ast::ExprName { id, .. }) if id.as_str() == self.name => {
+                self.found = true;
+            }
+            ast::Expr::Attribute(ast::ExprAttribute { attr, .. }) if attr.as_str() == self.name => {
+                self.found = true;
+            }
+            _ => {}
+        }
+        visitor::walk_expr(self, expr);
+    }
+}
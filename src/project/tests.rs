@@ -1,6 +1,12 @@
-use super::index::ProjectIndex;
+use super::graph::ImpactedOptions;
+use super::graph::{WarningSummary, render_warnings};
+use super::index::{IndexOptions, ProjectIndex};
+use super::resolve::{ImportKind, ImportSpec, resolve_import};
+use super::unresolved::{UnresolvedGroup, group_unresolved};
 use super::utils::is_test_file;
+use super::warning::Warning;
 use camino::{Utf8Path, Utf8PathBuf};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
 use tempfile::tempdir;
 
@@ -107,6 +113,58 @@ fn deleted_python_file_still_impacts_importers() {
     );
 }
 
+#[test]
+fn deleted_distance_limit_caps_only_deleted_seeds() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let removed = write_file(root, "pkg/core.py", "");
+    write_file(root, "pkg/mid.py", "from pkg import core\n");
+    let edited = write_file(root, "pkg/other.py", "");
+    write_file(root, "pkg/other_mid.py", "from pkg import other\n");
+    write_file(root, "tests/test_core.py", "from pkg import core\n");
+    write_file(root, "tests/test_mid.py", "from pkg import mid\n");
+    write_file(
+        root,
+        "tests/test_other_mid.py",
+        "from pkg import other_mid\n",
+    );
+    std::fs::remove_file(removed.as_std_path()).unwrap();
+
+    let index = ProjectIndex::build(root).unwrap();
+    let select = |deleted_distance_limit| {
+        let opts = ImpactedOptions {
+            quiet: true,
+            deleted_distance_limit,
+            ..ImpactedOptions::default()
+        };
+        let mut paths: Vec<String> = index
+            .impacted_tests_with(&[removed.clone(), edited.clone()], &opts)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect();
+        paths.sort();
+        paths
+    };
+
+    assert_eq!(
+        select(None),
+        [
+            "tests/test_core.py",
+            "tests/test_mid.py",
+            "tests/test_other_mid.py"
+        ]
+    );
+    // The deleted module reaches only its direct importers; the edited one still walks on.
+    assert_eq!(
+        select(Some(1)),
+        ["tests/test_core.py", "tests/test_other_mid.py"]
+    );
+}
+
 #[test]
 fn deleted_top_level_module_impacts_importers() {
     let tmp = tempdir().unwrap();
@@ -158,3 +216,1610 @@ fn conftest_is_not_considered_a_test() {
         "test_*.py should be treated as a test file"
     );
 }
+
+#[test]
+fn render_warnings_truncates_with_summary() {
+    let warnings: Vec<String> = (0..5).map(|i| format!("w{i}")).collect();
+
+    let capped = render_warnings(&warnings, 2);
+    assert_eq!(capped, vec!["w0", "w1", "… and 3 more"]);
+
+    let uncapped = render_warnings(&warnings, 0);
+    assert_eq!(uncapped.len(), 5);
+    assert_eq!(render_warnings(&warnings, 5).len(), 5);
+}
+
+#[test]
+fn resolve_applies_each_tier() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    write_file(root, "pkg/foo.py", "def f():\n    return 1\n");
+    // No `lib/__init__.py`, so this module is indexed as `sub.mod`.
+    write_file(root, "lib/sub/__init__.py", "");
+    write_file(root, "lib/sub/mod.py", "");
+
+    let index = ProjectIndex::build(root).unwrap();
+
+    assert_eq!(index.resolve("pkg.foo").as_deref(), Some("pkg.foo"));
+    assert_eq!(index.resolve("lib.sub.mod").as_deref(), Some("sub.mod"));
+    assert_eq!(index.resolve("pkg.foo.f").as_deref(), Some("pkg.foo"));
+    assert_eq!(index.resolve("requests.get"), None);
+}
+
+#[test]
+fn from_import_distinguishes_submodules_from_symbols() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let init = write_file(root, "pkg/__init__.py", "class SomeClass:\n    pass\n");
+    let sub = write_file(root, "pkg/sub.py", "def f():\n    return 1\n");
+    write_file(root, "tests/test_sub.py", "from pkg import sub\n");
+    write_file(root, "tests/test_cls.py", "from pkg import SomeClass\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.resolve("pkg.sub").as_deref(), Some("pkg.sub"));
+    assert_eq!(index.resolve("pkg.SomeClass").as_deref(), Some("pkg"));
+
+    // Both forms resolve without warnings, so this must not bail. Strict mode keeps the
+    // `__init__.py` change from seeding `pkg.sub`, but `from pkg import sub` still runs
+    // `pkg/__init__.py`.
+    let opts = ImpactedOptions {
+        quiet: true,
+        warn_as_error: true,
+        strict_package_init: true,
+        ..ImpactedOptions::default()
+    };
+
+    let impacted = index.impacted_tests_with(&[sub], &opts).unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_sub.py"]);
+
+    let impacted = index.impacted_tests_with(&[init], &opts).unwrap();
+    let mut names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["tests/test_cls.py", "tests/test_sub.py"]);
+}
+
+#[test]
+fn no_transitive_selects_only_direct_importers() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let core_path = write_file(root, "pkg/core.py", "def core():\n    return 1\n");
+    write_file(root, "pkg/service.py", "from pkg import core\n");
+    write_file(root, "tests/test_core.py", "from pkg import core\n");
+    write_file(root, "tests/test_service.py", "from pkg import service\n");
+    let changed_test = write_file(root, "tests/test_other.py", "def test_ok():\n    pass\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let changed = vec![core_path, changed_test];
+    let opts = ImpactedOptions {
+        quiet: true,
+        no_transitive: true,
+        ..ImpactedOptions::default()
+    };
+    let impacted = index.impacted_tests_with(&changed, &opts).unwrap();
+
+    let mut names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["tests/test_core.py", "tests/test_other.py"]);
+}
+
+#[test]
+fn min_filename_match_drops_low_confidence_tests() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let changed_path = write_file(root, "pkg/foo.py", "def f():\n    return 1\n");
+    write_file(root, "tests/test_foo.py", "from pkg import foo\n");
+    write_file(root, "tests/test_other.py", "from pkg import foo\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let changed = vec![changed_path];
+    let mut opts = ImpactedOptions {
+        quiet: true,
+        ..ImpactedOptions::default()
+    };
+
+    let all = index.impacted_tests_with(&changed, &opts).unwrap();
+    assert_eq!(all.len(), 2);
+
+    opts.min_filename_match = Some(0);
+    let strict = index.impacted_tests_with(&changed, &opts).unwrap();
+    let names: Vec<_> = strict.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_foo.py"]);
+}
+
+#[test]
+fn changed_tests_only_ignores_source_changes() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let source = write_file(root, "pkg/foo.py", "def f():\n    return 1\n");
+    write_file(root, "tests/__init__.py", "");
+    let helper_test = write_file(root, "tests/test_helpers.py", "def make():\n    return 1\n");
+    write_file(root, "tests/test_foo.py", "from pkg import foo\n");
+    write_file(
+        root,
+        "tests/test_uses_helpers.py",
+        "from tests import test_helpers\n",
+    );
+
+    let index = ProjectIndex::build(root).unwrap();
+    let opts = ImpactedOptions {
+        quiet: true,
+        changed_tests_only: true,
+        ..ImpactedOptions::default()
+    };
+
+    let source_only = index.impacted_tests_with(&[source.clone()], &opts).unwrap();
+    assert!(source_only.is_empty());
+
+    let impacted = index
+        .impacted_tests_with(&[source, helper_test], &opts)
+        .unwrap();
+    let mut names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["tests/test_helpers.py", "tests/test_uses_helpers.py"]
+    );
+}
+
+#[test]
+fn max_files_aborts_oversized_index() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    for i in 0..3 {
+        write_file(root, &format!("mod_{i}.py"), "");
+    }
+
+    let capped = IndexOptions {
+        max_files: Some(2),
+        ..IndexOptions::default()
+    };
+    let err = ProjectIndex::build_with(root, &capped)
+        .err()
+        .expect("expected the file cap to abort the build");
+    assert!(
+        err.to_string().contains("--root"),
+        "unexpected error: {err}"
+    );
+
+    let roomy = IndexOptions {
+        max_files: Some(3),
+        ..IndexOptions::default()
+    };
+    assert_eq!(
+        ProjectIndex::build_with(root, &roomy)
+            .unwrap()
+            .modules
+            .len(),
+        3
+    );
+}
+
+#[test]
+fn fixture_change_selects_sibling_tests() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let fixture = write_file(root, "tests/fixtures/data.json", "{}");
+    write_file(root, "tests/test_load.py", "def test_load():\n    pass\n");
+    write_file(
+        root,
+        "tests/sub/test_nested.py",
+        "def test_nested():\n    pass\n",
+    );
+    write_file(
+        root,
+        "other/test_unrelated.py",
+        "def test_other():\n    pass\n",
+    );
+
+    let index = ProjectIndex::build(root).unwrap();
+    let changed = vec![fixture];
+
+    let off = ImpactedOptions {
+        quiet: true,
+        ..ImpactedOptions::default()
+    };
+    assert!(
+        index
+            .impacted_tests_with(&changed, &off)
+            .unwrap()
+            .is_empty()
+    );
+
+    let on = ImpactedOptions {
+        fixture_dirs: vec!["fixtures".to_string()],
+        ..off
+    };
+    let impacted = index.impacted_tests_with(&changed, &on).unwrap();
+    let mut names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["tests/sub/test_nested.py", "tests/test_load.py"]
+    );
+}
+
+#[test]
+fn unresolved_imports_group_by_top_level() {
+    let pairs = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(i, m)| (i.to_string(), m.to_string()))
+            .collect()
+    };
+    let unresolved = pairs(&[
+        ("requests.adapters", "pkg.http"),
+        ("requests", "pkg.client"),
+        ("requests", "pkg.http"),
+        ("pkg.missing", "pkg.core"),
+    ]);
+    let top_levels: HashSet<&str> = ["pkg"].into_iter().collect();
+
+    let groups = group_unresolved(&unresolved, &top_levels);
+    assert_eq!(
+        groups,
+        vec![
+            UnresolvedGroup {
+                top_level: "requests".to_string(),
+                count: 3,
+                first_party: false,
+                examples: vec!["pkg.client".to_string(), "pkg.http".to_string()],
+            },
+            UnresolvedGroup {
+                top_level: "pkg".to_string(),
+                count: 1,
+                first_party: true,
+                examples: vec!["pkg.core".to_string()],
+            },
+        ]
+    );
+}
+
+#[test]
+fn changed_symbol_keeps_only_referencing_tests() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    write_file(
+        root,
+        "pkg/foo.py",
+        "def bar():\n    return 1\n\ndef baz():\n    return 2\n",
+    );
+    write_file(
+        root,
+        "tests/test_bar.py",
+        "from pkg import foo\n\ndef test_bar():\n    assert foo.bar() == 1\n",
+    );
+    write_file(
+        root,
+        "tests/test_baz.py",
+        "from pkg.foo import baz\n\ndef test_baz():\n    assert baz() == 2\n",
+    );
+
+    let index = ProjectIndex::build(root).unwrap();
+    let opts = ImpactedOptions {
+        quiet: true,
+        changed_symbol: Some(("pkg.foo".to_string(), "bar".to_string())),
+        ..ImpactedOptions::default()
+    };
+    let impacted = index.impacted_tests_with(&[], &opts).unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_bar.py"]);
+}
+
+#[test]
+fn changed_symbol_leaves_tests_of_other_changes_alone() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    write_file(root, "pkg/foo.py", "def bar():\n    return 1\n");
+    let other = write_file(root, "pkg/other.py", "def qux():\n    return 3\n");
+    write_file(
+        root,
+        "tests/test_bar.py",
+        "from pkg import foo\n\ndef test_bar():\n    assert foo.bar() == 1\n",
+    );
+    write_file(
+        root,
+        "tests/test_foo_misc.py",
+        "from pkg import foo\n\ndef test_module():\n    assert foo\n",
+    );
+    write_file(
+        root,
+        "tests/test_other.py",
+        "from pkg.other import qux\n\ndef test_qux():\n    assert qux() == 3\n",
+    );
+
+    let index = ProjectIndex::build(root).unwrap();
+    let opts = ImpactedOptions {
+        quiet: true,
+        changed_symbol: Some(("pkg.foo".to_string(), "bar".to_string())),
+        ..ImpactedOptions::default()
+    };
+    let impacted = index.impacted_tests_with(&[other], &opts).unwrap();
+    let mut names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["tests/test_bar.py", "tests/test_other.py"]);
+}
+
+#[test]
+fn package_init_change_selects_bare_package_importers() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let init = write_file(root, "pkg/__init__.py", "VERSION = 1\n");
+    write_file(root, "pkg/foo.py", "def f():\n    return 1\n");
+    write_file(
+        root,
+        "tests/test_pkg.py",
+        "import pkg\n\ndef test_version():\n    assert pkg.VERSION == 1\n",
+    );
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(
+        index.path_to_module.get(&init).map(String::as_str),
+        Some("pkg")
+    );
+
+    let impacted = index
+        .impacted_tests(&[init], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_pkg.py"]);
+}
+
+#[test]
+fn package_wins_dotted_name_collision() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let init = write_file(root, "pkg/__init__.py", "");
+    let module = write_file(root, "pkg.py", "");
+    write_file(root, "tests/test_pkg.py", "import pkg\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.modules["pkg"].path, init);
+    assert_eq!(index.path_to_module[&module], "pkg");
+    assert_eq!(index.path_to_module[&init], "pkg");
+    assert!(
+        index
+            .warnings
+            .iter()
+            .any(|w| matches!(w, Warning::ModuleCollision { module, .. } if module == "pkg")),
+        "expected a collision warning, got {:?}",
+        index.warnings
+    );
+
+    // Either side of the collision seeds the package's importers.
+    for changed in [init, module] {
+        let impacted = index
+            .impacted_tests(&[changed], None, None, true, false)
+            .unwrap();
+        let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(names, vec!["tests/test_pkg.py"]);
+    }
+}
+
+#[test]
+fn failure_history_ranks_flaky_test_first() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let changed_path = write_file(root, "pkg/core.py", "X = 1\n");
+    write_file(root, "tests/test_alpha.py", "from pkg import core\n");
+    write_file(root, "tests/test_beta.py", "from pkg import core\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let changed = vec![changed_path];
+    let mut opts = ImpactedOptions {
+        quiet: true,
+        ..ImpactedOptions::default()
+    };
+
+    let plain = index.impacted_tests_with(&changed, &opts).unwrap();
+    let names: Vec<_> = plain.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_alpha.py", "tests/test_beta.py"]);
+
+    opts.history = HashMap::from([("tests/test_beta.py".to_string(), 4)]);
+    let ranked = index.impacted_tests_with(&changed, &opts).unwrap();
+    let names: Vec<_> = ranked.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_beta.py", "tests/test_alpha.py"]);
+}
+
+#[test]
+fn order_by_mtime_lists_recently_edited_tests_first() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let changed_path = write_file(root, "pkg/core.py", "X = 1\n");
+    let older = write_file(root, "tests/test_alpha.py", "from pkg import core\n");
+    let newer = write_file(root, "tests/test_beta.py", "from pkg import core\n");
+
+    let now = std::time::SystemTime::now();
+    for (path, age) in [(&older, 3600), (&newer, 60)] {
+        fs::File::options()
+            .write(true)
+            .open(path.as_std_path())
+            .unwrap()
+            .set_modified(now - std::time::Duration::from_secs(age))
+            .unwrap();
+    }
+
+    let index = ProjectIndex::build(root).unwrap();
+    let changed = vec![changed_path];
+    let mut opts = ImpactedOptions {
+        quiet: true,
+        ..ImpactedOptions::default()
+    };
+
+    let by_path = index.impacted_tests_with(&changed, &opts).unwrap();
+    let names: Vec<_> = by_path.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_alpha.py", "tests/test_beta.py"]);
+
+    opts.order_by_mtime = true;
+    let by_mtime = index.impacted_tests_with(&changed, &opts).unwrap();
+    let names: Vec<_> = by_mtime.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_beta.py", "tests/test_alpha.py"]);
+}
+
+#[test]
+fn root_conftest_selects_whole_suite() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let conftest = write_file(root, "conftest.py", "import pytest\n");
+    let nested = write_file(root, "tests/conftest.py", "import pytest\n");
+    let shared = write_file(root, "support/conftest.py", "import pytest\n");
+    write_file(root, "tests/test_a.py", "def test_a():\n    pass\n");
+    write_file(root, "tests/unit/test_b.py", "def test_b():\n    pass\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let mut opts = ImpactedOptions {
+        quiet: true,
+        ..ImpactedOptions::default()
+    };
+
+    let impacted = index.impacted_tests_with(&[conftest], &opts).unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_a.py", "tests/unit/test_b.py"]);
+
+    // A conftest below the root only reaches its own subtree.
+    let impacted = index.impacted_tests_with(&[nested], &opts).unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_a.py", "tests/unit/test_b.py"]);
+    assert!(
+        index
+            .impacted_tests_with(&[root.join("tests/unit/conftest.py")], &opts)
+            .unwrap()
+            .iter()
+            .all(|t| t.path == "tests/unit/test_b.py")
+    );
+
+    // `support/` holds no tests, so its conftest selects nothing...
+    assert!(
+        index
+            .impacted_tests_with(&[shared.clone()], &opts)
+            .unwrap()
+            .is_empty()
+    );
+
+    // ...unless its directory is configured as a global fixtures location.
+    opts.global_conftest_dirs = vec!["support".to_string()];
+    let impacted = index.impacted_tests_with(&[shared], &opts).unwrap();
+    assert_eq!(impacted.len(), 2);
+}
+
+#[test]
+fn deleted_subpackage_under_src_seeds_its_importers() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "src/pkg/__init__.py", "");
+    write_file(root, "src/pkg/core.py", "X = 1\n");
+    write_file(root, "tests/test_core.py", "from pkg import core\n");
+    write_file(
+        root,
+        "tests/test_legacy.py",
+        "from pkg.legacy import helpers\n",
+    );
+
+    // `git rm -r src/pkg/legacy`: neither the file nor its package directory exists anymore.
+    let deleted = root.join("src/pkg/legacy/helpers.py");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let impacted = index
+        .impacted_tests(&[deleted], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_legacy.py"]);
+}
+
+#[test]
+fn jobs_one_matches_default() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    for i in 0..5 {
+        write_file(
+            root,
+            &format!("pkg/mod{i}.py"),
+            &format!("from pkg import mod{}\n", (i + 1) % 5),
+        );
+        write_file(
+            root,
+            &format!("tests/test_mod{i}.py"),
+            &format!("from pkg import mod{i}\n"),
+        );
+    }
+    write_file(root, "pkg/broken.py", "def (:\n");
+
+    let serial = ProjectIndex::build_with(
+        root,
+        &IndexOptions {
+            jobs: std::num::NonZeroUsize::new(1),
+            ..IndexOptions::default()
+        },
+    )
+    .unwrap();
+    let default = ProjectIndex::build(root).unwrap();
+
+    let snapshot = |index: &ProjectIndex| {
+        let mut modules: Vec<_> = index
+            .modules
+            .values()
+            .map(|info| (info.module.clone(), info.path.clone(), info.imports.clone()))
+            .collect();
+        modules.sort();
+        (modules, index.warnings.clone())
+    };
+    assert_eq!(snapshot(&serial), snapshot(&default));
+
+    let changed = vec![root.join("pkg/mod3.py")];
+    let paths = |index: &ProjectIndex| -> Vec<String> {
+        index
+            .impacted_tests(&changed, None, None, true, false)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect()
+    };
+    assert_eq!(paths(&serial), paths(&default));
+}
+
+#[test]
+fn serial_and_parallel_builds_agree() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    for i in 0..20 {
+        write_file(
+            root,
+            &format!("pkg/mod{i}.py"),
+            &format!("from pkg import mod{}\n", (i + 1) % 20),
+        );
+        write_file(
+            root,
+            &format!("tests/test_mod{i}.py"),
+            &format!("from pkg import mod{i}\n"),
+        );
+    }
+    write_file(root, "pkg/broken.py", "def (:\n");
+    write_file(root, "pkg/another_broken.py", "class :\n");
+
+    let snapshot = |index: &ProjectIndex| {
+        let mut modules: Vec<_> = index
+            .modules
+            .values()
+            .map(|info| (info.module.clone(), info.path.clone(), info.imports.clone()))
+            .collect();
+        modules.sort();
+        let mut paths: Vec<_> = index.path_to_module.iter().collect();
+        paths.sort();
+        format!("{modules:?}\n{paths:?}\n{:?}", index.warnings)
+    };
+
+    let serial = ProjectIndex::build_with(
+        root,
+        &IndexOptions {
+            jobs: std::num::NonZeroUsize::new(1),
+            ..IndexOptions::default()
+        },
+    )
+    .unwrap();
+    let parallel = ProjectIndex::build(root).unwrap();
+    assert_eq!(snapshot(&serial), snapshot(&parallel));
+    // Warnings follow the sorted walk, not the order threads finished in.
+    let failed: Vec<_> = serial
+        .warnings
+        .iter()
+        .map(|warning| match warning {
+            Warning::ParseError { path, .. } => path.clone(),
+            other => panic!("unexpected warning {other}"),
+        })
+        .collect();
+    assert_eq!(
+        failed,
+        vec![
+            root.join("pkg/another_broken.py").into_std_path_buf(),
+            root.join("pkg/broken.py").into_std_path_buf(),
+        ]
+    );
+
+    let changed = vec![root.join("pkg/mod3.py")];
+    let paths = |index: &ProjectIndex| -> Vec<String> {
+        index
+            .impacted_tests(&changed, None, None, true, false)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect()
+    };
+    assert_eq!(paths(&serial), paths(&parallel));
+}
+
+#[test]
+fn ignore_path_case_matches_case_mismatched_changed_path() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    write_file(root, "pkg/foo.py", "X = 1\n");
+    write_file(root, "tests/test_foo.py", "from pkg import foo\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    // As reported by git on a case-insensitive checkout.
+    let changed = vec![root.join("Pkg/Foo.py")];
+    let opts = ImpactedOptions {
+        quiet: true,
+        ignore_path_case: true,
+        ..ImpactedOptions::default()
+    };
+    let impacted = index.impacted_tests_with(&changed, &opts).unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_foo.py"]);
+
+    // Folding case never turns a different name into a match.
+    let impacted = index
+        .impacted_tests_with(&[root.join("pkg/Food.py")], &opts)
+        .unwrap();
+    assert!(impacted.is_empty());
+}
+
+#[test]
+fn namespace_packages_take_their_imported_dotted_names() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    // `company/` is a PEP 420 namespace directory: no `__init__.py`.
+    write_file(root, "company/foo/__init__.py", "");
+    let bar = write_file(root, "company/foo/bar.py", "X = 1\n");
+    write_file(root, "company/foo/baz.py", "from . import bar\n");
+    write_file(root, "tests/test_baz.py", "from company.foo import baz\n");
+    // A plain subdirectory nobody imports through keeps its package name.
+    write_file(root, "vendor/lib/__init__.py", "");
+    write_file(root, "vendor/lib/util.py", "");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let module_of = |index: &ProjectIndex, rel: &str| index.path_to_module[&root.join(rel)].clone();
+    assert_eq!(module_of(&index, "company/foo/bar.py"), "company.foo.bar");
+    assert_eq!(module_of(&index, "company/foo/__init__.py"), "company.foo");
+    assert_eq!(module_of(&index, "vendor/lib/util.py"), "lib.util");
+
+    let impacted = index
+        .impacted_tests(&[bar.clone()], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_baz.py"]);
+
+    // Turning the namespace into a regular package keeps every name the same.
+    write_file(root, "company/__init__.py", "");
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(module_of(&index, "company/foo/bar.py"), "company.foo.bar");
+    let impacted = index
+        .impacted_tests(&[bar], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_baz.py"]);
+}
+
+#[test]
+fn src_layout_modules_are_named_from_the_source_root() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pyproject.toml", "[project]\nname = \"mypkg\"\n");
+    let foo = write_file(root, "src/mypkg/foo.py", "X = 1\n");
+    write_file(root, "tests/test_foo.py", "from mypkg import foo\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.path_to_module[&foo], "mypkg.foo");
+    let impacted = index
+        .impacted_tests(&[foo], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_foo.py"]);
+}
+
+#[test]
+fn setuptools_find_where_declares_several_source_roots() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(
+        root,
+        "pyproject.toml",
+        "[tool.setuptools.packages.find]\nwhere = [\"lib\", \"plugins\"]\n",
+    );
+    let core = write_file(root, "lib/core/engine.py", "");
+    let plugin = write_file(root, "plugins/extra/hooks.py", "from core import engine\n");
+    write_file(root, "tests/test_hooks.py", "from extra import hooks\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.path_to_module[&core], "core.engine");
+    assert_eq!(index.path_to_module[&plugin], "extra.hooks");
+    let impacted = index
+        .impacted_tests(&[core], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_hooks.py"]);
+
+    // An explicit source root replaces the detected ones.
+    let opts = IndexOptions {
+        source_roots: vec!["lib".to_string()],
+        ..IndexOptions::default()
+    };
+    let index = ProjectIndex::build_with(root, &opts).unwrap();
+    assert_eq!(index.path_to_module[&plugin], "plugins.extra.hooks");
+}
+
+#[test]
+fn setup_cfg_declares_source_roots() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(
+        root,
+        "setup.cfg",
+        "[metadata]\nname = demo\n\n[options]\npackage_dir =\n    = lib\n    other = vendor/other\n",
+    );
+    let engine = write_file(root, "lib/core/engine.py", "");
+    write_file(root, "tests/test_engine.py", "from core import engine\n");
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.path_to_module[&engine], "core.engine");
+
+    // `[options.packages.find] where` wins over `package_dir`.
+    write_file(
+        root,
+        "setup.cfg",
+        "[options]\npackage_dir = =lib\n\n[options.packages.find]\nwhere = plugins\n",
+    );
+    let hooks = write_file(root, "plugins/extra/hooks.py", "");
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.path_to_module[&hooks], "extra.hooks");
+    assert_eq!(index.path_to_module[&engine], "lib.core.engine");
+
+    // pyproject.toml takes precedence when it configures setuptools.
+    write_file(
+        root,
+        "pyproject.toml",
+        "[tool.setuptools.packages.find]\nwhere = [\"lib\"]\n",
+    );
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.path_to_module[&engine], "core.engine");
+    assert_eq!(index.path_to_module[&hooks], "plugins.extra.hooks");
+}
+
+#[test]
+fn cache_dir_tracks_edits_and_deletions_between_builds() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().join("repo")).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+    let cache_dir = Utf8PathBuf::from_path_buf(tmp.path().join("cache")).unwrap();
+
+    write_file(root, "pkg/__init__.py", "");
+    let a = write_file(root, "pkg/a.py", "X = 1\n");
+    write_file(root, "pkg/b.py", "from pkg import a\n");
+    write_file(root, "tests/test_b.py", "from pkg import b\n");
+    write_file(root, "tests/test_old.py", "from pkg import a\n");
+
+    let opts = IndexOptions {
+        cache_dir: Some(cache_dir.clone()),
+        ..IndexOptions::default()
+    };
+    let selected = |index: &ProjectIndex| -> Vec<String> {
+        let mut names: Vec<_> = index
+            .impacted_tests(&[a.clone()], None, None, true, false)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect();
+        names.sort();
+        names
+    };
+
+    let index = ProjectIndex::build_with(root, &opts).unwrap();
+    assert_eq!(
+        selected(&index),
+        vec!["tests/test_b.py", "tests/test_old.py"]
+    );
+    let cache_file = cache_dir.join("index-cache.json");
+    assert!(cache_file.exists());
+
+    // A warm build sees the edit to `b.py` and forgets the deleted test.
+    fs::remove_file(root.join("tests/test_old.py")).unwrap();
+    write_file(root, "pkg/b.py", "import json\n");
+    let index = ProjectIndex::build_with(root, &opts).unwrap();
+    assert!(selected(&index).is_empty());
+    assert!(!index.modules.contains_key("tests.test_old"));
+    let cached = fs::read_to_string(&cache_file).unwrap();
+    assert!(!cached.contains("test_old.py"));
+
+    // A corrupt cache is ignored and rewritten.
+    fs::write(&cache_file, "{not json").unwrap();
+    let index = ProjectIndex::build_with(root, &opts).unwrap();
+    assert!(selected(&index).is_empty());
+    assert!(index.warnings.is_empty(), "{:?}", index.warnings);
+    assert!(
+        fs::read_to_string(&cache_file)
+            .unwrap()
+            .contains("test_b.py")
+    );
+}
+
+#[test]
+fn conftest_change_selects_tests_in_its_subtree() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let conftest = write_file(root, "tests/conftest.py", "import pytest\n");
+    write_file(root, "tests/sub/test_x.py", "def test_x():\n    pass\n");
+    write_file(root, "other/test_y.py", "def test_y():\n    pass\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let impacted = index
+        .impacted_tests(&[conftest], None, None, true, false)
+        .unwrap();
+    let selected: Vec<_> = impacted
+        .iter()
+        .map(|t| (t.path.as_str(), t.distance))
+        .collect();
+    assert_eq!(selected, vec![("tests/sub/test_x.py", 0)]);
+    assert!(!is_test_file(root.join("tests/conftest.py").as_std_path()));
+}
+
+#[test]
+fn type_checking_imports_are_not_runtime_edges() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let heavy = write_file(root, "pkg/heavy.py", "");
+    let other = write_file(root, "pkg/other.py", "");
+    let runtime = write_file(root, "pkg/runtime.py", "");
+    write_file(
+        root,
+        "pkg/api.py",
+        "from typing import TYPE_CHECKING\nimport typing\n\nif TYPE_CHECKING:\n    from pkg import heavy\n\nif typing.TYPE_CHECKING:\n    import pkg.other\nelse:\n    from pkg import runtime\n",
+    );
+    write_file(root, "tests/test_api.py", "from pkg import api\n");
+
+    let selected = |index: &ProjectIndex, changed: &Utf8PathBuf| -> Vec<String> {
+        index
+            .impacted_tests(&[changed.clone()], None, None, true, false)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect()
+    };
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert!(selected(&index, &heavy).is_empty());
+    assert!(selected(&index, &other).is_empty());
+    assert_eq!(selected(&index, &runtime), vec!["tests/test_api.py"]);
+
+    let opts = IndexOptions {
+        type_checking_imports: true,
+        ..IndexOptions::default()
+    };
+    let index = ProjectIndex::build_with(root, &opts).unwrap();
+    assert_eq!(selected(&index, &heavy), vec!["tests/test_api.py"]);
+    assert_eq!(selected(&index, &other), vec!["tests/test_api.py"]);
+}
+
+#[test]
+fn star_import_reaches_submodules_listed_in_dunder_all() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(
+        root,
+        "pkg/__init__.py",
+        "__all__ = [\"helpers\"]\n__all__ += (\"VERSION\",)\nVERSION = 1\n",
+    );
+    let helpers = write_file(root, "pkg/helpers.py", "def foo():\n    return 1\n");
+    let unlisted = write_file(root, "pkg/internal.py", "");
+    write_file(
+        root,
+        "tests/test_star.py",
+        "from pkg import *\n\ndef test_foo():\n    helpers.foo()\n",
+    );
+
+    let index = ProjectIndex::build(root).unwrap();
+    let impacted = index
+        .impacted_tests(&[helpers], None, None, true, false)
+        .unwrap();
+    let selected: Vec<_> = impacted
+        .iter()
+        .map(|t| (t.path.as_str(), t.distance))
+        .collect();
+    assert_eq!(selected, vec![("tests/test_star.py", 1)]);
+
+    // Only what `__all__` names is pulled in.
+    assert!(
+        index
+            .impacted_tests(&[unlisted], None, None, true, false)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn stub_edits_select_the_same_tests_as_the_implementation() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let implementation = write_file(root, "pkg/foo.py", "def f():\n    return 1\n");
+    let stub = write_file(root, "pkg/foo.pyi", "def f() -> int: ...\n");
+    write_file(root, "tests/test_foo.py", "from pkg import foo\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert_eq!(index.modules["pkg.foo"].path, implementation);
+    assert!(
+        index
+            .warnings
+            .iter()
+            .all(|w| !matches!(w, Warning::ModuleCollision { .. })),
+        "{:?}",
+        index.warnings
+    );
+    for changed in [implementation, stub] {
+        let impacted = index
+            .impacted_tests(&[changed], None, None, true, false)
+            .unwrap();
+        let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(names, vec!["tests/test_foo.py"]);
+    }
+}
+
+#[test]
+fn stub_only_packages_resolve() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "typed/__init__.pyi", "");
+    let stub = write_file(root, "typed/api.pyi", "def call() -> None: ...\n");
+    write_file(root, "tests/test_api.py", "from typed.api import call\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert!(index.modules.contains_key("typed.api"));
+    let impacted = index
+        .impacted_tests(&[stub], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_api.py"]);
+}
+
+#[test]
+fn package_init_change_seeds_the_package_subtree() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let init = write_file(root, "pkg/__init__.py", "from pkg.sub.deep import helper\n");
+    write_file(root, "pkg/sub/__init__.py", "");
+    write_file(root, "pkg/sub/deep.py", "def helper():\n    return 1\n");
+    write_file(root, "pkgextra/__init__.py", "");
+    write_file(
+        root,
+        "tests/test_pkg_sub.py",
+        "from pkg.sub.deep import helper\n",
+    );
+    write_file(root, "tests/test_extra.py", "import pkgextra\n");
+    // Imports nothing, so only the subtree seeding reaches it.
+    write_file(root, "pkg/sub/test_deep.py", "def test_x():\n    pass\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let select = |strict_package_init| {
+        let opts = ImpactedOptions {
+            quiet: true,
+            strict_package_init,
+            ..ImpactedOptions::default()
+        };
+        index
+            .impacted_tests_with(std::slice::from_ref(&init), &opts)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.path, t.distance))
+            .collect::<BTreeSet<_>>()
+    };
+
+    // `from pkg.sub.deep import helper` runs `pkg/__init__.py` either way.
+    let importer = ("tests/test_pkg_sub.py".to_string(), 1);
+    assert_eq!(
+        select(false),
+        BTreeSet::from([("pkg/sub/test_deep.py".to_string(), 0), importer.clone()])
+    );
+    assert_eq!(select(true), BTreeSet::from([importer]));
+}
+
+#[test]
+fn extra_skip_dirs_are_not_indexed() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    write_file(root, "generated/api_pb2.py", "import google.protobuf\n");
+    write_file(root, "pkg/generated/inner.py", "");
+
+    let default = ProjectIndex::build(root).unwrap();
+    assert!(default.modules.contains_key("generated.api_pb2"));
+
+    let index = ProjectIndex::build_with(
+        root,
+        &IndexOptions {
+            extra_skip_dirs: vec!["generated".to_string()],
+            ..IndexOptions::default()
+        },
+    )
+    .unwrap();
+    let mut modules: Vec<_> = index.modules.keys().map(String::as_str).collect();
+    modules.sort();
+    assert_eq!(modules, vec!["pkg"]);
+}
+
+#[test]
+fn testdiffignore_patterns_skip_indexing_and_changed_files() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(
+        root,
+        ".testdiffignore",
+        "migrations/*.py\n!migrations/keep.py\n",
+    );
+    write_file(root, "migrations/__init__.py", "");
+    let migration = write_file(root, "migrations/0001_initial.py", "");
+    let kept = write_file(root, "migrations/keep.py", "");
+    let core = write_file(root, "core.py", "");
+    write_file(
+        root,
+        "tests/test_migrations.py",
+        "import core\nimport migrations.keep\n",
+    );
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert!(
+        index
+            .path_to_module
+            .keys()
+            .all(|path| !path.as_str().contains("0001_initial"))
+    );
+    assert!(index.path_to_module.contains_key(&kept));
+
+    let select = |changed: &Utf8PathBuf| {
+        index
+            .impacted_tests(std::slice::from_ref(changed), None, None, true, false)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect::<Vec<_>>()
+    };
+    assert!(select(&migration).is_empty());
+    assert_eq!(select(&kept), vec!["tests/test_migrations.py"]);
+    assert_eq!(select(&core), vec!["tests/test_migrations.py"]);
+}
+
+#[test]
+fn imports_guarded_by_import_error_are_optional() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let speedups = write_file(root, "pkg/speedups.py", "");
+    write_file(
+        root,
+        "pkg/compat.py",
+        "try:\n    import ujson as json\n    from pkg import speedups\nexcept (ImportError, OSError):\n    import json\n\ntry:\n    import yaml\nexcept ValueError:\n    pass\n",
+    );
+    write_file(root, "tests/test_compat.py", "from pkg import compat\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let compat = &index.modules["pkg.compat"];
+    let mut optional: Vec<_> = compat.optional_imports.iter().map(String::as_str).collect();
+    optional.sort();
+    assert_eq!(optional, vec!["pkg.speedups", "ujson"]);
+    // The fallback and imports under unrelated handlers are regular.
+    assert!(compat.imports.iter().any(|i| i == "json"));
+    assert!(compat.imports.iter().any(|i| i == "yaml"));
+
+    // A first-party optional import still carries selection.
+    let impacted = index
+        .impacted_tests(&[speedups], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_compat.py"]);
+}
+
+#[test]
+fn dotted_import_depends_on_parent_packages() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let pkg_init = write_file(root, "pkg/__init__.py", "");
+    let sub_init = write_file(root, "pkg/sub/__init__.py", "SETTING = 1\n");
+    write_file(root, "pkg/sub/mod.py", "");
+    write_file(root, "tests/test_mod.py", "import pkg.sub.mod\n");
+    write_file(root, "tests/test_from.py", "from pkg.sub import mod\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    // Strict mode isolates the import edges from the package-subtree seeding.
+    let opts = ImpactedOptions {
+        quiet: true,
+        strict_package_init: true,
+        ..ImpactedOptions::default()
+    };
+    for changed in [sub_init, pkg_init] {
+        let impacted = index
+            .impacted_tests_with(std::slice::from_ref(&changed), &opts)
+            .unwrap();
+        let mut names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["tests/test_from.py", "tests/test_mod.py"],
+            "changed {changed}"
+        );
+    }
+}
+
+#[test]
+fn equal_priority_cutoff_and_seeds_are_stable() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let left = write_file(root, "pkg/left.py", "");
+    let right = write_file(root, "pkg/right.py", "");
+    write_file(root, "pkg/mid.py", "from pkg import left, right\n");
+    for name in ["delta", "alpha", "echo", "charlie", "bravo"] {
+        write_file(
+            root,
+            &format!("tests/test_{name}.py"),
+            "from pkg import mid\n",
+        );
+    }
+
+    let changed = vec![right, left];
+    // Every build hashes with fresh keys, so repeated runs shake out order dependence.
+    for _ in 0..10 {
+        let index = ProjectIndex::build(root).unwrap();
+        let impacted = index
+            .impacted_tests(&changed, Some(2), None, true, false)
+            .unwrap();
+        let selected: Vec<_> = impacted
+            .iter()
+            .map(|t| (t.path.as_str(), t.seed.as_str()))
+            .collect();
+        assert_eq!(
+            selected,
+            vec![
+                ("tests/test_alpha.py", "pkg.right"),
+                ("tests/test_bravo.py", "pkg.right"),
+            ]
+        );
+    }
+}
+
+#[test]
+fn include_and_exclude_globs_filter_before_max() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let core = write_file(root, "pkg/core.py", "");
+    for test in [
+        "tests/slow/test_big.py",
+        "tests/test_fast.py",
+        "tests/integration/test_api.py",
+        "tests/integration/slow/test_soak.py",
+    ] {
+        write_file(root, test, "from pkg import core\n");
+    }
+    let index = ProjectIndex::build(root).unwrap();
+    let select = |include: &[&str], exclude: &[&str], max: Option<usize>| {
+        let opts = ImpactedOptions {
+            quiet: true,
+            max,
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            ..ImpactedOptions::default()
+        };
+        let impacted = index
+            .impacted_tests_with(std::slice::from_ref(&core), &opts)
+            .unwrap();
+        impacted.into_iter().map(|t| t.path).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        select(&[], &["tests/slow/**"], None),
+        vec![
+            "tests/integration/slow/test_soak.py",
+            "tests/integration/test_api.py",
+            "tests/test_fast.py",
+        ]
+    );
+    assert_eq!(
+        select(
+            &["tests/integration/**", "tests/test_*.py"],
+            &["**/slow/**"],
+            None
+        ),
+        vec!["tests/integration/test_api.py", "tests/test_fast.py"]
+    );
+    // Filtered out tests do not use up --max.
+    assert_eq!(
+        select(&[], &["tests/integration/**"], Some(1)),
+        vec!["tests/slow/test_big.py"]
+    );
+
+    let opts = ImpactedOptions {
+        include: vec!["tests/[".to_string()],
+        ..ImpactedOptions::default()
+    };
+    assert!(index.impacted_tests_with(&[core], &opts).is_err());
+}
+
+#[test]
+fn data_deps_select_tests_opening_a_changed_fixture() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    let data = write_file(root, "tests/fixtures/data.json", "{}");
+    let rows = write_file(root, "pkg/rows.csv", "a,b\n");
+    write_file(root, "pkg/__init__.py", "");
+    write_file(
+        root,
+        "pkg/loader.py",
+        "from pathlib import Path\nROWS = (Path(__file__).parent / \"rows.csv\").read_text()\n",
+    );
+    write_file(
+        root,
+        "tests/test_data.py",
+        "import json\n\ndef test_data():\n    with open(\"tests/fixtures/data.json\") as f:\n        json.load(f)\n",
+    );
+    write_file(root, "tests/test_loader.py", "from pkg import loader\n");
+    write_file(
+        root,
+        "tests/test_missing.py",
+        "open(\"tests/fixtures/absent.json\")\nopen(\"/etc/hosts\")\n",
+    );
+
+    let select = |index: &ProjectIndex, changed: &Utf8PathBuf| {
+        let impacted = index
+            .impacted_tests(std::slice::from_ref(changed), None, None, true, false)
+            .unwrap();
+        impacted.into_iter().map(|t| t.path).collect::<Vec<_>>()
+    };
+
+    // Off by default.
+    let index = ProjectIndex::build(root).unwrap();
+    assert!(select(&index, &data).is_empty());
+
+    let opts = IndexOptions {
+        data_deps: true,
+        ..IndexOptions::default()
+    };
+    let index = ProjectIndex::build_with(root, &opts).unwrap();
+    assert_eq!(select(&index, &data), vec!["tests/test_data.py"]);
+    assert_eq!(select(&index, &rows), vec!["tests/test_loader.py"]);
+    assert_eq!(index.data_readers.len(), 2);
+}
+
+#[test]
+fn relative_import_above_the_top_level_package_is_flagged() {
+    let from = |level: u32, module: Option<&str>, name: &str| ImportSpec {
+        level,
+        module: module.map(str::to_string),
+        name: Some(name.to_string()),
+        kind: ImportKind::ImportFrom,
+        type_checking: false,
+        optional: false,
+    };
+    assert_eq!(
+        resolve_import("pkg.mod", false, &from(1, None, "x")).as_deref(),
+        Some("pkg.x")
+    );
+    // A package anchors its own relative imports: `..` in `pkg/sub/__init__.py` is `pkg`.
+    assert_eq!(
+        resolve_import("pkg.sub", true, &from(2, Some("util"), "x")).as_deref(),
+        Some("pkg.util.x")
+    );
+    assert_eq!(resolve_import("pkg.mod", false, &from(2, None, "x")), None);
+    assert_eq!(
+        resolve_import("pkg.mod", false, &from(4, Some("a"), "x")),
+        None
+    );
+    assert_eq!(resolve_import("pkg", true, &from(2, None, "x")), None);
+
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+    write_file(root, "pkg/__init__.py", "");
+    write_file(root, "pkg/mod.py", "from .... import x\n");
+    write_file(root, "x.py", "");
+    write_file(root, "tests/test_x.py", "import x\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert!(index.modules["pkg.mod"].imports.is_empty());
+    assert_eq!(
+        index.warnings,
+        vec![Warning::EscapingRelativeImport {
+            import: "....".to_string(),
+            module: "pkg.mod".to_string(),
+        }]
+    );
+    assert_eq!(
+        index.warnings[0].to_string(),
+        "Relative import `....` in module `pkg.mod` escapes package root"
+    );
+}
+
+#[test]
+fn relative_imports_from_packages_and_submodules() {
+    let from = |level: u32, module: Option<&str>| ImportSpec {
+        level,
+        module: module.map(str::to_string),
+        name: Some("x".to_string()),
+        kind: ImportKind::ImportFrom,
+        type_checking: false,
+        optional: false,
+    };
+    // `a/b/__init__.py` (the package `a.b`) and `a/b/mod.py` share the anchor `a.b`.
+    for (module, is_package) in [("a.b", true), ("a.b.mod", false)] {
+        let resolve = |level, target| resolve_import(module, is_package, &from(level, target));
+        assert_eq!(resolve(1, None).as_deref(), Some("a.b.x"), "{module}");
+        assert_eq!(resolve(2, None).as_deref(), Some("a.x"), "{module}");
+        assert_eq!(
+            resolve(2, Some("pkg")).as_deref(),
+            Some("a.pkg.x"),
+            "{module}"
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn changed_paths_match_through_a_symlinked_root() {
+    let tmp = tempdir().unwrap();
+    let real = Utf8PathBuf::from_path_buf(tmp.path().canonicalize().unwrap().join("real")).unwrap();
+    let link = real.with_file_name("link");
+    write_file(&real, "pkg/__init__.py", "");
+    write_file(&real, "pkg/core.py", "");
+    write_file(&real, "tests/test_core.py", "from pkg import core\n");
+    std::os::unix::fs::symlink(&real, &link).unwrap();
+
+    // Index through the symlink, change through the real path, and the other way round.
+    for (root, changed_root) in [(&link, &real), (&real, &link)] {
+        let index = ProjectIndex::build(root).unwrap();
+        let changed = changed_root.join("pkg/core.py");
+        assert!(!index.path_to_module.contains_key(&changed));
+        let impacted = index
+            .impacted_tests(&[changed], None, None, true, false)
+            .unwrap();
+        let selected: Vec<_> = impacted
+            .iter()
+            .map(|t| (t.path.as_str(), t.seed.as_str(), t.distance))
+            .collect();
+        assert_eq!(selected, vec![("tests/test_core.py", "pkg.core", 1)]);
+    }
+}
+
+#[test]
+fn byte_order_mark_does_not_hide_a_module() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    write_file(root, "pkg/__init__.py", "");
+    let core = write_file(root, "pkg/core.py", "X = 1\n");
+    write_file(root, "pkg/api.py", "\u{feff}from pkg import core\n");
+    write_file(root, "tests/test_api.py", "from pkg import api\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    assert!(index.warnings.is_empty(), "{:?}", index.warnings);
+    assert_eq!(index.modules["pkg.api"].imports, vec!["pkg.core"]);
+    let impacted = index
+        .impacted_tests(&[core], None, None, true, false)
+        .unwrap();
+    let names: Vec<_> = impacted.iter().map(|t| t.path.as_str()).collect();
+    assert_eq!(names, vec!["tests/test_api.py"]);
+}
+
+#[test]
+fn warning_summary_counts_each_kind() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    // `ns` is a namespace package, so nothing shorter resolves its missing submodules.
+    write_file(root, "ns/core.py", "import ns.gone\nimport ns.missing\n");
+    write_file(root, "tests/test_core.py", "from ns import core\n");
+
+    let index = ProjectIndex::build(root).unwrap();
+    let deleted = root.join("ns/deleted.py");
+    let opts = ImpactedOptions {
+        quiet: true,
+        ..ImpactedOptions::default()
+    };
+    let (_, summary) = index
+        .impacted_tests_summarized(std::slice::from_ref(&deleted), &opts)
+        .unwrap();
+    assert_eq!(
+        summary,
+        WarningSummary {
+            unresolved: 2,
+            unindexed: 1,
+            other: 0,
+        }
+    );
+    assert_eq!(
+        summary.render().as_deref(),
+        Some("2 warnings (2 unresolved imports); 1 unindexed file")
+    );
+
+    // The summary's total is what --warn-as-error counts: the unindexed file is not in it.
+    let opts = ImpactedOptions {
+        quiet: true,
+        warn_as_error: true,
+        ..ImpactedOptions::default()
+    };
+    let err = index
+        .impacted_tests_with(&[deleted], &opts)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("(2 warnings)"), "unexpected error: {err}");
+
+    let other = WarningSummary {
+        other: 1,
+        ..WarningSummary::default()
+    };
+    assert_eq!(
+        other.render().as_deref(),
+        Some("1 warning (0 unresolved imports, 1 other)")
+    );
+    let unindexed = WarningSummary {
+        unindexed: 2,
+        ..WarningSummary::default()
+    };
+    assert_eq!(unindexed.render().as_deref(), Some("2 unindexed files"));
+    assert_eq!(WarningSummary::default().render(), None);
+}
+
+#[test]
+fn unresolved_imports_are_reported_in_module_order() {
+    let tmp = tempdir().unwrap();
+    let root_path = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+    let root: &Utf8Path = root_path.as_ref();
+
+    // More unresolved imports than `max_warnings`, spread over modules that hash apart.
+    for name in ["e", "b", "d", "a", "c"] {
+        write_file(
+            root,
+            &format!("ns/{name}.py"),
+            &format!("import ns.zz_{name}\nimport ns.gone_{name}\n"),
+        );
+    }
+    let expected: Vec<(String, String)> = ["a", "b", "c", "d", "e"]
+        .iter()
+        .flat_map(|name| {
+            [
+                (format!("ns.gone_{name}"), format!("ns.{name}")),
+                (format!("ns.zz_{name}"), format!("ns.{name}")),
+            ]
+        })
+        .collect();
+
+    let opts = ImpactedOptions {
+        quiet: true,
+        warn_as_error: true,
+        max_warnings: 3,
+        ..ImpactedOptions::default()
+    };
+    // Every build has its own hash seeds, so repeated builds would expose hash order.
+    for _ in 0..4 {
+        let index = ProjectIndex::build(root).unwrap();
+        let (_, unresolved) = index.reverse_graph(None);
+        assert_eq!(unresolved, expected);
+
+        let err = index
+            .impacted_tests_with(&[], &opts)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.ends_with("(10 warnings). First: Unresolved import `ns.gone_a` in module `ns.a`"),
+            "unexpected error: {err}"
+        );
+    }
+}
+
+#[test]
+fn warnings_display_the_stderr_messages() {
+    let unindexed = Warning::UnindexedChange {
+        path: Utf8PathBuf::from("/repo/pkg/gone.py"),
+        guessed_module: "pkg.gone".to_string(),
+    };
+    assert_eq!(
+        render_warnings(&[unindexed], 0),
+        vec!["changed file not indexed (using module `pkg.gone`): /repo/pkg/gone.py"]
+    );
+    let parse = Warning::ParseError {
+        path: "/repo/bad.py".into(),
+        detail: "unexpected indent".to_string(),
+    };
+    assert_eq!(
+        parse.to_string(),
+        "Failed to parse /repo/bad.py: unexpected indent"
+    );
+}
@@ -0,0 +1,70 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+const MAX_EXAMPLES: usize = 3;
+
+/// Unresolved imports sharing a top-level name, for `--explain-unresolved`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct UnresolvedGroup {
+    pub top_level: String,
+    pub count: usize,
+    /// The top-level name matches a package in the project, so this is likely a broken import
+    /// rather than a third-party dependency.
+    pub first_party: bool,
+    /// A few importing modules, sorted.
+    pub examples: Vec<String>,
+}
+
+/// Group `(import, importing_module)` pairs by top-level name; most frequent first.
+pub(crate) fn group_unresolved(
+    unresolved: &[(String, String)],
+    top_levels: &HashSet<&str>,
+) -> Vec<UnresolvedGroup> {
+    let mut grouped: BTreeMap<&str, (usize, BTreeSet<&str>)> = BTreeMap::new();
+    for (import, module) in unresolved {
+        let top = import.split('.').next().unwrap_or(import);
+        let entry = grouped.entry(top).or_default();
+        entry.0 += 1;
+        entry.1.insert(module);
+    }
+
+    let mut groups: Vec<UnresolvedGroup> = grouped
+        .into_iter()
+        .map(|(top, (count, modules))| UnresolvedGroup {
+            top_level: top.to_string(),
+            count,
+            first_party: top_levels.contains(top),
+            examples: modules
+                .into_iter()
+                .take(MAX_EXAMPLES)
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.top_level.cmp(&b.top_level))
+    });
+    groups
+}
+
+pub(crate) fn render_unresolved(groups: &[UnresolvedGroup]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Unresolved imports ({} top-level names):",
+        groups.len()
+    )];
+    for group in groups {
+        lines.push(format!(
+            "  {:<24} {:>5}  {:<11}  {}",
+            group.top_level,
+            group.count,
+            if group.first_party {
+                "first-party"
+            } else {
+                "third-party"
+            },
+            group.examples.join(", ")
+        ));
+    }
+    lines
+}
@@ -1,28 +1,25 @@
 use std::path::Path;
 
-pub(crate) fn filter_dir(path: &Path) -> bool {
-    const SKIP: &[&str] = &[
-        ".git",
-        "target",
-        ".tox",
-        ".venv",
-        "venv",
-        "__pycache__",
-        "node_modules",
-    ]; // keep scan lean
-    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-        if SKIP.contains(&name) {
-            return false;
-        }
-    }
-    true
+use crate::paths::filter_dir;
+
+/// [`filter_dir`], also skipping directories whose name is in `extra`.
+pub(crate) fn filter_dir_with(path: &Path, extra: &[String]) -> bool {
+    filter_dir(path)
+        && !path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|name| extra.iter().any(|e| e == name))
 }
 
-pub(crate) fn is_python_file(path: &Path) -> bool {
-    path.extension().map(|ext| ext == "py").unwrap_or(false)
+pub(crate) fn is_stub_file(path: &Path) -> bool {
+    path.extension().map(|ext| ext == "pyi").unwrap_or(false)
 }
 
 pub(crate) fn is_test_file(path: &Path) -> bool {
+    // pytest never collects stubs, even `test_*.pyi`.
+    if is_stub_file(path) {
+        return false;
+    }
     let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
     filename.starts_with("test_") || filename.ends_with("_test.py")
 }
@@ -0,0 +1,70 @@
+//! Problems found while indexing or selecting, kept typed so library callers can tell them
+//! apart. `Display` gives the message printed after `Warning: ` on stderr.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use camino::Utf8PathBuf;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// An import of a first-party top-level package that matches no indexed module.
+    UnresolvedImport { import: String, module: String },
+    /// A changed Python file that is not in the index (deleted, or failed to parse); it is
+    /// seeded as `guessed_module`.
+    UnindexedChange {
+        path: Utf8PathBuf,
+        guessed_module: String,
+    },
+    /// A Python file the parser rejected; it is left out of the index.
+    ParseError { path: PathBuf, detail: String },
+    /// A Python file that could not be read.
+    ReadError { path: PathBuf, detail: String },
+    /// A directory entry the walker could not visit.
+    WalkError { detail: String },
+    /// Two files define the same dotted module name; `kept` wins.
+    ModuleCollision {
+        module: String,
+        kept: Utf8PathBuf,
+        dropped: Utf8PathBuf,
+    },
+    /// A relative import with more leading dots than its module has enclosing packages.
+    EscapingRelativeImport { import: String, module: String },
+    /// Configuration the index could not use (source roots, `.testdiffignore`, the cache).
+    Config { detail: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnresolvedImport { import, module } => {
+                write!(f, "Unresolved import `{import}` in module `{module}`")
+            }
+            Self::UnindexedChange {
+                path,
+                guessed_module,
+            } => write!(
+                f,
+                "changed file not indexed (using module `{guessed_module}`): {path}"
+            ),
+            Self::ParseError { path, detail } => {
+                write!(f, "Failed to parse {}: {detail}", path.display())
+            }
+            Self::ReadError { path, detail } => write!(f, "{}: {detail}", path.display()),
+            Self::WalkError { detail } => write!(f, "Skipping entry: {detail}"),
+            Self::ModuleCollision {
+                module,
+                kept,
+                dropped,
+            } => write!(
+                f,
+                "Module `{module}` is defined by both {kept} and {dropped}; using {kept}"
+            ),
+            Self::EscapingRelativeImport { import, module } => write!(
+                f,
+                "Relative import `{import}` in module `{module}` escapes package root"
+            ),
+            Self::Config { detail } => f.write_str(detail),
+        }
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, ValueEnum};
+
+use crate::SelectArgs;
+use crate::config::FileConfig;
+use crate::git::commit_changed;
+use crate::paths::is_python_file;
+use testdiff::{ImpactedOptions, ProjectIndex};
+
+/// Print the file path of indexed modules (the inverse of module naming).
+#[derive(Args, Debug)]
+pub struct PathOfArgs {
+    /// Dotted module names (e.g., `pkg.foo`)
+    #[arg(required = true)]
+    pub modules: Vec<String>,
+
+    /// Project root to scan (defaults to current directory)
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+
+    /// Print a JSON object mapping each module to its path
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Print every module that transitively imports a module (its reverse-dependency closure).
+#[derive(Args, Debug)]
+pub struct ImpactArgs {
+    /// Dotted module name (e.g., `pkg.foo`) or the path of its file (e.g., `pkg/foo.py`)
+    pub module: String,
+
+    /// Project root to scan (defaults to current directory)
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+
+    /// Only report importers up to this graph distance
+    #[arg(long)]
+    pub distance_limit: Option<usize>,
+
+    /// Only report test modules
+    #[arg(long)]
+    pub tests_only: bool,
+
+    /// Print a JSON document instead of `distance<TAB>module` lines
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Dump the reverse-dependency import graph that selection walks.
+#[derive(Args, Debug)]
+pub struct GraphArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    pub format: GraphFormat,
+
+    /// Only show the part of the graph reachable from this file's module (what a change to it
+    /// would select)
+    #[arg(long, value_name = "FILE")]
+    pub changed: Option<PathBuf>,
+
+    /// Project root to scan (defaults to current directory)
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+}
+
+/// Formats `testdiff graph` can print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT; edges point from a module to its importers, test modules are boxes
+    Dot,
+}
+
+/// Compare the tests selected for two commits' own changes.
+#[derive(Args, Debug)]
+pub struct DiffSelectionArgs {
+    /// The earlier commit (its changes are diffed against its parent)
+    pub commit_a: String,
+
+    /// The later commit (its changes are diffed against its parent)
+    pub commit_b: String,
+
+    /// Project root to scan (defaults to the root detected from the changed files)
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+
+    /// Print a JSON document instead of the three-section report
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Test paths selected for commit B relative to commit A.
+#[derive(Debug, Default, PartialEq)]
+pub struct SelectionDiff {
+    /// Selected for B but not for A.
+    pub added: Vec<String>,
+    /// Selected for A but not for B.
+    pub removed: Vec<String>,
+    /// Selected for both.
+    pub unchanged: Vec<String>,
+}
+
+/// Entry point for the `testdiff path-of` subcommand.
+pub fn path_of(args: &PathOfArgs, select: &SelectArgs) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let root = crate::choose_root(args.root.clone(), &[], &cwd)?;
+    let index = build_index(&root, select)?;
+    let found = lookup_paths(&index, &args.modules)?;
+
+    if args.json {
+        println!("{}", paths_json(found));
+    } else {
+        for (_, path) in found {
+            println!("{path}");
+        }
+    }
+    Ok(())
+}
+
+/// Entry point for the `testdiff impact` subcommand.
+pub fn impact(args: &ImpactArgs, select: &SelectArgs) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let (module, importers) = dependents(args, select, &cwd)?;
+
+    if args.json {
+        println!("{}", impact_json(&module, importers));
+    } else {
+        for (module, distance) in importers {
+            println!("{distance}\t{module}");
+        }
+    }
+    Ok(())
+}
+
+/// Entry point for the `testdiff graph` subcommand.
+pub fn graph(args: &GraphArgs, select: &SelectArgs) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let changed: Vec<PathBuf> = args
+        .changed
+        .iter()
+        .map(|path| crate::canonicalize_lenient(cwd.join(path)))
+        .collect();
+    let root = crate::choose_root(args.root.clone(), &changed, &cwd)?;
+    let index = build_index(&root, select)?;
+
+    let from = match crate::normalize_changed(&changed)?.first() {
+        Some(path) => match index.path_to_module.get(path) {
+            Some(module) => Some(module.as_str()),
+            None => anyhow::bail!("{path} is not an indexed module under {root}; check --root"),
+        },
+        None => None,
+    };
+    match args.format {
+        GraphFormat::Dot => print!("{}", render_dot(&index, from)),
+    }
+    Ok(())
+}
+
+/// The module `args.module` names (a dotted name, or a file relative to `cwd`) and its
+/// transitive importers with their distances.
+fn dependents(
+    args: &ImpactArgs,
+    select: &SelectArgs,
+    cwd: &Path,
+) -> Result<(String, Vec<(String, usize)>)> {
+    let file =
+        is_path_like(&args.module).then(|| crate::canonicalize_lenient(cwd.join(&args.module)));
+    let root = crate::choose_root(args.root.clone(), file.as_slice(), cwd)?;
+    let index = build_index(&root, select)?;
+    let module = match crate::normalize_changed(file.as_slice())?.first() {
+        Some(path) => match index.path_to_module.get(path) {
+            Some(module) => module.clone(),
+            None => anyhow::bail!("{path} is not an indexed module under {root}; check --root"),
+        },
+        None => args.module.clone(),
+    };
+    let mut importers = index.importers_of(&module, args.distance_limit)?;
+    if args.tests_only {
+        importers.retain(|(importer, _)| index.is_test_module(importer));
+    }
+    Ok((module, importers))
+}
+
+/// Index `root` as selection would: with the indexing flags in `select` (global, so they can
+/// follow the subcommand) and the root's `[tool.testdiff]` settings.
+fn build_index(root: &Utf8Path, select: &SelectArgs) -> Result<ProjectIndex> {
+    let mut select = select.clone();
+    if let Some(file_config) = FileConfig::load(root)? {
+        file_config.apply(&mut select);
+    }
+    ProjectIndex::build_with(root, &crate::index_options(&select))
+}
+
+/// A file path rather than a dotted module name: has a separator or a Python extension.
+fn is_path_like(arg: &str) -> bool {
+    arg.contains(['/', std::path::MAIN_SEPARATOR]) || is_python_file(Path::new(arg))
+}
+
+/// Entry point for the `testdiff diff-selection` subcommand.
+pub fn diff_selection(args: &DiffSelectionArgs, select: &SelectArgs) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let diff = selection_diff(
+        &cwd,
+        &args.commit_a,
+        &args.commit_b,
+        args.root.clone(),
+        select,
+    )?;
+
+    if args.json {
+        println!("{}", selection_diff_json(args, &diff));
+    } else {
+        for (title, paths) in [
+            ("Newly selected", &diff.added),
+            ("No longer selected", &diff.removed),
+            ("Unchanged", &diff.unchanged),
+        ] {
+            println!("{title} ({}):", paths.len());
+            for path in paths {
+                println!("  {path}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Select tests for each commit's own changes and set-diff the results. Both selections use
+/// the current working tree's import graph.
+fn selection_diff(
+    cwd: &Path,
+    commit_a: &str,
+    commit_b: &str,
+    root: Option<PathBuf>,
+    select: &SelectArgs,
+) -> Result<SelectionDiff> {
+    let changed_a = crate::filter_python_files(commit_changed(cwd, commit_a)?);
+    let changed_b = crate::filter_python_files(commit_changed(cwd, commit_b)?);
+
+    let all: Vec<PathBuf> = changed_a.iter().chain(&changed_b).cloned().collect();
+    let root = crate::choose_root(root, &all, cwd)?;
+    let index = build_index(&root, select)?;
+
+    let select = |changed: &[PathBuf]| -> Result<BTreeSet<String>> {
+        let changed = crate::normalize_changed(changed)?;
+        let opts = ImpactedOptions {
+            quiet: true,
+            ..ImpactedOptions::default()
+        };
+        let tests = index.impacted_tests_with(&changed, &opts)?;
+        Ok(tests.into_iter().map(|t| t.path).collect())
+    };
+    let selected_a = select(&changed_a)?;
+    let selected_b = select(&changed_b)?;
+
+    Ok(SelectionDiff {
+        added: selected_b.difference(&selected_a).cloned().collect(),
+        removed: selected_a.difference(&selected_b).cloned().collect(),
+        unchanged: selected_a.intersection(&selected_b).cloned().collect(),
+    })
+}
+
+/// `{"schemaVersion": 1, "commits": {"a": ..., "b": ...}, "added": [...], "removed": [...],
+/// "unchanged": [...]}`
+fn selection_diff_json(args: &DiffSelectionArgs, diff: &SelectionDiff) -> serde_json::Value {
+    let mut fields = serde_json::Map::new();
+    fields.insert(
+        "commits".to_string(),
+        serde_json::json!({ "a": args.commit_a, "b": args.commit_b }),
+    );
+    fields.insert("added".to_string(), serde_json::json!(diff.added));
+    fields.insert("removed".to_string(), serde_json::json!(diff.removed));
+    fields.insert("unchanged".to_string(), serde_json::json!(diff.unchanged));
+    crate::json::document(fields)
+}
+
+/// Graphviz DOT for [`ProjectIndex::import_edges`]: one node per module, test modules drawn
+/// as filled boxes and the `from` module (if any) highlighted.
+fn render_dot(index: &ProjectIndex, from: Option<&str>) -> String {
+    let edges = index.import_edges(from);
+    let mut nodes: BTreeSet<&str> = from.into_iter().collect();
+    for (imported, importer) in &edges {
+        nodes.insert(imported);
+        nodes.insert(importer);
+    }
+
+    let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut out = String::from("digraph testdiff {\n    rankdir=LR;\n    node [shape=ellipse];\n");
+    for node in nodes {
+        let mut attrs = Vec::new();
+        if index.is_test_module(node) {
+            attrs.push("shape=box, style=filled, fillcolor=lightblue");
+        }
+        if Some(node) == from {
+            attrs.push("color=red, penwidth=2");
+        }
+        if attrs.is_empty() {
+            out.push_str(&format!("    {};\n", quote(node)));
+        } else {
+            out.push_str(&format!("    {} [{}];\n", quote(node), attrs.join(", ")));
+        }
+    }
+    for (imported, importer) in &edges {
+        out.push_str(&format!(
+            "    {} -> {};\n",
+            quote(imported),
+            quote(importer)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// `{"schemaVersion": 1, "module": "...", "importers": [{"module": "...", "distance": N}, ...]}`
+fn impact_json(module: &str, importers: Vec<(String, usize)>) -> serde_json::Value {
+    let importers = importers
+        .into_iter()
+        .map(|(module, distance)| serde_json::json!({ "module": module, "distance": distance }))
+        .collect();
+    let mut fields = serde_json::Map::new();
+    fields.insert("module".to_string(), serde_json::Value::from(module));
+    fields.insert("importers".to_string(), serde_json::Value::Array(importers));
+    crate::json::document(fields)
+}
+
+/// `{"schemaVersion": 1, "paths": {"<module>": "<path>", ...}}`
+fn paths_json(found: Vec<(String, Utf8PathBuf)>) -> serde_json::Value {
+    let paths: serde_json::Map<String, serde_json::Value> = found
+        .into_iter()
+        .map(|(module, path)| (module, serde_json::Value::String(path.into_string())))
+        .collect();
+    let mut fields = serde_json::Map::new();
+    fields.insert("paths".to_string(), serde_json::Value::Object(paths));
+    crate::json::document(fields)
+}
+
+fn lookup_paths(index: &ProjectIndex, modules: &[String]) -> Result<Vec<(String, Utf8PathBuf)>> {
+    modules
+        .iter()
+        .map(|module| match index.modules.get(module) {
+            Some(info) => Ok((module.clone(), info.path.clone())),
+            None => anyhow::bail!(
+                "Module `{module}` is not indexed under {}; pass --root to point at the project",
+                index.root
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ImpactArgs, SelectionDiff, dependents, impact_json, lookup_paths, paths_json, render_dot,
+        selection_diff,
+    };
+    use crate::json::SCHEMA_VERSION;
+    use crate::{Cli, SelectArgs};
+    use camino::Utf8PathBuf;
+    use clap::Parser;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+    use testdiff::ProjectIndex;
+
+    fn select_args(argv: &[&str]) -> SelectArgs {
+        let mut full = vec!["testdiff"];
+        full.extend_from_slice(argv);
+        Cli::try_parse_from(full).unwrap().select
+    }
+
+    #[test]
+    fn looks_up_known_and_rejects_unknown_modules() {
+        let tmp = tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::write(root.join("pkg/__init__.py"), "").unwrap();
+        fs::write(root.join("pkg/foo.py"), "").unwrap();
+
+        let index = ProjectIndex::build(&root).unwrap();
+        let found = lookup_paths(&index, &["pkg.foo".to_string(), "pkg".to_string()]).unwrap();
+        assert_eq!(
+            found,
+            vec![
+                ("pkg.foo".to_string(), root.join("pkg/foo.py")),
+                ("pkg".to_string(), root.join("pkg/__init__.py")),
+            ]
+        );
+
+        let doc = paths_json(found);
+        assert_eq!(SCHEMA_VERSION, 1);
+        assert_eq!(doc["schemaVersion"], SCHEMA_VERSION);
+        assert_eq!(doc["paths"]["pkg.foo"], root.join("pkg/foo.py").as_str());
+
+        let err = lookup_paths(&index, &["pkg.missing".to_string()])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`pkg.missing`") && err.contains("--root"));
+    }
+
+    #[test]
+    fn impact_reports_diamond_closure_by_distance() {
+        let tmp = tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::write(root.join("pkg/__init__.py"), "").unwrap();
+        fs::write(root.join("pkg/base.py"), "").unwrap();
+        fs::write(root.join("pkg/left.py"), "from pkg import base\n").unwrap();
+        fs::write(root.join("pkg/right.py"), "from . import base\n").unwrap();
+        fs::write(root.join("pkg/top.py"), "from pkg import left, right\n").unwrap();
+        fs::write(root.join("unrelated.py"), "import os\n").unwrap();
+
+        let index = ProjectIndex::build(&root).unwrap();
+        let closure = index.importers_of("pkg.base", None).unwrap();
+        assert_eq!(
+            closure,
+            vec![
+                ("pkg.left".to_string(), 1),
+                ("pkg.right".to_string(), 1),
+                ("pkg.top".to_string(), 2),
+            ]
+        );
+
+        let near = index.importers_of("pkg.base", Some(1)).unwrap();
+        assert_eq!(near.len(), 2);
+
+        let doc = impact_json("pkg.base", closure);
+        assert_eq!(doc["schemaVersion"], SCHEMA_VERSION);
+        assert_eq!(doc["importers"][2]["module"], "pkg.top");
+        assert_eq!(doc["importers"][2]["distance"], 2);
+
+        assert!(index.importers_of("pkg.missing", None).is_err());
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=testdiff",
+                "-c",
+                "user.email=testdiff@example.com",
+            ])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn diff_selection_compares_two_commits() {
+        let tmp = tempdir().unwrap();
+        let repo = tmp.path().canonicalize().unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::create_dir_all(repo.join("pkg")).unwrap();
+        fs::create_dir_all(repo.join("tests")).unwrap();
+        fs::write(repo.join("pyproject.toml"), "").unwrap();
+        fs::write(repo.join("pkg/__init__.py"), "").unwrap();
+        fs::write(repo.join("pkg/a.py"), "A = 1\n").unwrap();
+        fs::write(repo.join("pkg/b.py"), "B = 1\n").unwrap();
+        fs::write(repo.join("tests/test_a.py"), "from pkg import a\n").unwrap();
+        fs::write(repo.join("tests/test_b.py"), "from pkg import b\n").unwrap();
+        fs::write(repo.join("tests/test_ab.py"), "from pkg import a, b\n").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "initial"]);
+
+        fs::write(repo.join("pkg/a.py"), "A = 2\n").unwrap();
+        git(&repo, &["commit", "-q", "-am", "touch a"]);
+        fs::write(repo.join("pkg/b.py"), "B = 2\n").unwrap();
+        git(&repo, &["commit", "-q", "-am", "touch b"]);
+
+        let diff = selection_diff(&repo, "HEAD~1", "HEAD", None, &select_args(&[])).unwrap();
+        assert_eq!(
+            diff,
+            SelectionDiff {
+                added: vec!["tests/test_b.py".to_string()],
+                removed: vec!["tests/test_a.py".to_string()],
+                unchanged: vec!["tests/test_ab.py".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn dot_graph_styles_tests_and_scopes_to_a_module() {
+        let tmp = tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(tmp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("pkg/__init__.py"), "").unwrap();
+        fs::write(root.join("pkg/a.py"), "import os\n").unwrap();
+        fs::write(root.join("pkg/b.py"), "from pkg import a\n").unwrap();
+        fs::write(root.join("pkg/c.py"), "").unwrap();
+        fs::write(root.join("tests/test_b.py"), "from pkg import b\n").unwrap();
+        fs::write(root.join("tests/test_c.py"), "from pkg import c\n").unwrap();
+
+        let index = ProjectIndex::build(&root).unwrap();
+        let full = render_dot(&index, None);
+        assert!(full.starts_with("digraph testdiff {\n"));
+        assert!(full.contains("    \"pkg.a\" -> \"pkg.b\";\n"));
+        assert!(full.contains("    \"pkg.c\" -> \"tests.test_c\";\n"));
+        assert!(
+            full.contains("    \"tests.test_b\" [shape=box, style=filled, fillcolor=lightblue];\n")
+        );
+        // Unresolved third-party imports are not nodes.
+        assert!(!full.contains("\"os\""));
+
+        let scoped = render_dot(&index, Some("pkg.a"));
+        assert_eq!(
+            scoped,
+            "digraph testdiff {
+    rankdir=LR;
+    node [shape=ellipse];
+    \"pkg.a\" [color=red, penwidth=2];
+    \"pkg.b\";
+    \"tests.test_b\" [shape=box, style=filled, fillcolor=lightblue];
+    \"pkg.a\" -> \"pkg.b\";
+    \"pkg.b\" -> \"tests.test_b\";
+}
+"
+        );
+    }
+
+    #[test]
+    fn dependents_accepts_a_file_path_and_filters_tests() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("pyproject.toml"), "").unwrap();
+        fs::write(root.join("pkg/__init__.py"), "").unwrap();
+        fs::write(root.join("pkg/core.py"), "").unwrap();
+        fs::write(root.join("pkg/service.py"), "from pkg import core\n").unwrap();
+        fs::write(
+            root.join("tests/test_service.py"),
+            "from pkg import service\n",
+        )
+        .unwrap();
+
+        let args = |module: &str, tests_only| ImpactArgs {
+            module: module.to_string(),
+            root: None,
+            distance_limit: None,
+            tests_only,
+            json: false,
+        };
+        let select = select_args(&[]);
+        let (module, importers) = dependents(&args("pkg/core.py", false), &select, &root).unwrap();
+        assert_eq!(module, "pkg.core");
+        assert_eq!(
+            importers,
+            vec![
+                ("pkg.service".to_string(), 1),
+                ("tests.test_service".to_string(), 2),
+            ]
+        );
+
+        let (_, tests) = dependents(&args("pkg.core", true), &select, &root).unwrap();
+        assert_eq!(tests, vec![("tests.test_service".to_string(), 2)]);
+    }
+
+    #[test]
+    fn dependents_index_like_selection() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(root.join("generated")).unwrap();
+        fs::write(
+            root.join("pyproject.toml"),
+            "[tool.testdiff]\nextra_skip_dirs = [\"generated\"]\n",
+        )
+        .unwrap();
+        fs::write(root.join("pkg/__init__.py"), "").unwrap();
+        fs::write(root.join("pkg/core.py"), "").unwrap();
+        fs::write(
+            root.join("pkg/typed.py"),
+            "from typing import TYPE_CHECKING\nif TYPE_CHECKING:\n    from pkg import core\n",
+        )
+        .unwrap();
+        fs::write(root.join("generated/stub.py"), "from pkg import core\n").unwrap();
+
+        let args = ImpactArgs {
+            module: "pkg.core".to_string(),
+            root: None,
+            distance_limit: None,
+            tests_only: false,
+            json: false,
+        };
+        let (_, importers) = dependents(&args, &select_args(&[]), &root).unwrap();
+        assert!(importers.is_empty());
+
+        // Global, so the flag also parses after the subcommand.
+        let cli =
+            Cli::try_parse_from(["testdiff", "impact", "pkg.core", "--type-checking-imports"])
+                .unwrap();
+        let (_, importers) = dependents(&args, &cli.select, &root).unwrap();
+        assert_eq!(importers, vec![("pkg.typed".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,105 @@
+//! `--run`: hand the selection straight to pytest.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+use pathdiff::diff_paths;
+
+/// Environment variable naming the pytest command when `--pytest` is not given.
+pub const PYTEST_ENV: &str = "TESTDIFF_PYTEST";
+
+/// The pytest command line to use: `--pytest`, else `$TESTDIFF_PYTEST`, else `pytest`. Split on
+/// whitespace so wrappers like `python -m pytest` or `uv run pytest` work.
+pub fn pytest_command(flag: Option<&str>, env: Option<String>) -> Result<Vec<String>> {
+    let raw = flag
+        .map(str::to_string)
+        .or(env)
+        .unwrap_or_else(|| "pytest".to_string());
+    let argv: Vec<String> = raw.split_whitespace().map(str::to_string).collect();
+    if argv.is_empty() {
+        bail!("The pytest command is empty (check --pytest or ${PYTEST_ENV})");
+    }
+    Ok(argv)
+}
+
+/// Run `command` on `tests` (absolute paths or node ids) followed by `extra`, from `cwd`, and
+/// return pytest's exit code. Nothing is run, and 0 returned, when `tests` is empty.
+pub fn run_pytest(
+    command: &[String],
+    cwd: &Path,
+    tests: &[PathBuf],
+    extra: &[String],
+) -> Result<i32> {
+    if tests.is_empty() {
+        return Ok(0);
+    }
+    let (program, leading) = command
+        .split_first()
+        .context("The pytest command is empty")?;
+    let status = Command::new(program)
+        .args(leading)
+        .args(tests.iter().map(|test| relative_to_cwd(cwd, test)))
+        .args(extra)
+        .current_dir(cwd)
+        .status()
+        .with_context(|| format!("Failed to run {}", command.join(" ")))?;
+    // Killed by a signal: report a plain failure.
+    Ok(status.code().unwrap_or(1))
+}
+
+/// `test` as pytest should see it when started from `cwd`.
+fn relative_to_cwd(cwd: &Path, test: &Path) -> PathBuf {
+    diff_paths(test, cwd).unwrap_or_else(|| test.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pytest_command_prefers_flag_then_env() {
+        assert_eq!(pytest_command(None, None).unwrap(), vec!["pytest"]);
+        assert_eq!(
+            pytest_command(None, Some("uv run pytest".into())).unwrap(),
+            vec!["uv", "run", "pytest"]
+        );
+        assert_eq!(
+            pytest_command(Some("python -m pytest"), Some("uv run pytest".into())).unwrap(),
+            vec!["python", "-m", "pytest"]
+        );
+        assert!(pytest_command(Some("  "), None).is_err());
+    }
+
+    #[test]
+    fn no_tests_means_no_pytest() {
+        let command = vec!["definitely-not-a-real-pytest".to_string()];
+        let code = run_pytest(&command, Path::new("/"), &[], &[]).unwrap();
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    fn paths_are_relative_to_cwd() {
+        let test = Path::new("/repo/tests/test_a.py");
+        assert_eq!(
+            relative_to_cwd(Path::new("/repo/sub"), test),
+            PathBuf::from("../tests/test_a.py")
+        );
+        assert_eq!(
+            relative_to_cwd(
+                Path::new("/repo"),
+                Path::new("/repo/tests/test_a.py::test_x")
+            ),
+            PathBuf::from("tests/test_a.py::test_x")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn propagates_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = vec!["sh".to_string(), "-c".to_string(), "exit 3".to_string()];
+        let code = run_pytest(&command, dir.path(), &[dir.path().join("x")], &[]).unwrap();
+        assert_eq!(code, 3);
+    }
+}
@@ -0,0 +1,200 @@
+//! `--shard i/n`: print one of `n` slices of the selection, so parallel CI jobs can split
+//! the impacted tests between them.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Shard `index` (1-based) of `count`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shard {
+    index: usize,
+    count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("expected `i/n` with 1 <= i <= n, got `{raw}`");
+        let (index, count) = raw.split_once('/').ok_or_else(usage)?;
+        let index: usize = index.trim().parse().map_err(|_| usage())?;
+        let count: usize = count.trim().parse().map_err(|_| usage())?;
+        if index == 0 || index > count {
+            return Err(usage());
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl Shard {
+    /// The shard (0-based) of each test, given in selection order with its weight, by greedy
+    /// longest-processing-time packing: heaviest test first, each onto the lightest shard so
+    /// far (the earliest on ties). With equal weights that is plain round-robin.
+    fn assign(&self, weights: &[f64]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..weights.len()).collect();
+        order.sort_by(|&a, &b| weights[b].total_cmp(&weights[a]));
+        let mut totals = vec![0.0; self.count];
+        let mut shards = vec![0; weights.len()];
+        for position in order {
+            let lightest = (0..self.count)
+                .min_by(|&a, &b| totals[a].total_cmp(&totals[b]))
+                .unwrap_or(0);
+            totals[lightest] += weights[position];
+            shards[position] = lightest;
+        }
+        shards
+    }
+
+    /// Keep this shard's tests, numbering them across `lists` in output order (one list per
+    /// root), so the shards are disjoint and together give back the whole selection.
+    /// `seconds` is a test's recorded duration; tests without one weigh the average of those
+    /// with one, and with no durations at all the shards are dealt round-robin.
+    pub fn retain<'a, T: 'a>(
+        &self,
+        lists: impl IntoIterator<Item = &'a mut Vec<T>>,
+        seconds: impl Fn(&T) -> Option<f64>,
+    ) {
+        let lists: Vec<_> = lists.into_iter().collect();
+        let recorded: Vec<Option<f64>> =
+            lists.iter().flat_map(|l| l.iter()).map(&seconds).collect();
+        let mut shards = self.assign(&with_average(&recorded)).into_iter();
+        for list in lists {
+            list.retain(|_| shards.next() == Some(self.index - 1));
+        }
+    }
+}
+
+/// Fill in missing durations with the mean of the known ones (1 second if none are known).
+fn with_average(recorded: &[Option<f64>]) -> Vec<f64> {
+    let known: Vec<f64> = recorded.iter().flatten().copied().collect();
+    let average = if known.is_empty() {
+        1.0
+    } else {
+        known.iter().sum::<f64>() / known.len() as f64
+    };
+    recorded.iter().map(|s| s.unwrap_or(average)).collect()
+}
+
+/// Seconds per test from a previous run's JUnit report (`--durations`), summed over its
+/// testcases. Ordered maps, so float sums (and with them the shards) never vary between runs.
+#[derive(Clone, Debug, Default)]
+pub struct Durations {
+    /// Testcase `file` attributes as reported, when present (xunit1 reports).
+    pub files: BTreeMap<PathBuf, f64>,
+    /// Testcase `classname`s (`tests.test_foo` or `tests.test_foo.TestBar`).
+    pub classnames: BTreeMap<String, f64>,
+}
+
+impl Durations {
+    /// Recorded seconds of the test file at root-relative `path`, indexed as `module`;
+    /// matched like `PreviousFailures::contains`.
+    pub fn seconds(&self, path: &str, module: &str) -> Option<f64> {
+        let by_file: Vec<f64> = self
+            .files
+            .iter()
+            .filter(|(file, _)| file.ends_with(path))
+            .map(|(_, secs)| *secs)
+            .collect();
+        if !by_file.is_empty() {
+            return Some(by_file.iter().sum());
+        }
+        let by_class: Vec<f64> = self
+            .classnames
+            .iter()
+            .filter(|(class, _)| {
+                *class == module
+                    || class
+                        .strip_prefix(module)
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+            .map(|(_, secs)| *secs)
+            .collect();
+        (!by_class.is_empty()).then(|| by_class.iter().sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_index_over_count() {
+        assert_eq!("2/3".parse::<Shard>(), Ok(Shard { index: 2, count: 3 }));
+        for bad in ["0/3", "4/3", "1", "a/b", "1/0"] {
+            assert!(bad.parse::<Shard>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn shards_are_disjoint_and_cover_every_root() {
+        let all = (vec![1, 2, 3, 4], vec![5, 6, 7]);
+        let mut seen = Vec::new();
+        for index in 1..=3 {
+            let shard: Shard = format!("{index}/3").parse().unwrap();
+            let (mut first, mut second) = all.clone();
+            shard.retain([&mut first, &mut second], |_| None);
+            assert!((first.len() + second.len()).abs_diff(7 / 3) <= 1);
+            seen.extend(first);
+            seen.extend(second);
+        }
+        seen.sort();
+        assert_eq!(seen, (1..=7).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn skewed_durations_balance_shard_totals() {
+        let durations = [60.0, 30.0, 5.0, 5.0, 5.0, 5.0, 4.0, 3.0, 2.0, 1.0];
+        let total: f64 = durations.iter().sum();
+        let mut seen = 0;
+        let mut worst: f64 = 0.0;
+        for index in 1..=3 {
+            let shard: Shard = format!("{index}/3").parse().unwrap();
+            let mut tests = durations.to_vec();
+            shard.retain([&mut tests], |secs| Some(*secs));
+            seen += tests.len();
+            worst = worst.max(tests.iter().sum());
+        }
+        assert_eq!(seen, durations.len());
+        // One 60s test bounds the best possible split; the packing reaches it.
+        assert_eq!(worst, 60.0);
+        assert!(worst <= 1.5 * total / 3.0);
+    }
+
+    #[test]
+    fn unknown_durations_weigh_the_average() {
+        assert_eq!(
+            with_average(&[Some(4.0), None, Some(2.0)]),
+            vec![4.0, 3.0, 2.0]
+        );
+        assert_eq!(with_average(&[None, None]), vec![1.0, 1.0]);
+
+        let durations = Durations {
+            files: BTreeMap::new(),
+            classnames: BTreeMap::from([
+                ("tests.test_a.TestX".to_string(), 2.0),
+                ("tests.test_a".to_string(), 1.5),
+                ("tests.test_ab".to_string(), 9.0),
+            ]),
+        };
+        assert_eq!(
+            durations.seconds("tests/test_a.py", "tests.test_a"),
+            Some(3.5)
+        );
+        assert_eq!(durations.seconds("tests/test_b.py", "tests.test_b"), None);
+
+        // Summed in name order: `1e16 + 1.0` rounds back to `1e16` before `-1e16` cancels it.
+        let durations = Durations {
+            files: BTreeMap::new(),
+            classnames: BTreeMap::from([
+                ("tests.test_c.TestC".to_string(), -1e16),
+                ("tests.test_c.TestA".to_string(), 1e16),
+                ("tests.test_c.TestB".to_string(), 1.0),
+            ]),
+        };
+        assert_eq!(
+            durations.seconds("tests/test_c.py", "tests.test_c"),
+            Some(0.0)
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+
+use testdiff::TestResult;
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Literal(String),
+    Path,
+    Distance,
+    FilenameMatch,
+    Module,
+}
+
+/// A parsed `--template` such as `pytest {path} # dist={distance}`.
+/// `{{` and `}}` produce literal braces.
+#[derive(Debug)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = raw.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => anyhow::bail!("Unclosed `{{` in template `{raw}`"),
+                        }
+                    }
+                    let piece = match name.as_str() {
+                        "path" => Piece::Path,
+                        "distance" => Piece::Distance,
+                        "filename_match" => Piece::FilenameMatch,
+                        "module" => Piece::Module,
+                        other => anyhow::bail!(
+                            "Unknown placeholder `{{{other}}}` in template (expected path, distance, filename_match, module)"
+                        ),
+                    };
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(piece);
+                }
+                '}' => anyhow::bail!("Unmatched `}}` in template `{raw}`"),
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Ok(Self { pieces })
+    }
+
+    /// One output line for `res`; `{path}` is `path`, its path as printed.
+    pub fn render(&self, res: &TestResult, path: &str) -> String {
+        let mut out = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(text) => out.push_str(text),
+                Piece::Path => out.push_str(path),
+                Piece::Distance => out.push_str(&res.distance.to_string()),
+                Piece::FilenameMatch => out.push_str(&res.priority.filename_match.to_string()),
+                Piece::Module => out.push_str(&res.module),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+    use std::cmp::Reverse;
+    use testdiff::{Priority, TestResult};
+
+    fn result() -> TestResult {
+        TestResult {
+            path: "tests/test_foo.py".to_string(),
+            module: "tests.test_foo".to_string(),
+            seed: "pkg.foo".to_string(),
+            seeds: Vec::new(),
+            priority: Priority {
+                filename_match: 0,
+                distance: 1,
+                recent_failures: Reverse(0),
+            },
+            distance: 1,
+        }
+    }
+
+    #[test]
+    fn renders_custom_template() {
+        let template =
+            Template::parse("pytest {path} # dist={distance} fm={filename_match} {{{module}}}")
+                .unwrap();
+        assert_eq!(
+            template.render(&result(), "tests/test_foo.py"),
+            "pytest tests/test_foo.py # dist=1 fm=0 {tests.test_foo}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        let err = Template::parse("{path} {nope}").unwrap_err().to_string();
+        assert!(err.contains("`{nope}`"), "unexpected error: {err}");
+        assert!(Template::parse("{path").is_err());
+        assert!(Template::parse("path}").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+
+use crate::paths::{filter_dir, is_python_file};
+use crate::{SelectArgs, changed_files, choose_roots, diag, explicit_roots, run_selection};
+
+/// Events arriving within this window of each other are handled as one batch.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+enum WatchEvent {
+    Fs(notify::Result<notify::Event>),
+    Interrupt,
+}
+
+/// `--watch`: run the selection once, then again after every batch of `.py` changes under
+/// the roots until Ctrl-C. The roots are the ones the first selection would index.
+/// Selection errors are reported and the loop keeps going.
+pub fn watch(args: SelectArgs, cwd: &Path) -> Result<()> {
+    let changed = changed_files(&args, cwd)?;
+    let roots: Vec<_> = choose_roots(&explicit_roots(&args, cwd), &changed, cwd, args.quiet)?
+        .into_iter()
+        .map(|(root, _)| root.into_std_path_buf())
+        .collect();
+
+    let (tx, rx) = mpsc::channel();
+    let interrupt = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = interrupt.send(WatchEvent::Interrupt);
+    })
+    .context("Failed to install the Ctrl-C handler")?;
+    let mut watcher = notify::recommended_watcher(move |res| {
+        let _ = tx.send(WatchEvent::Fs(res));
+    })
+    .context("Failed to start the file watcher")?;
+    for root in &roots {
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", root.display()))?;
+    }
+
+    rerun(&args, cwd);
+    if !args.quiet {
+        let shown: Vec<String> = roots.iter().map(|r| r.display().to_string()).collect();
+        diag::info(format_args!(
+            "watching {} (Ctrl-C to stop)",
+            shown.join(", ")
+        ));
+    }
+
+    while let Ok(event) = rx.recv() {
+        let mut relevant = false;
+        let mut next = Some(event);
+        while let Some(event) = next {
+            match event {
+                WatchEvent::Interrupt => return Ok(()),
+                WatchEvent::Fs(Ok(event)) => {
+                    relevant |= event.paths.iter().any(|p| is_watched(p));
+                }
+                WatchEvent::Fs(Err(err)) => {
+                    if !args.quiet {
+                        diag::warning(format_args!("file watcher error: {err}"));
+                    }
+                }
+            }
+            next = rx.recv_timeout(DEBOUNCE).ok();
+        }
+
+        if relevant {
+            rerun(&args, cwd);
+        }
+    }
+    Ok(())
+}
+
+fn rerun(args: &SelectArgs, cwd: &Path) {
+    println!("---");
+    if let Err(err) = run_selection(args.clone(), cwd) {
+        diag::error(format_args!("{err:#}"));
+    }
+}
+
+/// A Python file outside the directories the index skips (`.git`, `.venv`, ...).
+fn is_watched(path: &Path) -> bool {
+    is_python_file(path) && path.ancestors().all(filter_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_watched;
+    use std::path::Path;
+
+    #[test]
+    fn only_python_files_outside_skipped_dirs_are_watched() {
+        assert!(is_watched(Path::new("/repo/pkg/foo.py")));
+        assert!(!is_watched(Path::new("/repo/pkg/data.json")));
+        assert!(!is_watched(Path::new("/repo/.venv/lib/site.py")));
+        assert!(!is_watched(Path::new("/repo/pkg/__pycache__/foo.py")));
+    }
+}